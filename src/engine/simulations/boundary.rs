@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum BoundaryCondition {
+    /// Solid wall, the tangential velocity vanishes at the wall.
+    NoSlipWall,
+    /// Solid wall the flow slides along freely.
+    FreeSlipWall,
+    /// Prescribed velocity entering (or leaving) the domain.
+    Inflow { velocity: [f32; 2] },
+    /// Open edge at zero pressure, velocities and scalars leave with zero gradient.
+    Outflow,
+    /// Wraps around to the opposite edge, which must be periodic as well.
+    Periodic,
+}
+
+impl BoundaryCondition {
+    /// Whether the ghost cells along this edge are solid.
+    pub fn is_solid(&self) -> bool {
+        !matches!(
+            self,
+            BoundaryCondition::Outflow | BoundaryCondition::Periodic
+        )
+    }
+}
+
+/// Conditions on the four edges of the domain. The one cell border around the grid
+/// holds the ghost cells implementing them.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Boundaries {
+    pub left: BoundaryCondition,
+    pub right: BoundaryCondition,
+    pub bottom: BoundaryCondition,
+    pub top: BoundaryCondition,
+}
+
+impl Default for Boundaries {
+    /// Channel open on the right, as in the original wind tunnel.
+    fn default() -> Self {
+        Self {
+            left: BoundaryCondition::FreeSlipWall,
+            right: BoundaryCondition::Outflow,
+            bottom: BoundaryCondition::FreeSlipWall,
+            top: BoundaryCondition::FreeSlipWall,
+        }
+    }
+}
+
+impl Boundaries {
+    /// Boundaries with periodic edges only kept where the opposite edge is periodic too;
+    /// an unpaired periodic edge becomes a free-slip wall.
+    pub fn validated(mut self) -> Self {
+        if !self.periodic_x() {
+            for edge in [&mut self.left, &mut self.right] {
+                if *edge == BoundaryCondition::Periodic {
+                    *edge = BoundaryCondition::FreeSlipWall;
+                }
+            }
+        }
+        if !self.periodic_y() {
+            for edge in [&mut self.bottom, &mut self.top] {
+                if *edge == BoundaryCondition::Periodic {
+                    *edge = BoundaryCondition::FreeSlipWall;
+                }
+            }
+        }
+        self
+    }
+
+    pub fn periodic_x(&self) -> bool {
+        self.left == BoundaryCondition::Periodic && self.right == BoundaryCondition::Periodic
+    }
+
+    pub fn periodic_y(&self) -> bool {
+        self.bottom == BoundaryCondition::Periodic && self.top == BoundaryCondition::Periodic
+    }
+
+    /// Condition owning the ghost cell `(i, j)` of a `width` x `height` grid, if it is one.
+    /// Corner cells belong to the left and right edges.
+    pub fn at(&self, i: usize, j: usize, width: usize, height: usize) -> Option<BoundaryCondition> {
+        if i == 0 {
+            Some(self.left)
+        } else if i == width - 1 {
+            Some(self.right)
+        } else if j == 0 {
+            Some(self.bottom)
+        } else if j == height - 1 {
+            Some(self.top)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the ghost cell `(i, j)` is solid. Corners are solid if either edge is.
+    pub fn is_solid(&self, i: usize, j: usize, width: usize, height: usize) -> bool {
+        (i == 0 && self.left.is_solid())
+            || (i == width - 1 && self.right.is_solid())
+            || (j == 0 && self.bottom.is_solid())
+            || (j == height - 1 && self.top.is_solid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validated_demotes_unpaired_periodic_edges() {
+        let boundaries = Boundaries {
+            left: BoundaryCondition::Periodic,
+            right: BoundaryCondition::Outflow,
+            bottom: BoundaryCondition::Periodic,
+            top: BoundaryCondition::Periodic,
+        }
+        .validated();
+        assert_eq!(boundaries.left, BoundaryCondition::FreeSlipWall);
+        assert_eq!(boundaries.right, BoundaryCondition::Outflow);
+        assert!(!boundaries.periodic_x());
+        assert!(boundaries.periodic_y());
+    }
+
+    #[test]
+    fn corners_belong_to_the_side_edges() {
+        let boundaries = Boundaries {
+            left: BoundaryCondition::Outflow,
+            ..Boundaries::default()
+        };
+        let (w, h) = (6, 5);
+        assert_eq!(boundaries.at(0, 0, w, h), Some(BoundaryCondition::Outflow));
+        assert_eq!(boundaries.at(2, 0, w, h), Some(boundaries.bottom));
+        assert_eq!(boundaries.at(2, 2, w, h), None);
+        // an open edge still has solid corners where it meets a wall
+        assert!(boundaries.is_solid(0, 0, w, h));
+        assert!(!boundaries.is_solid(0, 2, w, h));
+    }
+}
@@ -0,0 +1,17 @@
+/// Blue to cyan, green, yellow and red ramp for a scalar field spanning `[min, max]`.
+/// Values outside of the range are clamped; a constant field is drawn mid-ramp.
+pub fn scalar_color(value: f32, min: f32, max: f32) -> [f32; 3] {
+    let mut value = f32::min(f32::max(value, min), max - 0.01);
+    let d = max - min;
+    value = if d == 0.0 { 0.5 } else { (value - min) / d };
+    let m = 0.25;
+    let num = (value / m).floor();
+    let s = (value - num * m) / m;
+    match num as i32 {
+        0 => [0.0, s, 1.0],
+        1 => [0.0, 1.0, 1.0 - s],
+        2 => [s, 1.0, 0.0],
+        3 => [1.0, 1.0 - s, 0.0],
+        _ => [0.0, 0.0, 0.0],
+    }
+}
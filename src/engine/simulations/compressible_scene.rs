@@ -0,0 +1,301 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    parse_scene, parse_scene_file, Boundaries, BoundaryCondition, Real, Region, SceneError,
+};
+
+/// Gas described by its primitive variables.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct GasState<T = f32> {
+    pub density: T,
+    #[serde(default)]
+    pub velocity: [T; 2],
+    pub pressure: T,
+}
+
+impl<T: Real> GasState<T> {
+    pub fn new(density: T, velocity: [T; 2], pressure: T) -> Self {
+        Self {
+            density,
+            velocity,
+            pressure,
+        }
+    }
+
+    /// Density, momentum and total energy per unit volume of an ideal gas with
+    /// adiabatic index `gamma`.
+    pub fn conserved(&self, gamma: T) -> [T; 4] {
+        let [u, v] = self.velocity;
+        let kinetic = T::from_f64(0.5) * self.density * (u * u + v * v);
+        [
+            self.density,
+            self.density * u,
+            self.density * v,
+            self.pressure / (gamma - T::one()) + kinetic,
+        ]
+    }
+
+    /// Inverse of `conserved`.
+    pub fn from_conserved(conserved: [T; 4], gamma: T) -> Self {
+        let [density, mx, my, energy] = conserved;
+        let (u, v) = (mx / density, my / density);
+        let kinetic = T::from_f64(0.5) * density * (u * u + v * v);
+        Self::new(density, [u, v], (gamma - T::one()) * (energy - kinetic))
+    }
+
+    pub fn sound_speed(&self, gamma: T) -> T {
+        (gamma * self.pressure / self.density).sqrt()
+    }
+
+    pub fn speed(&self) -> T {
+        let [u, v] = self.velocity;
+        (u * u + v * v).sqrt()
+    }
+
+    /// The same state in another precision.
+    pub fn cast<S: Real>(&self) -> GasState<S> {
+        GasState {
+            density: S::from_f64(self.density.as_f64()),
+            velocity: self.velocity.map(|v| S::from_f64(v.as_f64())),
+            pressure: S::from_f64(self.pressure.as_f64()),
+        }
+    }
+}
+
+/// Gas filling a region of the initial condition.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GasRegion {
+    /// Box in fractions of the domain width and height; it holds the cells whose centre
+    /// lies inside, the lower and left edges included.
+    pub region: Region,
+    pub state: GasState,
+}
+
+/// Approximate Riemann solver computing the flux across each cell face.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RiemannSolver {
+    /// Two wave Harten-Lax-van Leer solver; robust but smears contact discontinuities.
+    Hll,
+    /// HLL with the contact wave restored, keeping contacts and shear layers sharp.
+    #[default]
+    Hllc,
+}
+
+/// Limiter of the MUSCL slopes reconstructing the face states from cell averages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SlopeLimiter {
+    /// No reconstruction: piecewise constant cells, first order Godunov.
+    FirstOrder,
+    /// Most diffusive of the second order limiters.
+    Minmod,
+    #[default]
+    VanLeer,
+    /// Monotonized central, the least diffusive.
+    MonotonizedCentral,
+}
+
+impl SlopeLimiter {
+    /// Limited slope from the one sided differences `a` and `b`, zero at extrema.
+    pub fn limit<T: Real>(self, a: T, b: T) -> T {
+        if a * b <= T::zero() {
+            return T::zero();
+        }
+        match self {
+            SlopeLimiter::FirstOrder => T::zero(),
+            SlopeLimiter::Minmod => a.signum() * a.abs().min(b.abs()),
+            SlopeLimiter::VanLeer => T::from_f64(2.0) * a * b / (a + b),
+            SlopeLimiter::MonotonizedCentral => {
+                let two = T::from_f64(2.0);
+                a.signum()
+                    * (two * a.abs())
+                        .min(two * b.abs())
+                        .min(T::from_f64(0.5) * (a + b).abs())
+            }
+        }
+    }
+}
+
+/// Quantity drawn on the instance grid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompressibleField {
+    #[default]
+    Density,
+    Pressure,
+    Speed,
+    Mach,
+    /// Numerical schlieren: the density gradient magnitude in grey, dark at shocks.
+    Schlieren,
+}
+
+/// Description of a `CompressibleSimulation` run: domain, gas, numerics and the
+/// initial condition. Edges use the shared `Boundaries`: walls reflect the gas, free-slip
+/// keeping the tangential momentum and no-slip reversing it, outflow edges are
+/// transmissive and inflow edges impose their velocity on the gas next to them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressibleScene {
+    pub width: usize,
+    pub height: usize,
+    /// Cell size, the domain spanning `width * spacing` by `height * spacing`.
+    pub spacing: f32,
+    /// Adiabatic index of the ideal gas.
+    pub gamma: f32,
+    /// Courant number of the explicit time steps.
+    pub cfl: f32,
+    pub riemann_solver: RiemannSolver,
+    pub limiter: SlopeLimiter,
+    pub boundaries: Boundaries,
+    /// Gas everywhere outside of `regions`.
+    pub ambient: GasState,
+    /// Initial gas regions, later ones painted over earlier ones.
+    pub regions: Vec<GasRegion>,
+    pub display: CompressibleField,
+    /// Simulated time advanced every frame, the frame duration when unset.
+    pub frame_time: Option<f32>,
+    /// Time steps allowed per frame.
+    pub max_substeps: usize,
+    /// Time at which the simulation stops advancing, for comparing with reference
+    /// solutions.
+    pub end_time: Option<f32>,
+}
+
+impl Default for CompressibleScene {
+    /// The Sod shock tube on 400 cells.
+    fn default() -> Self {
+        Self::sod_shock_tube(400)
+    }
+}
+
+impl CompressibleScene {
+    /// Sod's shock tube over `[0, 1]` in `cells` cells and a few rows: gas at rest with
+    /// density and pressure 1 on the left of `x = 0.5`, 0.125 and 0.1 on the right, run
+    /// until `t = 0.2`.
+    pub fn sod_shock_tube(cells: usize) -> Self {
+        Self {
+            width: cells,
+            height: 4,
+            spacing: 1.0 / cells as f32,
+            gamma: 1.4,
+            cfl: 0.4,
+            riemann_solver: RiemannSolver::default(),
+            limiter: SlopeLimiter::default(),
+            boundaries: Boundaries {
+                left: BoundaryCondition::Outflow,
+                right: BoundaryCondition::Outflow,
+                bottom: BoundaryCondition::FreeSlipWall,
+                top: BoundaryCondition::FreeSlipWall,
+            },
+            ambient: GasState::new(0.125, [0.0, 0.0], 0.1),
+            regions: vec![GasRegion {
+                region: Region::new(0.0, 0.5, 0.0, 1.0),
+                state: GasState::new(1.0, [0.0, 0.0], 1.0),
+            }],
+            display: CompressibleField::Density,
+            frame_time: Some(0.001),
+            max_substeps: 100,
+            end_time: Some(0.2),
+        }
+    }
+
+    /// Two dimensional Riemann problem `configuration` of Lax and Liu (1998) on the unit
+    /// square in `cells` x `cells` cells: four constant quadrants meeting at its centre.
+    /// Configurations 3 (four shocks), 4 (shock interaction), 6 (four vortex sheets)
+    /// and 12 (contacts and shocks) are available.
+    pub fn riemann_2d(configuration: usize, cells: usize) -> Option<Self> {
+        // quadrants counter-clockwise from the upper right: density, u, v, pressure
+        let (quadrants, end_time) = match configuration {
+            3 => (
+                [
+                    [1.5, 0.0, 0.0, 1.5],
+                    [0.5323, 1.206, 0.0, 0.3],
+                    [0.138, 1.206, 1.206, 0.029],
+                    [0.5323, 0.0, 1.206, 0.3],
+                ],
+                0.3,
+            ),
+            4 => (
+                [
+                    [1.1, 0.0, 0.0, 1.1],
+                    [0.5065, 0.8939, 0.0, 0.35],
+                    [1.1, 0.8939, 0.8939, 1.1],
+                    [0.5065, 0.0, 0.8939, 0.35],
+                ],
+                0.25,
+            ),
+            6 => (
+                [
+                    [1.0, 0.75, -0.5, 1.0],
+                    [2.0, 0.75, 0.5, 1.0],
+                    [1.0, -0.75, 0.5, 1.0],
+                    [3.0, -0.75, -0.5, 1.0],
+                ],
+                0.3,
+            ),
+            12 => (
+                [
+                    [0.5313, 0.0, 0.0, 0.4],
+                    [1.0, 0.7276, 0.0, 1.0],
+                    [0.8, 0.0, 0.0, 1.0],
+                    [1.0, 0.0, 0.7276, 1.0],
+                ],
+                0.25,
+            ),
+            _ => return None,
+        };
+
+        let boxes = [
+            Region::new(0.5, 1.0, 0.5, 1.0),
+            Region::new(0.0, 0.5, 0.5, 1.0),
+            Region::new(0.0, 0.5, 0.0, 0.5),
+            Region::new(0.5, 1.0, 0.0, 0.5),
+        ];
+        let regions = boxes
+            .into_iter()
+            .zip(quadrants)
+            .map(|(region, [density, u, v, pressure])| GasRegion {
+                region,
+                state: GasState::new(density, [u, v], pressure),
+            })
+            .collect();
+
+        Some(Self {
+            width: cells,
+            height: cells,
+            spacing: 1.0 / cells as f32,
+            boundaries: Boundaries {
+                left: BoundaryCondition::Outflow,
+                right: BoundaryCondition::Outflow,
+                bottom: BoundaryCondition::Outflow,
+                top: BoundaryCondition::Outflow,
+            },
+            ambient: GasState::new(1.0, [0.0, 0.0], 1.0),
+            regions,
+            frame_time: Some(0.002),
+            end_time: Some(end_time),
+            ..Self::sod_shock_tube(cells)
+        })
+    }
+
+    /// Initial gas at the point `(x, y)` given in fractions of the domain.
+    pub fn state_at(&self, x: f32, y: f32) -> GasState {
+        self.regions
+            .iter()
+            .rev()
+            .find(|gas| {
+                let Region { x: xs, y: ys } = gas.region;
+                xs[0] <= x && x < xs[1] && ys[0] <= y && y < ys[1]
+            })
+            .map_or(self.ambient, |gas| gas.state)
+    }
+
+    /// Loads a scene, picking the format from the file extension (`toml`, `json` or `ron`).
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        parse_scene_file(path.as_ref())
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, SceneError> {
+        parse_scene(source, "toml")
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt;
+
+/// Global measures of an `EulerSimulation` state, for judging its stability and accuracy.
+/// Integrals are taken over the fluid with cell area `spacing^2`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FlowDiagnostics {
+    /// Simulated time the state belongs to.
+    pub time: f32,
+    pub max_divergence: f32,
+    /// Mean absolute divergence over the fluid cells.
+    pub mean_divergence: f32,
+    /// `0.5 * density * |u|^2`, integrated.
+    pub kinetic_energy: f32,
+    /// `0.5 * vorticity^2`, integrated.
+    pub enstrophy: f32,
+    pub total_smoke: f32,
+    /// Integrated concentration of every dye, in the order of `EulerSimulation::dyes`.
+    pub dye_mass: Vec<f32>,
+    pub min_pressure: f32,
+    pub max_pressure: f32,
+    /// Largest speed at the centre of a fluid cell, from the averaged face velocities.
+    pub max_velocity: f32,
+    /// Cells travelled at `max_velocity` during the last step.
+    pub cfl: f32,
+}
+
+impl fmt::Display for FlowDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "t = {:.3}: div max {:.3e} mean {:.3e}, energy {:.4e}, enstrophy {:.4e}, smoke {:.3}, \
+             pressure [{:.3e}, {:.3e}], max velocity {:.3} (CFL {:.2})",
+            self.time,
+            self.max_divergence,
+            self.mean_divergence,
+            self.kinetic_energy,
+            self.enstrophy,
+            self.total_smoke,
+            self.min_pressure,
+            self.max_pressure,
+            self.max_velocity,
+            self.cfl
+        )
+    }
+}
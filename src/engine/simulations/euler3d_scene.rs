@@ -0,0 +1,278 @@
+use std::{ops::Range, path::Path};
+
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    parse_scene, parse_scene_file, AdvectionScheme, Buoyancy, Convergence, PressureSolverConfig,
+    ResidualNorm, SceneError, TimeStepping, VorticityConfinement,
+};
+
+/// Box `x[0]..x[1]`, `y[0]..y[1]`, `z[0]..z[1]` in fractions of the domain width, height
+/// and depth. It covers the cells whose centre lies inside, and at least one cell along
+/// each axis unless the box is empty.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Region3D {
+    pub x: [f32; 2],
+    pub y: [f32; 2],
+    pub z: [f32; 2],
+}
+
+impl Region3D {
+    pub fn new(x: [f32; 2], y: [f32; 2], z: [f32; 2]) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Cells of the region on a domain of `size` cells, counted like the simulation grid
+    /// from the corner of its one cell border.
+    pub fn cells(&self, size: [usize; 3]) -> impl Iterator<Item = (usize, usize, usize)> {
+        let xs = Self::cell_range(self.x, size[0]);
+        let ys = Self::cell_range(self.y, size[1]);
+        let zs = Self::cell_range(self.z, size[2]);
+        xs.flat_map(move |i| {
+            let zs = zs.clone();
+            ys.clone()
+                .flat_map(move |j| zs.clone().map(move |k| (i, j, k)))
+        })
+    }
+
+    fn cell_range(bounds: [f32; 2], cells: usize) -> Range<usize> {
+        let to_cell =
+            |fraction: f32| ((fraction * cells as f32 - 0.5).ceil().max(0.0) as usize).min(cells);
+        let (start, end) = (to_cell(bounds[0]), to_cell(bounds[1]));
+        if end > start || bounds[1] <= bounds[0] || cells == 0 {
+            start + 1..end + 1
+        } else {
+            let cell = ((bounds[0] * cells as f32).floor().max(0.0) as usize).min(cells - 1);
+            cell + 1..cell + 2
+        }
+    }
+}
+
+/// Analytic solid shape. Positions are fractions of the domain width, height and depth,
+/// lengths fractions of its height.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "shape")]
+pub enum Obstacle3D {
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+    },
+    /// Axis aligned box.
+    Cuboid {
+        center: [f32; 3],
+        size: [f32; 3],
+    },
+    /// Circular cylinder with its axis along z.
+    Cylinder {
+        center: [f32; 3],
+        radius: f32,
+        length: f32,
+    },
+}
+
+impl Obstacle3D {
+    /// Signed distance from `p` to the boundary of the shape, negative inside.
+    pub fn signed_distance(&self, p: Vec3) -> f32 {
+        match *self {
+            Obstacle3D::Sphere { center, radius } => (p - Vec3::from(center)).length() - radius,
+            Obstacle3D::Cuboid { center, size } => {
+                let q = (p - Vec3::from(center)).abs() - 0.5 * Vec3::from(size);
+                q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+            }
+            Obstacle3D::Cylinder {
+                center,
+                radius,
+                length,
+            } => {
+                let d = p - Vec3::from(center);
+                let radial = d.truncate().length() - radius;
+                let axial = d.z.abs() - 0.5 * length;
+                let outside = Vec3::new(radial.max(0.0), axial.max(0.0), 0.0).length();
+                outside + radial.max(axial).min(0.0)
+            }
+        }
+    }
+
+    /// The shape in the cell units of a domain of `size` cells, where cell `(i, j, k)`
+    /// spans `[i, i + 1]` x `[j, j + 1]` x `[k, k + 1]` including the one cell border.
+    pub fn to_cells(&self, size: [usize; 3]) -> Self {
+        let position = |p: [f32; 3]| [0, 1, 2].map(|axis| 1.0 + p[axis] * size[axis] as f32);
+        let length = size[1] as f32;
+        match *self {
+            Obstacle3D::Sphere { center, radius } => Obstacle3D::Sphere {
+                center: position(center),
+                radius: radius * length,
+            },
+            Obstacle3D::Cuboid { center, size } => Obstacle3D::Cuboid {
+                center: position(center),
+                size: size.map(|s| s * length),
+            },
+            Obstacle3D::Cylinder {
+                center,
+                radius,
+                length: cylinder_length,
+            } => Obstacle3D::Cylinder {
+                center: position(center),
+                radius: radius * length,
+                length: cylinder_length * length,
+            },
+        }
+    }
+}
+
+/// Cells filled with smoke, and optionally held at a temperature, every step.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SmokeSource3D {
+    pub region: Region3D,
+    #[serde(default = "SmokeSource3D::default_smoke")]
+    pub smoke: f32,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+}
+
+impl SmokeSource3D {
+    fn default_smoke() -> f32 {
+        1.0
+    }
+}
+
+/// Quantity colouring the smoke voxels. Smoke draws its density in grey levels, the other
+/// fields use the colour ramp over the range of the drawn voxels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Euler3DField {
+    #[default]
+    Smoke,
+    Speed,
+    Pressure,
+}
+
+/// Description of an `Euler3DSimulation` run: a box of `width` x `height` x `depth` cells
+/// with y up, closed by free slip walls unless an inflow blows through the left face and
+/// out of the right one. Regions and shapes are laid out in fractions of the domain.
+/// Fields missing from a scene file take their default values, except for the layout
+/// (initial flow, open faces, obstacles and sources) which is empty unless given.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Euler3DScene {
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+    pub spacing: f32,
+    pub density: f32,
+    #[serde(default)]
+    pub initial_velocity: [f32; 3],
+    /// Velocity entering through the left face, which leaves the right face open.
+    #[serde(default)]
+    pub inflow: Option<[f32; 3]>,
+    /// Leaves the top face open so that rising smoke escapes.
+    #[serde(default)]
+    pub open_top: bool,
+    #[serde(default)]
+    pub obstacles: Vec<Region3D>,
+    #[serde(default)]
+    pub shapes: Vec<Obstacle3D>,
+    #[serde(default)]
+    pub smoke_sources: Vec<SmokeSource3D>,
+    pub buoyancy: Buoyancy,
+    pub pressure_solver: PressureSolverConfig,
+    pub time_stepping: TimeStepping,
+    pub advection: AdvectionScheme,
+    pub vorticity_confinement: VorticityConfinement,
+    pub display: Euler3DField,
+    /// Smoke below this density is not drawn.
+    pub smoke_threshold: f32,
+}
+
+impl Default for Euler3DScene {
+    /// The sphere in a wind tunnel, 24 cells high.
+    fn default() -> Self {
+        Self::sphere_tunnel(24)
+    }
+}
+
+impl Euler3DScene {
+    /// Smoke streaming past a sphere in a tunnel `cells` cells high, twice as long and as
+    /// deep as it is high.
+    pub fn sphere_tunnel(cells: usize) -> Self {
+        Self {
+            width: 2 * cells,
+            height: cells,
+            depth: cells,
+            spacing: 1.0 / cells as f32,
+            density: 1000.0,
+            initial_velocity: [1.0, 0.0, 0.0],
+            inflow: Some([1.0, 0.0, 0.0]),
+            open_top: false,
+            obstacles: Vec::new(),
+            shapes: vec![Obstacle3D::Sphere {
+                center: [0.3, 0.5, 0.5],
+                radius: 0.15,
+            }],
+            smoke_sources: vec![SmokeSource3D {
+                region: Region3D::new([0.0, 0.02], [0.3, 0.7], [0.3, 0.7]),
+                smoke: 1.0,
+                temperature: None,
+            }],
+            buoyancy: Buoyancy::default(),
+            pressure_solver: PressureSolverConfig::ConjugateGradient {
+                convergence: Convergence::tolerance(200, 1e-4, ResidualNorm::Max),
+            },
+            time_stepping: TimeStepping::Adaptive {
+                cfl: 1.0,
+                max_substeps: 4,
+                frame_dt: None,
+            },
+            advection: AdvectionScheme::MacCormack,
+            vorticity_confinement: VorticityConfinement::default(),
+            display: Euler3DField::Smoke,
+            smoke_threshold: 0.05,
+        }
+    }
+
+    /// Hot smoke rising from the floor of a cube `cells` cells wide, around a cuboid
+    /// hanging above the source, and out of the open top.
+    pub fn rising_plume(cells: usize) -> Self {
+        Self {
+            width: cells,
+            height: cells,
+            depth: cells,
+            initial_velocity: [0.0; 3],
+            inflow: None,
+            open_top: true,
+            shapes: vec![Obstacle3D::Cuboid {
+                center: [0.5, 0.55, 0.5],
+                size: [0.3, 0.06, 0.3],
+            }],
+            smoke_sources: vec![SmokeSource3D {
+                region: Region3D::new([0.4, 0.6], [0.0, 0.05], [0.4, 0.6]),
+                smoke: 1.0,
+                temperature: Some(1.0),
+            }],
+            buoyancy: Buoyancy {
+                ambient_temperature: 0.0,
+                smoke_weight: 0.0,
+                thermal_expansion: 2.0,
+            },
+            vorticity_confinement: VorticityConfinement {
+                enabled: true,
+                strength: 2.0,
+            },
+            ..Self::sphere_tunnel(cells)
+        }
+    }
+
+    /// Number of cells along x, y and z, without the border.
+    pub fn size(&self) -> [usize; 3] {
+        [self.width, self.height, self.depth]
+    }
+
+    /// Loads a scene, picking the format from the file extension (`toml`, `json` or `ron`).
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        parse_scene_file(path.as_ref())
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, SceneError> {
+        parse_scene(source, "toml")
+    }
+}
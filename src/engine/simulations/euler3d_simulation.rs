@@ -0,0 +1,910 @@
+use std::time::{Duration, Instant};
+
+use glam::Vec3;
+use tracing::warn;
+
+use crate::{
+    fluid_fraction, scalar_color, AdvectionScheme, Buoyancy, Convergence, Euler3DField,
+    Euler3DScene, GaussSeidelSolver, Grid3D, GridLocation3D, Instance, Obstacle3D,
+    PressureProblem3D, PressureSolver3D, Real, SmokeSource3D, SolverStats, TimeStepping,
+    VorticityConfinement,
+};
+
+/// Cells cut by an obstacle with less fluid than this are made fully solid.
+const MIN_FLUID_FRACTION: f32 = 0.1;
+/// Cells with less fluid than this are drawn as part of their obstacle.
+const DRAWN_SOLID_FRACTION: f32 = 0.5;
+const SOLID_COLOR: [f32; 3] = [0.5, 0.5, 0.5];
+
+/// Incompressible flow on a 3D staggered grid, the counterpart of `EulerSimulation` with
+/// y up. The grid has a one cell border of ghost cells, solid except on the open faces.
+/// Only the smoke and the obstacles are drawn, as cubes sorted back to front from the
+/// viewpoint since the renderer has no depth buffer.
+pub struct Euler3DSimulation<T: Real = f32> {
+    pub density: T,
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+    pub cells_num: usize,
+    pub instances: Vec<Instance>,
+
+    u: Grid3D<T>,
+    v: Grid3D<T>,
+    w: Grid3D<T>,
+    pressure: Grid3D<T>,
+    /// Fluid fraction of each cell, 0 for solid.
+    solids: Grid3D<T>,
+    smoke: Grid3D<T>,
+    temperature: Grid3D<T>,
+    spacing: T,
+    inflow: Option<[f32; 3]>,
+    open_top: bool,
+    smoke_sources: Vec<SmokeSource3D>,
+    buoyancy: Buoyancy,
+    pressure_solver: Box<dyn PressureSolver3D<T>>,
+    solver_stats: SolverStats,
+    advection: AdvectionScheme,
+    vorticity_confinement: VorticityConfinement,
+    time_stepping: TimeStepping,
+    display: Euler3DField,
+    smoke_threshold: f32,
+    viewpoint: Vec3,
+    time: T,
+    substeps: usize,
+    steps: usize,
+}
+
+impl<T: Real> Euler3DSimulation<T> {
+    pub fn new(density: T, width: usize, height: usize, depth: usize, spacing: T) -> Self {
+        let viewpoint = Vec3::new(
+            width as f32 / 2.0,
+            height as f32 / 2.0,
+            (depth + 2 * height) as f32,
+        );
+
+        let width = width + 2;
+        let height = height + 2;
+        let depth = depth + 2;
+        let cells_num = width * height * depth;
+
+        let grid = |location| Grid3D::new(width, height, depth, location, T::zero());
+
+        Self {
+            density,
+            width,
+            height,
+            depth,
+            cells_num,
+            instances: Vec::new(),
+            u: grid(GridLocation3D::UFace),
+            v: grid(GridLocation3D::VFace),
+            w: grid(GridLocation3D::WFace),
+            pressure: grid(GridLocation3D::CellCenter),
+            solids: grid(GridLocation3D::CellCenter),
+            smoke: grid(GridLocation3D::CellCenter),
+            temperature: grid(GridLocation3D::CellCenter),
+            spacing,
+            inflow: None,
+            open_top: false,
+            smoke_sources: Vec::new(),
+            buoyancy: Buoyancy::default(),
+            pressure_solver: Box::new(GaussSeidelSolver::new(Convergence::fixed(40), 1.9)),
+            solver_stats: SolverStats::default(),
+            advection: AdvectionScheme::default(),
+            vorticity_confinement: VorticityConfinement::default(),
+            time_stepping: TimeStepping::default(),
+            display: Euler3DField::default(),
+            smoke_threshold: 0.05,
+            viewpoint,
+            time: T::zero(),
+            substeps: 0,
+            steps: 0,
+        }
+    }
+
+    pub fn from_scene(scene: &Euler3DScene) -> Self {
+        let mut simulation = Self::new(
+            T::from_f32(scene.density),
+            scene.width,
+            scene.height,
+            scene.depth,
+            T::from_f32(scene.spacing),
+        );
+        simulation.apply_scene(scene);
+        simulation
+    }
+
+    /// Resets all fields and lays out the walls, obstacles and sources of `scene`.
+    pub fn apply_scene(&mut self, scene: &Euler3DScene) {
+        let size = [self.width - 2, self.height - 2, self.depth - 2];
+        let [sx, sy, sz] = self.solids.strides();
+
+        self.inflow = scene.inflow;
+        self.open_top = scene.open_top;
+        self.pressure.fill(T::zero());
+        self.smoke.fill(T::zero());
+        self.temperature
+            .fill(T::from_f32(scene.buoyancy.ambient_temperature));
+
+        for (i, j, k) in self.solids.cells() {
+            let c = self.solids.index(i, j, k);
+            self.solids[c] = if self.is_wall(i, j, k) {
+                T::zero()
+            } else {
+                T::one()
+            };
+        }
+        for region in &scene.obstacles {
+            for (i, j, k) in region.cells(size) {
+                self.solids[(i, j, k)] = T::zero();
+            }
+        }
+        for shape in &scene.shapes {
+            self.add_obstacle(&shape.to_cells(size));
+        }
+
+        // faces between two fluid cells start with the initial velocity
+        let [u0, v0, w0] = scene.initial_velocity.map(T::from_f32);
+        let fluid = |c: usize| !self.solids[c].is_zero();
+        for (i, j, k) in self.solids.cells() {
+            let c = self.solids.index(i, j, k);
+            self.u[c] = if i > 0 && fluid(c) && fluid(c - sx) {
+                u0
+            } else {
+                T::zero()
+            };
+            self.v[c] = if j > 0 && fluid(c) && fluid(c - sy) {
+                v0
+            } else {
+                T::zero()
+            };
+            self.w[c] = if k > 0 && fluid(c) && fluid(c - sz) {
+                w0
+            } else {
+                T::zero()
+            };
+        }
+
+        self.smoke_sources = scene.smoke_sources.clone();
+        self.buoyancy = scene.buoyancy;
+        self.pressure_solver = scene.pressure_solver.build_3d();
+        self.advection = scene.advection;
+        self.vorticity_confinement = scene.vorticity_confinement;
+        self.time_stepping = scene.time_stepping;
+        self.display = scene.display;
+        self.smoke_threshold = scene.smoke_threshold;
+        self.time = T::zero();
+        self.steps = 0;
+        self.apply_boundaries();
+        self.update_instances();
+    }
+
+    /// Whether `(i, j, k)` is a ghost cell of a closed face. Edges and corners are solid
+    /// if either face is.
+    fn is_wall(&self, i: usize, j: usize, k: usize) -> bool {
+        i == 0
+            || (i == self.width - 1 && self.inflow.is_none())
+            || j == 0
+            || (j == self.height - 1 && !self.open_top)
+            || k == 0
+            || k == self.depth - 1
+    }
+
+    /// Rasterizes `obstacle`, given in cell units, into the solids.
+    pub fn add_obstacle(&mut self, obstacle: &Obstacle3D) {
+        self.add_obstacle_sdf(|p| obstacle.signed_distance(p));
+    }
+
+    /// Makes the region where `sdf` is negative solid. Distances are in cell units, with
+    /// cell `(i, j, k)` spanning `[i, i + 1]` x `[j, j + 1]` x `[k, k + 1]`. Cells cut by
+    /// the boundary keep the fluid fraction estimated from the distance at their centre.
+    pub fn add_obstacle_sdf(&mut self, sdf: impl Fn(Vec3) -> f32) {
+        for (i, j, k) in self.solids.interior() {
+            let c = self.solids.index(i, j, k);
+            let centre = Vec3::new(i as f32 + 0.5, j as f32 + 0.5, k as f32 + 0.5);
+            let mut fluid = fluid_fraction(sdf(centre));
+            if fluid < MIN_FLUID_FRACTION {
+                fluid = 0.0;
+            }
+            self.solids[c] = self.solids[c].min(T::from_f32(fluid));
+        }
+        self.clear_solid_cells();
+    }
+
+    /// Removes all obstacles, leaving only the walls of the domain faces.
+    pub fn clear_obstacles(&mut self) {
+        for (i, j, k) in self.solids.interior() {
+            self.solids[(i, j, k)] = T::one();
+        }
+    }
+
+    /// Stops the flow on the faces of fully solid interior cells and empties their smoke.
+    fn clear_solid_cells(&mut self) {
+        let [sx, sy, sz] = self.solids.strides();
+        for (i, j, k) in self.solids.interior() {
+            let c = self.solids.index(i, j, k);
+            if !self.solids[c].is_zero() {
+                continue;
+            }
+            self.u[c] = T::zero();
+            self.u[c + sx] = T::zero();
+            self.v[c] = T::zero();
+            self.v[c + sy] = T::zero();
+            self.w[c] = T::zero();
+            self.w[c + sz] = T::zero();
+            self.smoke[c] = T::zero();
+        }
+    }
+
+    pub fn spacing(&self) -> T {
+        self.spacing
+    }
+
+    pub fn u(&self) -> &Grid3D<T> {
+        &self.u
+    }
+
+    pub fn v(&self) -> &Grid3D<T> {
+        &self.v
+    }
+
+    pub fn w(&self) -> &Grid3D<T> {
+        &self.w
+    }
+
+    pub fn pressure(&self) -> &Grid3D<T> {
+        &self.pressure
+    }
+
+    pub fn smoke(&self) -> &Grid3D<T> {
+        &self.smoke
+    }
+
+    /// Fluid fraction of each cell, 0 for solid.
+    pub fn solids(&self) -> &Grid3D<T> {
+        &self.solids
+    }
+
+    pub fn set_time_stepping(&mut self, time_stepping: TimeStepping) {
+        self.time_stepping = time_stepping;
+    }
+
+    pub fn set_pressure_solver(&mut self, solver: impl PressureSolver3D<T> + 'static) {
+        self.pressure_solver = Box::new(solver);
+    }
+
+    pub fn set_advection(&mut self, advection: AdvectionScheme) {
+        self.advection = advection;
+    }
+
+    pub fn set_vorticity_confinement(&mut self, vorticity_confinement: VorticityConfinement) {
+        self.vorticity_confinement = vorticity_confinement;
+    }
+
+    pub fn toggle_vorticity_confinement(&mut self) -> bool {
+        self.vorticity_confinement.enabled = !self.vorticity_confinement.enabled;
+        self.vorticity_confinement.enabled
+    }
+
+    pub fn set_display(&mut self, display: Euler3DField) {
+        self.display = display;
+    }
+
+    pub fn set_smoke_threshold(&mut self, threshold: f32) {
+        self.smoke_threshold = threshold;
+    }
+
+    /// Eye position the instances are sorted from, in instance coordinates where cell
+    /// `(i, j, k)` is drawn at `(i - 1, j - 1, k - 1)`.
+    pub fn set_viewpoint(&mut self, viewpoint: Vec3) {
+        self.viewpoint = viewpoint;
+    }
+
+    /// Statistics of the last pressure solve.
+    pub fn solver_stats(&self) -> SolverStats {
+        self.solver_stats
+    }
+
+    /// Simulated time since the scene was applied.
+    pub fn time(&self) -> T {
+        self.time
+    }
+
+    /// Number of steps taken by the last `update`.
+    pub fn substeps(&self) -> usize {
+        self.substeps
+    }
+
+    /// Total number of steps since the scene was applied.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Largest step that moves the fastest face velocity by at most `cfl` cells.
+    pub fn cfl_time_step(&self, cfl: T) -> T {
+        let max_velocity = self.max_velocity();
+        if max_velocity > T::zero() {
+            cfl * self.spacing / max_velocity
+        } else {
+            T::infinity()
+        }
+    }
+
+    fn max_velocity(&self) -> T {
+        [&self.u, &self.v, &self.w]
+            .iter()
+            .flat_map(|values| values.iter())
+            .fold(T::zero(), |max, value| max.max(value.abs()))
+    }
+
+    /// Largest velocity divergence over the fluid cells, per second.
+    pub fn max_divergence(&self) -> T {
+        let [sx, sy, sz] = self.solids.strides();
+        self.solids
+            .interior()
+            .map(|(i, j, k)| self.solids.index(i, j, k))
+            .filter(|&c| !self.solids[c].is_zero())
+            .map(|c| {
+                (self.u[c + sx] - self.u[c] + self.v[c + sy] - self.v[c] + self.w[c + sz]
+                    - self.w[c])
+                    .abs()
+                    / self.spacing
+            })
+            .fold(T::zero(), T::max)
+    }
+
+    /// Smoke density integrated over the fluid cells.
+    pub fn total_smoke(&self) -> T {
+        let volume = self.spacing * self.spacing * self.spacing;
+        self.solids
+            .interior()
+            .map(|(i, j, k)| self.smoke[(i, j, k)] * self.solids[(i, j, k)])
+            .sum::<T>()
+            * volume
+    }
+
+    pub fn update(&mut self, dt: Duration) {
+        let frame_dt = T::from_f64(dt.as_secs_f64());
+        self.substeps = 0;
+
+        match self.time_stepping {
+            TimeStepping::FrameTime => self.step(frame_dt),
+            TimeStepping::Fixed {
+                dt,
+                steps_per_frame,
+            } => {
+                for _ in 0..steps_per_frame {
+                    self.step(T::from_f32(dt));
+                }
+            }
+            TimeStepping::Adaptive {
+                cfl,
+                max_substeps,
+                frame_dt: fixed_frame_dt,
+            } => {
+                let mut remaining = fixed_frame_dt.map_or(frame_dt, T::from_f32);
+                while remaining > T::zero() && self.substeps < max_substeps {
+                    let mut dt = self.cfl_time_step(T::from_f32(cfl));
+                    if dt >= remaining {
+                        dt = remaining;
+                    } else if T::from_f64(2.0) * dt >= remaining {
+                        dt = T::from_f64(0.5) * remaining;
+                    }
+                    self.step(dt);
+                    remaining -= dt;
+                }
+            }
+        }
+
+        self.update_instances();
+    }
+
+    /// Advances the simulation by a single step of `dt` seconds.
+    pub fn step(&mut self, dt: T) {
+        let size = [self.width - 2, self.height - 2, self.depth - 2];
+        for source in &self.smoke_sources {
+            for (i, j, k) in source.region.cells(size) {
+                if self.solids[(i, j, k)].is_zero() {
+                    continue;
+                }
+                self.smoke[(i, j, k)] = T::from_f32(source.smoke);
+                if let Some(temperature) = source.temperature {
+                    self.temperature[(i, j, k)] = T::from_f32(temperature);
+                }
+            }
+        }
+
+        self.apply_boundaries();
+        self.apply_buoyancy(dt);
+
+        if self.vorticity_confinement.enabled {
+            self.apply_vorticity_confinement(dt);
+        }
+
+        self.pressure.fill(T::zero());
+        self.solve_incompressibility(dt);
+
+        self.apply_boundaries();
+        self.advect_vel(dt);
+        self.advect_scalars(dt);
+
+        self.time += dt;
+        self.substeps += 1;
+        self.steps += 1;
+    }
+
+    /// Rebuilds the instances: a cube for every smoke voxel denser than the threshold,
+    /// coloured by the displayed field, and a grey one for every obstacle voxel, sorted
+    /// from the farthest to the nearest to the viewpoint.
+    pub fn update_instances(&mut self) {
+        let threshold = T::from_f32(self.smoke_threshold);
+        let mut smoke = Vec::new();
+        let mut solids = Vec::new();
+        for (i, j, k) in self.solids.interior() {
+            let c = self.solids.index(i, j, k);
+            let position = Vec3::new(i as f32, j as f32, k as f32) - Vec3::ONE;
+            if self.solids[c] < T::from_f32(DRAWN_SOLID_FRACTION) {
+                solids.push(position);
+            } else if self.smoke[c] > threshold {
+                smoke.push((position, self.display_value(i, j, k)));
+            }
+        }
+
+        let (min, max) = smoke.iter().fold(
+            (f32::INFINITY, f32::NEG_INFINITY),
+            |(min, max), (_, value)| (min.min(*value), max.max(*value)),
+        );
+
+        self.instances.clear();
+        self.instances
+            .extend(solids.into_iter().map(|position| Instance {
+                position,
+                color: SOLID_COLOR,
+            }));
+        self.instances
+            .extend(smoke.into_iter().map(|(position, value)| Instance {
+                position,
+                color: match self.display {
+                    Euler3DField::Smoke => [value.clamp(0.0, 1.0); 3],
+                    _ => scalar_color(value, min, max),
+                },
+            }));
+
+        let viewpoint = self.viewpoint;
+        self.instances.sort_by(|a, b| {
+            let distance = |instance: &Instance| instance.position.distance_squared(viewpoint);
+            distance(b).total_cmp(&distance(a))
+        });
+    }
+
+    fn display_value(&self, i: usize, j: usize, k: usize) -> f32 {
+        let c = self.solids.index(i, j, k);
+        match self.display {
+            Euler3DField::Smoke => self.smoke[c].as_f32(),
+            Euler3DField::Speed => {
+                let [u, v, w] = self.cell_velocity(c);
+                (u * u + v * v + w * w).sqrt().as_f32()
+            }
+            Euler3DField::Pressure => self.pressure[c].as_f32(),
+        }
+    }
+
+    /// Velocity at the centre of cell `c`, which must not be on the upper border.
+    fn cell_velocity(&self, c: usize) -> [T; 3] {
+        let [sx, sy, sz] = self.solids.strides();
+        let half = T::from_f64(0.5);
+        [
+            half * (self.u[c] + self.u[c + sx]),
+            half * (self.v[c] + self.v[c + sy]),
+            half * (self.w[c] + self.w[c + sz]),
+        ]
+    }
+
+    fn solve_incompressibility(&mut self, dt: T) {
+        let mut problem = PressureProblem3D {
+            width: self.width,
+            height: self.height,
+            depth: self.depth,
+            u: &mut self.u,
+            v: &mut self.v,
+            w: &mut self.w,
+            pressure: &mut self.pressure,
+            solids: &self.solids,
+            scale: self.density * self.spacing / dt,
+        };
+
+        let start = Instant::now();
+        let mut stats = self.pressure_solver.solve(&mut problem);
+        stats.wall_time = start.elapsed();
+
+        if !stats.converged {
+            warn!(
+                "Pressure solver did not converge: residual {} after {} iterations",
+                stats.final_residual, stats.iterations
+            );
+        }
+        self.solver_stats = stats;
+    }
+
+    fn apply_buoyancy(&mut self, dt: T) {
+        let Buoyancy {
+            ambient_temperature,
+            smoke_weight,
+            thermal_expansion,
+        } = self.buoyancy;
+        if smoke_weight == 0.0 && thermal_expansion == 0.0 {
+            return;
+        }
+        let ambient_temperature = T::from_f32(ambient_temperature);
+        let smoke_weight = T::from_f32(smoke_weight);
+        let thermal_expansion = T::from_f32(thermal_expansion);
+        let half = T::from_f64(0.5);
+        let sy = self.solids.strides()[1];
+
+        for (i, j, k) in self.v.cells() {
+            if !self.is_advected(i, j, k, GridLocation3D::VFace) {
+                continue;
+            }
+            let c = self.v.index(i, j, k);
+            let smoke = half * (self.smoke[c] + self.smoke[c - sy]);
+            let temperature = half * (self.temperature[c] + self.temperature[c - sy]);
+            let force =
+                -smoke_weight * smoke + thermal_expansion * (temperature - ambient_temperature);
+            self.v[c] += dt * force;
+        }
+    }
+
+    /// Adds `strength * h * (N x w)` to the face velocities, where `w` is the cell
+    /// vorticity and `N` the normalized gradient of its magnitude.
+    fn apply_vorticity_confinement(&mut self, dt: T) {
+        let strides = self.solids.strides();
+        let h = self.spacing;
+        let strength = T::from_f32(self.vorticity_confinement.strength);
+        let half = T::from_f64(0.5);
+        let fluid = |c: usize| !self.solids[c].is_zero();
+
+        let mut velocity = vec![[T::zero(); 3]; self.cells_num];
+        for (i, j, k) in self.solids.cells() {
+            let c = self.solids.index(i, j, k);
+            if i + 1 < self.width && j + 1 < self.height && k + 1 < self.depth && fluid(c) {
+                velocity[c] = self.cell_velocity(c);
+            }
+        }
+
+        // central difference of velocity component `a` along axis `b`
+        let derivative = |c: usize, a: usize, b: usize| {
+            (velocity[c + strides[b]][a] - velocity[c - strides[b]][a]) * half / h
+        };
+        let mut curl = vec![[T::zero(); 3]; self.cells_num];
+        let mut magnitude = vec![T::zero(); self.cells_num];
+        for (i, j, k) in self.solids.interior() {
+            let c = self.solids.index(i, j, k);
+            if !fluid(c) {
+                continue;
+            }
+            curl[c] = [
+                derivative(c, 2, 1) - derivative(c, 1, 2),
+                derivative(c, 0, 2) - derivative(c, 2, 0),
+                derivative(c, 1, 0) - derivative(c, 0, 1),
+            ];
+            magnitude[c] = curl[c].iter().map(|w| *w * *w).sum::<T>().sqrt();
+        }
+
+        let mut force = vec![[T::zero(); 3]; self.cells_num];
+        for (i, j, k) in self.solids.interior() {
+            let c = self.solids.index(i, j, k);
+            let inner = i > 1
+                && j > 1
+                && k > 1
+                && i < self.width - 2
+                && j < self.height - 2
+                && k < self.depth - 2;
+            if !inner || !fluid(c) {
+                continue;
+            }
+            let gradient =
+                strides.map(|stride| (magnitude[c + stride] - magnitude[c - stride]) * half / h);
+            let length = gradient.iter().map(|g| *g * *g).sum::<T>().sqrt() + T::from_f64(1e-6);
+            let [nx, ny, nz] = gradient.map(|g| g / length);
+            let [wx, wy, wz] = curl[c];
+            force[c] =
+                [ny * wz - nz * wy, nz * wx - nx * wz, nx * wy - ny * wx].map(|f| strength * h * f);
+        }
+
+        let locations = [
+            GridLocation3D::UFace,
+            GridLocation3D::VFace,
+            GridLocation3D::WFace,
+        ];
+        for (i, j, k) in self.solids.cells() {
+            let c = self.solids.index(i, j, k);
+            for (axis, location) in locations.into_iter().enumerate() {
+                if !self.is_advected(i, j, k, location) {
+                    continue;
+                }
+                let delta = dt * half * (force[c][axis] + force[c - strides[axis]][axis]);
+                match axis {
+                    0 => self.u[c] += delta,
+                    1 => self.v[c] += delta,
+                    _ => self.w[c] += delta,
+                }
+            }
+        }
+    }
+
+    /// Fills the ghost cells of the border. Walls are free slip and open faces let the
+    /// flow and the smoke through with zero gradient; the inflow sets the velocity on the
+    /// left face and brings in clear air.
+    fn apply_boundaries(&mut self) {
+        let (w, h, d) = (self.width, self.height, self.depth);
+        let strides = self.solids.strides();
+        let size = [w, h, d];
+
+        for axis in 0..3 {
+            let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+            for low in [true, false] {
+                let open = match (axis, low) {
+                    (0, false) => self.inflow.is_some(),
+                    (1, false) => self.open_top,
+                    _ => false,
+                };
+                for p in 1..size[a] - 1 {
+                    for q in 1..size[b] - 1 {
+                        let mut index = [0; 3];
+                        index[axis] = if low { 0 } else { size[axis] - 1 };
+                        index[a] = p;
+                        index[b] = q;
+                        let ghost = self.solids.index(index[0], index[1], index[2]);
+                        let interior = if low {
+                            ghost + strides[axis]
+                        } else {
+                            ghost - strides[axis]
+                        };
+
+                        let mut velocities = [&mut self.u, &mut self.v, &mut self.w];
+                        match self.inflow {
+                            Some(inflow) if axis == 0 && low => {
+                                let inflow = inflow.map(T::from_f32);
+                                if !self.solids[interior].is_zero() {
+                                    velocities[0][interior] = inflow[0];
+                                }
+                                for (component, value) in velocities.iter_mut().zip(inflow) {
+                                    component[ghost] = value;
+                                }
+                                self.smoke[ghost] = T::zero();
+                            }
+                            _ => {
+                                for tangential in [a, b] {
+                                    velocities[tangential][ghost] =
+                                        velocities[tangential][interior];
+                                }
+                                if open {
+                                    self.smoke[ghost] = self.smoke[interior];
+                                    self.temperature[ghost] = self.temperature[interior];
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Grid samples and trilinear weights used to interpolate `values` at `p`, clamped
+    /// to the domain.
+    fn sample_stencil(&self, values: &Grid3D<T>, p: [T; 3]) -> ([usize; 8], [T; 8]) {
+        let h = self.spacing;
+        let size = [self.width, self.height, self.depth];
+        let [x, y, z] = [0, 1, 2].map(|axis| p[axis].max(h).min(T::from_usize(size[axis]) * h));
+        values.stencil(x, y, z, h)
+    }
+
+    fn sample_values(&self, values: &Grid3D<T>, p: [T; 3]) -> T {
+        let (cells, weights) = self.sample_stencil(values, p);
+        cells.iter().zip(weights).map(|(c, w)| w * values[*c]).sum()
+    }
+
+    fn velocity_at(&self, p: [T; 3]) -> [T; 3] {
+        [&self.u, &self.v, &self.w].map(|values| self.sample_values(values, p))
+    }
+
+    /// Whether the sample at `(i, j, k)` of a field at `location` is carried by the flow.
+    fn is_advected(&self, i: usize, j: usize, k: usize, location: GridLocation3D) -> bool {
+        let index = [i, j, k];
+        let size = [self.width, self.height, self.depth];
+        let inside = |axis: usize| index[axis] > 0 && index[axis] < size[axis] - 1;
+        let fluid = |c: usize| !self.solids[c].is_zero();
+        let c = self.solids.index(i, j, k);
+
+        let axis = match location {
+            GridLocation3D::CellCenter => return (0..3).all(inside) && fluid(c),
+            GridLocation3D::UFace => 0,
+            GridLocation3D::VFace => 1,
+            GridLocation3D::WFace => 2,
+        };
+        index[axis] > 0
+            && (0..3).filter(|&other| other != axis).all(inside)
+            && fluid(c)
+            && fluid(c - self.solids.strides()[axis])
+    }
+
+    /// Traces the characteristic through `p` back by `dt` with a Runge-Kutta scheme of the
+    /// given `order` (1 to 3), starting with velocity `velocity`.
+    fn backtrace(&self, p: [T; 3], velocity: [T; 3], dt: T, order: usize) -> [T; 3] {
+        let c = T::from_f64;
+        let along = |p: [T; 3], velocity: [T; 3], t: T| [0, 1, 2].map(|a| p[a] - t * velocity[a]);
+        match order {
+            1 => along(p, velocity, dt),
+            2 => {
+                let v2 = self.velocity_at(along(p, velocity, c(0.5) * dt));
+                along(p, v2, dt)
+            }
+            _ => {
+                let v2 = self.velocity_at(along(p, velocity, c(0.5) * dt));
+                let v3 = self.velocity_at(along(p, v2, c(0.75) * dt));
+                let blended = [0, 1, 2]
+                    .map(|a| (c(2.0) * velocity[a] + c(3.0) * v2[a] + c(4.0) * v3[a]) / c(9.0));
+                along(p, blended, dt)
+            }
+        }
+    }
+
+    /// Where each sample of a field at `location` came from `dt` ago, `None` for the
+    /// samples that are not advected.
+    fn departure_points(
+        &self,
+        location: GridLocation3D,
+        dt: T,
+        order: usize,
+    ) -> Vec<Option<[T; 3]>> {
+        let (dx, dy, dz) = location.offset();
+        let offset = [dx, dy, dz].map(T::from_f32);
+        self.solids
+            .cells()
+            .map(|(i, j, k)| {
+                self.is_advected(i, j, k, location).then(|| {
+                    let index = [i, j, k];
+                    let p = [0, 1, 2]
+                        .map(|axis| (T::from_usize(index[axis]) + offset[axis]) * self.spacing);
+                    self.backtrace(p, self.velocity_at(p), dt, order)
+                })
+            })
+            .collect()
+    }
+
+    /// One semi-Lagrangian step of `values` from its `departures`.
+    fn semi_lagrangian(&self, values: &Grid3D<T>, departures: &[Option<[T; 3]>]) -> Grid3D<T> {
+        let mut result = values.clone();
+        for (value, p) in result.iter_mut().zip(departures) {
+            if let Some(p) = *p {
+                *value = self.sample_values(values, p);
+            }
+        }
+        result
+    }
+
+    /// Clamps `corrected` to the range of the values the plain backtrace interpolated
+    /// from, which keeps the error-compensating schemes free of new extrema.
+    fn clamp_to_stencil(
+        &self,
+        values: &Grid3D<T>,
+        corrected: &mut Grid3D<T>,
+        departures: &[Option<[T; 3]>],
+    ) {
+        for (value, p) in corrected.iter_mut().zip(departures) {
+            if let Some(p) = *p {
+                let (cells, _) = self.sample_stencil(values, p);
+                let min = cells.iter().fold(T::infinity(), |m, c| m.min(values[*c]));
+                let max = cells
+                    .iter()
+                    .fold(T::neg_infinity(), |m, c| m.max(values[*c]));
+                *value = value.max(min).min(max);
+            }
+        }
+    }
+
+    /// Advects `fields`, all sampled at the same location, tracing the characteristics
+    /// once for all of them.
+    fn advect_fields(&self, fields: &[&Grid3D<T>], dt: T) -> Vec<Grid3D<T>> {
+        let Some(location) = fields.first().map(|values| values.location()) else {
+            return Vec::new();
+        };
+        let half = T::from_f64(0.5);
+
+        match self.advection {
+            AdvectionScheme::SemiLagrangian | AdvectionScheme::Rk2 | AdvectionScheme::Rk3 => {
+                let order = match self.advection {
+                    AdvectionScheme::SemiLagrangian => 1,
+                    AdvectionScheme::Rk2 => 2,
+                    _ => 3,
+                };
+                let departures = self.departure_points(location, dt, order);
+                fields
+                    .iter()
+                    .map(|values| self.semi_lagrangian(values, &departures))
+                    .collect()
+            }
+            AdvectionScheme::MacCormack | AdvectionScheme::Bfecc => {
+                let forward_points = self.departure_points(location, dt, 2);
+                let backward_points = self.departure_points(location, -dt, 2);
+                fields
+                    .iter()
+                    .map(|values| {
+                        let forward = self.semi_lagrangian(values, &forward_points);
+                        let backward = self.semi_lagrangian(&forward, &backward_points);
+                        let mut result = if self.advection == AdvectionScheme::MacCormack {
+                            let mut result = forward;
+                            for ((r, v), b) in
+                                result.iter_mut().zip(values.iter()).zip(backward.iter())
+                            {
+                                *r += half * (*v - *b);
+                            }
+                            result
+                        } else {
+                            let mut compensated = (*values).clone();
+                            for (c, b) in compensated.iter_mut().zip(backward.iter()) {
+                                *c += half * (*c - *b);
+                            }
+                            self.semi_lagrangian(&compensated, &forward_points)
+                        };
+                        self.clamp_to_stencil(values, &mut result, &forward_points);
+                        result
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    fn advect_vel(&mut self, dt: T) {
+        let [u, v, w] =
+            [&self.u, &self.v, &self.w].map(|values| self.advect_fields(&[values], dt).remove(0));
+
+        self.u = u;
+        self.v = v;
+        self.w = w;
+    }
+
+    fn advect_scalars(&mut self, dt: T) {
+        let mut advected = self.advect_fields(&[&self.smoke, &self.temperature], dt);
+        self.temperature = advected.remove(1);
+        self.smoke = advected.remove(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PressureSolverConfig, ResidualNorm};
+
+    #[test]
+    fn pressure_solvers_remove_divergence() {
+        let tolerance = 1e-8;
+        let solvers = [
+            PressureSolverConfig::GaussSeidel {
+                over_relaxation: 1.7,
+                convergence: Convergence::tolerance(2000, tolerance, ResidualNorm::Max),
+            },
+            PressureSolverConfig::ConjugateGradient {
+                convergence: Convergence::tolerance(200, tolerance, ResidualNorm::Max),
+            },
+        ];
+        for solver in solvers {
+            let mut scene = Euler3DScene::rising_plume(8);
+            scene.pressure_solver = solver;
+            let mut simulation = Euler3DSimulation::<f64>::from_scene(&scene);
+
+            // from rest the buoyancy is all that moves the smoke, and a short step keeps
+            // the advection from adding divergence back
+            simulation.step(1e-3);
+            let stats = simulation.solver_stats();
+            assert!(stats.converged, "{solver:?}: {stats:?}");
+            assert!(stats.initial_residual > 1e3 * tolerance);
+
+            let divergence = simulation.max_divergence() * simulation.spacing();
+            assert!(
+                divergence < tolerance as f64,
+                "{solver:?}: divergence {divergence}"
+            );
+        }
+    }
+}
@@ -0,0 +1,429 @@
+use std::{fmt, fs, io, ops::Range, path::Path};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    AdvectionScheme, Boundaries, Buoyancy, ForceReference, ImageMask, NacaAirfoil, Obstacle,
+    PressureSolverConfig, ProbeDescriptor, TimeStepping, VorticityConfinement,
+};
+
+/// Coordinate system of the positions and lengths of an `EulerScene`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SceneUnits {
+    /// Grid cells, counted from the corner of the one cell border added by
+    /// `EulerSimulation::new`.
+    #[default]
+    Cells,
+    /// Multiples of `spacing`, from the lower left corner of the domain inside the border.
+    World,
+    /// Fractions of the domain width and height, from the lower left corner of the domain
+    /// inside the border. Lengths (radii, sizes, chords) are fractions of the height.
+    Normalized,
+}
+
+/// Box `x[0]..x[1]`, `y[0]..y[1]` in the units of its scene. In cell units it covers
+/// the cells whose centre lies inside, and at least one cell along each axis unless
+/// the box is empty, so that thin inlets survive coarse grids.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub x: [f32; 2],
+    pub y: [f32; 2],
+}
+
+impl Region {
+    pub fn new(x0: f32, x1: f32, y0: f32, y1: f32) -> Self {
+        Self {
+            x: [x0, x1],
+            y: [y0, y1],
+        }
+    }
+
+    /// The region of the single cell `(i, j)`.
+    pub fn cell(i: usize, j: usize) -> Self {
+        Self::new(i as f32, (i + 1) as f32, j as f32, (j + 1) as f32)
+    }
+
+    /// Columns and rows covered by a region in cell units, before clipping to the grid.
+    pub fn cell_bounds(&self) -> (Range<usize>, Range<usize>) {
+        (Self::cell_range(self.x), Self::cell_range(self.y))
+    }
+
+    fn cell_range(bounds: [f32; 2]) -> Range<usize> {
+        let start = (bounds[0] - 0.5).ceil().max(0.0) as usize;
+        let end = (bounds[1] - 0.5).ceil().max(0.0) as usize;
+        if end > start || bounds[1] <= bounds[0] {
+            start..end
+        } else {
+            let cell = bounds[0].floor().max(0.0) as usize;
+            cell..cell + 1
+        }
+    }
+
+    pub fn contains(&self, i: usize, j: usize) -> bool {
+        let (xs, ys) = self.cell_bounds();
+        xs.contains(&i) && ys.contains(&j)
+    }
+
+    /// Cells of a region in cell units, clipped to a `width` x `height` grid.
+    pub fn cells(&self, width: usize, height: usize) -> impl Iterator<Item = (usize, usize)> {
+        let (xs, ys) = self.cell_bounds();
+        let ys = ys.start..ys.end.min(height);
+        (xs.start..xs.end.min(width)).flat_map(move |i| ys.clone().map(move |j| (i, j)))
+    }
+
+    fn mapped(&self, position: impl Fn([f32; 2]) -> [f32; 2]) -> Self {
+        let [x0, y0] = position([self.x[0], self.y[0]]);
+        let [x1, y1] = position([self.x[1], self.y[1]]);
+        Self::new(x0, x1, y0, y1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Inflow {
+    pub region: Region,
+    pub velocity: [f32; 2],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HeatSource {
+    pub region: Region,
+    pub temperature: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SmokeSource {
+    pub region: Region,
+    #[serde(default = "SmokeSource::default_value")]
+    pub value: f32,
+}
+
+impl SmokeSource {
+    fn default_value() -> f32 {
+        1.0
+    }
+}
+
+/// Named passive dye with its colour, decay rate and inlets.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DyeDescriptor {
+    pub name: String,
+    pub color: [f32; 3],
+    #[serde(default)]
+    pub dissipation: f32,
+    #[serde(default)]
+    pub sources: Vec<SmokeSource>,
+}
+
+/// Description of an `EulerSimulation` experiment: domain, solids and sources. Fields
+/// missing from a scene file take their default values, except for the layout which
+/// is empty and in cell units unless given.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EulerScene {
+    pub width: usize,
+    pub height: usize,
+    pub spacing: f32,
+    /// Units of all positions, regions and shapes below.
+    #[serde(default)]
+    pub units: SceneUnits,
+    pub density: f32,
+    /// Kinematic viscosity, zero for inviscid flow.
+    pub viscosity: f32,
+    pub initial_velocity: [f32; 2],
+    #[serde(default)]
+    pub obstacles: Vec<Region>,
+    /// Analytic obstacles, rasterized with fractional coverage on top of `obstacles`.
+    pub shapes: Vec<Obstacle>,
+    /// Obstacles and sources drawn in image files. Relative paths are resolved against
+    /// the scene file by `from_file`.
+    pub image_masks: Vec<ImageMask>,
+    #[serde(default)]
+    pub inflows: Vec<Inflow>,
+    #[serde(default)]
+    pub smoke_sources: Vec<SmokeSource>,
+    pub heat_sources: Vec<HeatSource>,
+    pub dyes: Vec<DyeDescriptor>,
+    pub buoyancy: Buoyancy,
+    pub boundaries: Boundaries,
+    pub pressure_solver: PressureSolverConfig,
+    pub time_stepping: TimeStepping,
+    pub advection: AdvectionScheme,
+    pub vorticity_confinement: VorticityConfinement,
+    /// Computes drag and lift on the obstacles every step when set.
+    pub force_reference: Option<ForceReference>,
+    /// Points recording field time series every step.
+    pub probes: Vec<ProbeDescriptor>,
+    /// Logs the flow diagnostics every this many steps.
+    pub diagnostics_interval: Option<usize>,
+}
+
+impl Default for EulerScene {
+    /// Wind tunnel with a rectangular obstacle, matching the original hardcoded setup at
+    /// its 200 x 100 resolution. Laid out in normalized units, it runs at any resolution.
+    fn default() -> Self {
+        Self {
+            width: 200,
+            height: 100,
+            spacing: 1.0 / 200.0 * 100.0,
+            units: SceneUnits::Normalized,
+            density: 1000.0,
+            viscosity: 0.0,
+            initial_velocity: [0.0, 0.0],
+            obstacles: vec![Region::new(0.255, 0.34, 0.4, 0.59)],
+            shapes: Vec::new(),
+            image_masks: Vec::new(),
+            inflows: vec![Inflow {
+                region: Region::new(0.0, 0.005, 0.0, 1.0),
+                velocity: [10.0, 0.0],
+            }],
+            smoke_sources: vec![SmokeSource {
+                region: Region::new(0.0, 0.005, 0.41, 0.59),
+                value: 1.0,
+            }],
+            heat_sources: Vec::new(),
+            dyes: Vec::new(),
+            buoyancy: Buoyancy::default(),
+            boundaries: Boundaries::default(),
+            pressure_solver: PressureSolverConfig::default(),
+            time_stepping: TimeStepping::default(),
+            advection: AdvectionScheme::default(),
+            vorticity_confinement: VorticityConfinement::default(),
+            force_reference: None,
+            probes: Vec::new(),
+            diagnostics_interval: None,
+        }
+    }
+}
+
+impl EulerScene {
+    /// The default wind tunnel, inflow on the left edge included, with `airfoil` in
+    /// place of the rectangular obstacle. The airfoil is in normalized units.
+    pub fn airfoil_tunnel(airfoil: NacaAirfoil) -> Self {
+        Self {
+            obstacles: Vec::new(),
+            shapes: vec![Obstacle::Airfoil(airfoil)],
+            ..Self::default()
+        }
+    }
+
+    /// Loads a scene, picking the format from the file extension (`toml`, `json` or `ron`).
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+        let mut scene: Self = parse_scene_file(path)?;
+
+        let directory = path.parent().unwrap_or(Path::new(""));
+        for mask in &mut scene.image_masks {
+            mask.path = directory.join(&mask.path);
+        }
+        Ok(scene)
+    }
+
+    /// Same scene at a `width` x `height` resolution, over the same domain in world
+    /// units. Layouts in any units are carried over.
+    pub fn with_resolution(&self, width: usize, height: usize) -> Self {
+        let mut scene = self.in_units(SceneUnits::World);
+        scene.spacing = self.spacing * self.width as f32 / width as f32;
+        scene.width = width;
+        scene.height = height;
+        scene.in_units(self.units)
+    }
+
+    /// Same scene with its layout converted to `units`.
+    pub fn in_units(&self, units: SceneUnits) -> Self {
+        self.to_cells().cells_to(units)
+    }
+
+    /// Same scene laid out in cell units, the form `EulerSimulation` works with.
+    pub fn to_cells(&self) -> Self {
+        let [scale_x, scale_y] = self.cell_scale();
+        let position = |p: [f32; 2]| match self.units {
+            SceneUnits::Cells => p,
+            _ => [p[0] * scale_x + 1.0, p[1] * scale_y + 1.0],
+        };
+        self.mapped(SceneUnits::Cells, position, self.cell_length())
+    }
+
+    /// Converts a scene laid out in cell units to `units`.
+    fn cells_to(&self, units: SceneUnits) -> Self {
+        let scene = Self {
+            units,
+            ..self.clone()
+        };
+        let [scale_x, scale_y] = scene.cell_scale();
+        let position = |p: [f32; 2]| match units {
+            SceneUnits::Cells => p,
+            _ => [(p[0] - 1.0) / scale_x, (p[1] - 1.0) / scale_y],
+        };
+        self.mapped(units, position, 1.0 / scene.cell_length())
+    }
+
+    /// Cells per unit along each axis, for units other than cells.
+    fn cell_scale(&self) -> [f32; 2] {
+        match self.units {
+            SceneUnits::Cells => [1.0, 1.0],
+            SceneUnits::World => [1.0 / self.spacing, 1.0 / self.spacing],
+            SceneUnits::Normalized => [self.width as f32, self.height as f32],
+        }
+    }
+
+    /// Cells per unit of length.
+    fn cell_length(&self) -> f32 {
+        match self.units {
+            SceneUnits::Normalized => self.height as f32,
+            _ => self.cell_scale()[0],
+        }
+    }
+
+    /// Copy in `units` with every position passed through `position` and every length
+    /// scaled by `length`.
+    fn mapped(
+        &self,
+        units: SceneUnits,
+        position: impl Fn([f32; 2]) -> [f32; 2],
+        length: f32,
+    ) -> Self {
+        let mut scene = self.clone();
+        scene.units = units;
+        for region in &mut scene.obstacles {
+            *region = region.mapped(&position);
+        }
+        for shape in &mut scene.shapes {
+            *shape = shape.mapped(&position, length);
+        }
+        for mask in &mut scene.image_masks {
+            mask.region = mask.region.map(|region| region.mapped(&position));
+        }
+        for inflow in &mut scene.inflows {
+            inflow.region = inflow.region.mapped(&position);
+        }
+        for source in &mut scene.smoke_sources {
+            source.region = source.region.mapped(&position);
+        }
+        for source in &mut scene.heat_sources {
+            source.region = source.region.mapped(&position);
+        }
+        for source in scene.dyes.iter_mut().flat_map(|dye| &mut dye.sources) {
+            source.region = source.region.mapped(&position);
+        }
+        for descriptor in &mut scene.probes {
+            descriptor.position = position(descriptor.position);
+        }
+        scene
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, SceneError> {
+        parse_scene(source, "toml")
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, SceneError> {
+        parse_scene(source, "json")
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self, SceneError> {
+        parse_scene(source, "ron")
+    }
+}
+
+/// Parses a scene of any kind in the format named by `extension` (`toml`, `json` or `ron`).
+pub(crate) fn parse_scene<S: DeserializeOwned>(
+    source: &str,
+    extension: &str,
+) -> Result<S, SceneError> {
+    match extension {
+        "toml" => toml::from_str(source).map_err(|err| SceneError::Parse(err.to_string())),
+        "json" => serde_json::from_str(source).map_err(|err| SceneError::Parse(err.to_string())),
+        "ron" => ron::from_str(source).map_err(|err| SceneError::Parse(err.to_string())),
+        _ => Err(SceneError::UnsupportedFormat(extension.to_string())),
+    }
+}
+
+/// Reads and parses a scene file, picking the format from its extension.
+pub(crate) fn parse_scene_file<S: DeserializeOwned>(path: &Path) -> Result<S, SceneError> {
+    let source = fs::read_to_string(path)?;
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    parse_scene(&source, extension)
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse(String),
+    UnsupportedFormat(String),
+    Image(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "could not read scene file: {}", err),
+            SceneError::Parse(err) => write!(f, "could not parse scene: {}", err),
+            SceneError::UnsupportedFormat(ext) => {
+                write!(f, "unsupported scene format: '{}'", ext)
+            }
+            SceneError::Image(err) => write!(f, "could not decode image: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<io::Error> for SceneError {
+    fn from(err: io::Error) -> Self {
+        SceneError::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Columns and rows of the obstacle and the smoke inlet of the default scene at a
+    /// `width` x `height` resolution.
+    fn default_layout(width: usize, height: usize) -> [(Range<usize>, Range<usize>); 2] {
+        let scene = EulerScene::default()
+            .with_resolution(width, height)
+            .to_cells();
+        [
+            scene.obstacles[0].cell_bounds(),
+            scene.smoke_sources[0].region.cell_bounds(),
+        ]
+    }
+
+    #[test]
+    fn default_scene_keeps_the_original_layout() {
+        let [obstacle, smoke] = default_layout(200, 100);
+        // the cells the wind tunnel used to hardcode
+        assert_eq!(obstacle, (52..69, 41..60));
+        assert_eq!(smoke, (1..2, 42..60));
+    }
+
+    #[test]
+    fn default_scene_scales_with_the_resolution() {
+        let [obstacle, smoke] = default_layout(200, 100);
+        for scale in [0.5, 2.0] {
+            let width = (200.0 * scale) as usize;
+            let [scaled_obstacle, scaled_smoke] = default_layout(width, width / 2);
+            let pairs = [
+                (obstacle.0.clone(), scaled_obstacle.0),
+                (obstacle.1.clone(), scaled_obstacle.1),
+                (smoke.1.clone(), scaled_smoke.1),
+            ];
+            for (original, scaled) in pairs {
+                // measured from the border, within a cell of the proportional bounds
+                for (a, b) in [(original.start, scaled.start), (original.end, scaled.end)] {
+                    let expected = (a - 1) as f32 * scale;
+                    assert!(
+                        ((b - 1) as f32 - expected).abs() <= 1.0,
+                        "{:?} became {:?} at scale {}",
+                        original,
+                        scaled,
+                        scale
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1,33 +1,141 @@
-use std::{cmp::min, ops::Sub, time::Duration};
+use std::time::{Duration, Instant};
 
-use glam::Vec3;
-use tracing::field::Field;
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
 
-use crate::Instance;
+use crate::{
+    fluid_fraction, label_obstacles, scalar_color, Boundaries, BoundaryCondition, Convergence,
+    DyeDescriptor, EulerScene, FlowDiagnostics, ForceReference, GaussSeidelSolver, Grid2D,
+    GridLocation, HeatSource, ImageMask, Inflow, Instance, MaskImage, MaskMarker, Obstacle,
+    ObstacleForces, PressureProblem, PressureSolver, Probe, ProbeDescriptor, ProbeField, Real,
+    Region, SceneError, SmokeSource, SolverStats,
+};
 
-pub enum FieldType {
-    UField,
-    VField,
-    SField,
+const DIFFUSION_ITERATIONS: usize = 20;
+/// Cells cut by an obstacle with less fluid than this are made fully solid.
+const MIN_FLUID_FRACTION: f32 = 0.1;
+
+/// Body force re-injecting small scale rotation lost to numerical dissipation.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VorticityConfinement {
+    pub enabled: bool,
+    pub strength: f32,
+}
+
+impl Default for VorticityConfinement {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            strength: 5.0,
+        }
+    }
+}
+
+/// How `update` turns frame time into simulation steps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode")]
+pub enum TimeStepping {
+    /// A single step with the frame duration.
+    #[default]
+    FrameTime,
+    /// `steps_per_frame` steps of `dt` every frame, regardless of the frame duration.
+    Fixed { dt: f32, steps_per_frame: usize },
+    /// Advances `frame_dt` (or the frame duration when unset) in substeps kept below
+    /// `cfl` cells of travel each. At most `max_substeps` are taken per frame.
+    Adaptive {
+        cfl: f32,
+        max_substeps: usize,
+        frame_dt: Option<f32>,
+    },
+}
+
+/// Passive scalar carried by the flow and drawn in its own colour.
+pub struct DyeField<T = f32> {
+    pub name: String,
+    pub color: [f32; 3],
+    /// Exponential decay rate per second.
+    pub dissipation: f32,
+    pub sources: Vec<SmokeSource>,
+    values: Grid2D<T>,
+}
+
+impl<T> DyeField<T> {
+    pub fn values(&self) -> &Grid2D<T> {
+        &self.values
+    }
+}
+
+/// Boussinesq buoyancy: the vertical force `-smoke_weight * s + thermal_expansion * (T - T_amb)`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Buoyancy {
+    pub ambient_temperature: f32,
+    pub smoke_weight: f32,
+    pub thermal_expansion: f32,
 }
 
-pub struct EulerSimulation {
-    pub density: f32,
+/// How `u`, `v`, `smoke` and `temperature` are transported by the flow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AdvectionScheme {
+    /// First order semi-Lagrangian with a single Euler backtrace.
+    #[default]
+    SemiLagrangian,
+    /// Semi-Lagrangian with a midpoint (RK2) backtrace.
+    Rk2,
+    /// Semi-Lagrangian with a Ralston RK3 backtrace.
+    Rk3,
+    /// MacCormack forward/backward correction over RK2 backtraces, clamped.
+    MacCormack,
+    /// Back and forth error compensation and correction over RK2 backtraces, clamped.
+    Bfecc,
+}
+
+/// Incompressible flow on a staggered grid, in `f32` or `f64` precision. Scene and
+/// configuration values are given in `f32` and converted; diagnostics, forces and probe
+/// records are reported in `f32`.
+pub struct EulerSimulation<T: Real = f32> {
+    pub density: T,
+    /// Kinematic viscosity; zero keeps the flow inviscid.
+    pub viscosity: T,
     pub width: usize,
     pub height: usize,
     pub cells_num: usize,
     pub instances: Vec<Instance>,
 
-    u: Vec<f32>,
-    v: Vec<f32>,
-    pressure: Vec<f32>,
-    solids: Vec<f32>,
-    smoke: Vec<f32>,
-    spacing: f32,
+    u: Grid2D<T>,
+    v: Grid2D<T>,
+    pressure: Grid2D<T>,
+    /// Fluid fraction of each cell, 0 for solid.
+    solids: Grid2D<T>,
+    smoke: Grid2D<T>,
+    temperature: Grid2D<T>,
+    spacing: T,
+    smoke_sources: Vec<SmokeSource>,
+    /// Inflows marked in image masks, held at their velocity every step.
+    marker_inflows: Vec<Inflow>,
+    heat_sources: Vec<HeatSource>,
+    dyes: Vec<DyeField<T>>,
+    buoyancy: Buoyancy,
+    boundaries: Boundaries,
+    pressure_solver: Box<dyn PressureSolver<T>>,
+    solver_stats: SolverStats,
+    advection: AdvectionScheme,
+    vorticity_confinement: VorticityConfinement,
+    time_stepping: TimeStepping,
+    time: T,
+    substeps: usize,
+    force_reference: Option<ForceReference>,
+    forces: Vec<ObstacleForces>,
+    probes: Vec<Probe>,
+    steps: usize,
+    last_dt: T,
+    diagnostics_interval: Option<usize>,
 }
 
-impl EulerSimulation {
-    pub fn new(density: f32, width: usize, height: usize, spacing: f32) -> Self {
+impl<T: Real> EulerSimulation<T> {
+    pub fn new(density: T, width: usize, height: usize, spacing: T) -> Self {
         let displacement = Vec3::new(1.0, 1.0, 0.0);
 
         let width = width + 2;
@@ -45,14 +153,16 @@ impl EulerSimulation {
 
         let cells_num = width * height;
 
-        let u: Vec<f32> = vec![0.0; cells_num];
-        let v: Vec<f32> = vec![0.0; cells_num];
-        let pressure: Vec<f32> = vec![0.0; cells_num];
-        let solids: Vec<f32> = vec![0.0; cells_num];
-        let smoke: Vec<f32> = vec![0.0; cells_num];
+        let u = Grid2D::new(width, height, GridLocation::UFace, T::zero());
+        let v = Grid2D::new(width, height, GridLocation::VFace, T::zero());
+        let pressure = Grid2D::new(width, height, GridLocation::CellCenter, T::zero());
+        let solids = Grid2D::new(width, height, GridLocation::CellCenter, T::zero());
+        let smoke = Grid2D::new(width, height, GridLocation::CellCenter, T::zero());
+        let temperature = Grid2D::new(width, height, GridLocation::CellCenter, T::zero());
 
         Self {
             density,
+            viscosity: T::zero(),
             width,
             height,
             cells_num,
@@ -62,295 +172,1681 @@ impl EulerSimulation {
             pressure,
             solids,
             smoke,
+            temperature,
             spacing,
+            smoke_sources: Vec::new(),
+            marker_inflows: Vec::new(),
+            heat_sources: Vec::new(),
+            dyes: Vec::new(),
+            buoyancy: Buoyancy::default(),
+            boundaries: Boundaries::default(),
+            pressure_solver: Box::new(GaussSeidelSolver::new(Convergence::fixed(20), 1.9)),
+            solver_stats: SolverStats::default(),
+            advection: AdvectionScheme::default(),
+            vorticity_confinement: VorticityConfinement::default(),
+            time_stepping: TimeStepping::default(),
+            time: T::zero(),
+            substeps: 0,
+            force_reference: None,
+            forces: Vec::new(),
+            probes: Vec::new(),
+            steps: 0,
+            last_dt: T::zero(),
+            diagnostics_interval: None,
         }
     }
 
+    pub fn from_scene(scene: &EulerScene) -> Self {
+        let mut simulation = Self::new(
+            T::from_f32(scene.density),
+            scene.width,
+            scene.height,
+            T::from_f32(scene.spacing),
+        );
+        simulation.apply_scene(scene);
+        simulation
+    }
+
     pub fn init(&mut self) {
+        self.apply_scene(&EulerScene::default());
+    }
+
+    /// Resets all fields and lays out walls, obstacles and inflows from `scene`, in
+    /// whatever units it uses. Regions falling outside of the grid are clipped.
+    pub fn apply_scene(&mut self, scene: &EulerScene) {
+        let scene = &scene.to_cells();
+
+        self.pressure.fill(T::zero());
+        self.smoke.fill(T::zero());
+        self.temperature
+            .fill(T::from_f32(scene.buoyancy.ambient_temperature));
+        self.boundaries = scene.boundaries.validated();
+
         for i in 0..self.width {
             for j in 0..self.height {
-                if i == 0 || j == 0 || j == self.height - 1 {
-                    self.solids[i * self.height + j] = 0.0; // solid
-                    continue;
+                let is_wall = self.boundaries.is_solid(i, j, self.width, self.height);
+                if is_wall || scene.obstacles.iter().any(|o| o.contains(i, j)) {
+                    self.solids[(i, j)] = T::zero(); // solid
                 } else {
-                    self.solids[i * self.height + j] = 1.0;
+                    self.solids[(i, j)] = T::one();
+                }
+
+                // faces touching a solid cell start at rest
+                let fluid = !self.solids[(i, j)].is_zero();
+                self.u[(i, j)] = if fluid && (i == 0 || !self.solids[(i - 1, j)].is_zero()) {
+                    T::from_f32(scene.initial_velocity[0])
+                } else {
+                    T::zero()
+                };
+                self.v[(i, j)] = if fluid && (j == 0 || !self.solids[(i, j - 1)].is_zero()) {
+                    T::from_f32(scene.initial_velocity[1])
+                } else {
+                    T::zero()
+                };
+            }
+        }
+
+        for shape in &scene.shapes {
+            self.add_obstacle(shape);
+        }
+
+        for inflow in &scene.inflows {
+            for (i, j) in inflow.region.cells(self.width, self.height) {
+                if !self.solids[(i, j)].is_zero() {
+                    self.u[(i, j)] = T::from_f32(inflow.velocity[0]);
+                    self.v[(i, j)] = T::from_f32(inflow.velocity[1]);
+                }
+            }
+        }
+
+        self.smoke_sources = scene.smoke_sources.clone();
+        self.marker_inflows.clear();
+        self.heat_sources = scene.heat_sources.clone();
+        self.dyes.clear();
+        for dye in &scene.dyes {
+            self.add_dye(dye);
+        }
+        for mask in &scene.image_masks {
+            if let Err(err) = self.load_image_mask(mask) {
+                warn!("skipping image mask {}: {}", mask.path.display(), err);
+            }
+        }
+        self.buoyancy = scene.buoyancy;
+        self.pressure_solver = scene.pressure_solver.build();
+        self.advection = scene.advection;
+        self.vorticity_confinement = scene.vorticity_confinement;
+        self.viscosity = T::from_f32(scene.viscosity);
+        self.time_stepping = scene.time_stepping;
+        self.force_reference = scene.force_reference;
+        self.forces.clear();
+        // probes sample in world units, from the corner of the interior like the scene
+        let h = self.spacing.as_f32();
+        self.probes = scene
+            .probes
+            .iter()
+            .map(|descriptor| {
+                Probe::new(&ProbeDescriptor {
+                    position: descriptor.position.map(|x| (x - 1.0) * h),
+                    ..descriptor.clone()
+                })
+            })
+            .collect();
+        self.diagnostics_interval = scene.diagnostics_interval;
+        self.time = T::zero();
+        self.steps = 0;
+        self.apply_boundaries();
+    }
+
+    pub fn boundaries(&self) -> &Boundaries {
+        &self.boundaries
+    }
+
+    /// Rasterizes `obstacle` into the solids, see `add_obstacle_sdf`.
+    pub fn add_obstacle(&mut self, obstacle: &Obstacle) {
+        if let Obstacle::Airfoil(airfoil) = obstacle {
+            // trace the outline once rather than for every cell
+            let polygon = airfoil.polygon();
+            self.add_obstacle_sdf(|p| polygon.signed_distance(p));
+        } else {
+            self.add_obstacle_sdf(|p| obstacle.signed_distance(p));
+        }
+    }
+
+    /// Makes the region where `sdf` is negative solid. Distances are in cell units,
+    /// with cell `(i, j)` spanning `[i, i + 1]` x `[j, j + 1]`. Cells cut by the
+    /// boundary keep the fluid fraction estimated from the distance at their centre.
+    pub fn add_obstacle_sdf(&mut self, sdf: impl Fn(Vec2) -> f32) {
+        for i in 1..self.width - 1 {
+            for j in 1..self.height - 1 {
+                let mut fluid = fluid_fraction(sdf(Vec2::new(i as f32 + 0.5, j as f32 + 0.5)));
+                if fluid < MIN_FLUID_FRACTION {
+                    fluid = 0.0;
+                }
+                self.solids[(i, j)] = self.solids[(i, j)].min(T::from_f32(fluid));
+            }
+        }
+        self.clear_solid_cells();
+    }
+
+    /// Loads the image of `mask` and applies it, see `apply_image_mask`.
+    pub fn load_image_mask(&mut self, mask: &ImageMask) -> Result<(), SceneError> {
+        let image = mask.load()?;
+        self.apply_image_mask(mask, &image);
+        Ok(())
+    }
+
+    /// Stretches `image` over the region of `mask`, the top image row on the top cell row.
+    /// Each cell keeps the fraction of its pixels that are not solid, and becomes a
+    /// source when one of them matches a marker.
+    pub fn apply_image_mask(&mut self, mask: &ImageMask, image: &MaskImage) {
+        let region = mask.region.unwrap_or(Region::new(
+            1.0,
+            (self.width - 1) as f32,
+            1.0,
+            (self.height - 1) as f32,
+        ));
+        let (xs, ys) = region.cell_bounds();
+        let (region_width, region_height) = (xs.len(), ys.len());
+        if region_width == 0 || region_height == 0 || image.width == 0 || image.height == 0 {
+            return;
+        }
+
+        for (i, j) in region.cells(self.width, self.height) {
+            let (ci, cj) = (i - xs.start, ys.end - 1 - j);
+            let x0 = ci * image.width / region_width;
+            let x1 = ((ci + 1) * image.width / region_width).max(x0 + 1);
+            let y0 = cj * image.height / region_height;
+            let y1 = ((cj + 1) * image.height / region_height).max(y0 + 1);
+
+            let mut solid = 0;
+            let mut marker = None;
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let pixel = image.pixel(x, y);
+                    if let Some(found) = mask.marker(pixel) {
+                        marker = Some(*found);
+                    } else if mask.is_solid(pixel) {
+                        solid += 1;
+                    }
+                }
+            }
+
+            let mut fluid = 1.0 - solid as f32 / ((x1 - x0) * (y1 - y0)) as f32;
+            if fluid < MIN_FLUID_FRACTION {
+                fluid = 0.0;
+            }
+            self.solids[(i, j)] = self.solids[(i, j)].min(T::from_f32(fluid));
+
+            match marker {
+                Some(MaskMarker::Smoke { value, .. }) => self.smoke_sources.push(SmokeSource {
+                    region: Region::cell(i, j),
+                    value,
+                }),
+                Some(MaskMarker::Inflow { velocity, .. }) => self.marker_inflows.push(Inflow {
+                    region: Region::cell(i, j),
+                    velocity,
+                }),
+                None => {}
+            }
+        }
+        self.clear_solid_cells();
+    }
+
+    /// Removes all obstacles, leaving only the walls of the domain edges.
+    pub fn clear_obstacles(&mut self) {
+        for i in 1..self.width - 1 {
+            for j in 1..self.height - 1 {
+                self.solids[(i, j)] = T::one();
+            }
+        }
+    }
+
+    /// Stops the flow on the faces of fully solid interior cells and empties their scalars.
+    fn clear_solid_cells(&mut self) {
+        for i in 1..self.width - 1 {
+            for j in 1..self.height - 1 {
+                if !self.solids[(i, j)].is_zero() {
+                    continue;
                 }
+                self.u.set(i, j, T::zero());
+                self.u.set(i + 1, j, T::zero());
+                self.v.set(i, j, T::zero());
+                self.v.set(i, j + 1, T::zero());
+                self.smoke.set(i, j, T::zero());
+                for dye in &mut self.dyes {
+                    dye.values.set(i, j, T::zero());
+                }
+            }
+        }
+    }
+
+    pub fn set_time_stepping(&mut self, time_stepping: TimeStepping) {
+        self.time_stepping = time_stepping;
+    }
+
+    /// Simulated time since the scene was applied.
+    pub fn time(&self) -> T {
+        self.time
+    }
+
+    /// Number of steps taken by the last `update`.
+    pub fn substeps(&self) -> usize {
+        self.substeps
+    }
+
+    /// Largest step that moves the fastest face velocity by at most `cfl` cells.
+    pub fn cfl_time_step(&self, cfl: T) -> T {
+        let max_velocity = self.max_velocity();
+        if max_velocity > T::zero() {
+            cfl * self.spacing / max_velocity
+        } else {
+            T::infinity()
+        }
+    }
+
+    /// Logs `diagnostics()` through `tracing` every `interval` steps, or never with `None`.
+    pub fn set_diagnostics_logging(&mut self, interval: Option<usize>) {
+        self.diagnostics_interval = interval;
+    }
 
-                if i > 51 && i < 69 && j > 40 && j < 60 {
-                    self.solids[i * self.height + j] = 0.0; // solid
+    /// Total number of steps since the scene was applied.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Divergence, energy, enstrophy, scalar totals and pressure and velocity extremes
+    /// of the current state.
+    pub fn diagnostics(&self) -> FlowDiagnostics {
+        let h = self.spacing;
+        let area = h * h;
+        let half = T::from_f64(0.5);
+        let fluid = |i: usize, j: usize| !self.solids[(i, j)].is_zero();
+
+        let mut max_divergence = T::zero();
+        let mut mean_divergence = T::zero();
+        let mut kinetic_energy = T::zero();
+        let mut enstrophy = T::zero();
+        let mut total_smoke = T::zero();
+        let mut dye_mass = vec![T::zero(); self.dyes.len()];
+        let mut min_pressure = T::infinity();
+        let mut max_pressure = T::neg_infinity();
+        let mut max_velocity = T::zero();
+        let mut fluid_cells = 0;
+
+        for i in 1..self.width - 1 {
+            for j in 1..self.height - 1 {
+                if !fluid(i, j) {
                     continue;
                 }
+                let s = self.solids[(i, j)];
+                fluid_cells += 1;
+
+                let divergence =
+                    (self.u[(i + 1, j)] - self.u[(i, j)] + self.v[(i, j + 1)] - self.v[(i, j)]) / h;
+                max_divergence = max_divergence.max(divergence.abs());
+                mean_divergence += divergence.abs();
+
+                let u = half * (self.u[(i, j)] + self.u[(i + 1, j)]);
+                let v = half * (self.v[(i, j)] + self.v[(i, j + 1)]);
+                kinetic_energy += half * self.density * (u * u + v * v) * s * area;
+                max_velocity = max_velocity.max((u * u + v * v).sqrt());
+
+                // vorticity on the lower left corner, where four fluid cells meet
+                if fluid(i - 1, j) && fluid(i, j - 1) && fluid(i - 1, j - 1) {
+                    let curl = (self.v[(i, j)] - self.v[(i - 1, j)] - self.u[(i, j)]
+                        + self.u[(i, j - 1)])
+                        / h;
+                    enstrophy += half * curl * curl * area;
+                }
 
-                if i == 1 {
-                    self.u[i * self.height + j] = 10.0;
+                total_smoke += self.smoke[(i, j)] * s * area;
+                for (mass, dye) in dye_mass.iter_mut().zip(&self.dyes) {
+                    *mass += dye.values[(i, j)] * s * area;
                 }
+                min_pressure = min_pressure.min(self.pressure[(i, j)]);
+                max_pressure = max_pressure.max(self.pressure[(i, j)]);
+            }
+        }
+
+        if fluid_cells > 0 {
+            mean_divergence /= T::from_usize(fluid_cells);
+        } else {
+            min_pressure = T::zero();
+            max_pressure = T::zero();
+        }
+        FlowDiagnostics {
+            time: self.time.as_f32(),
+            max_divergence: max_divergence.as_f32(),
+            mean_divergence: mean_divergence.as_f32(),
+            kinetic_energy: kinetic_energy.as_f32(),
+            enstrophy: enstrophy.as_f32(),
+            total_smoke: total_smoke.as_f32(),
+            dye_mass: dye_mass.into_iter().map(T::as_f32).collect(),
+            min_pressure: min_pressure.as_f32(),
+            max_pressure: max_pressure.as_f32(),
+            max_velocity: max_velocity.as_f32(),
+            cfl: (max_velocity * self.last_dt / h).as_f32(),
+        }
+    }
+
+    /// Upper bound on the speed anywhere on the grid, from the largest `|u|` and `|v|`
+    /// over all faces.
+    fn max_velocity(&self) -> T {
+        let max_u = self.u.iter().fold(T::zero(), |m, u| m.max(u.abs()));
+        let max_v = self.v.iter().fold(T::zero(), |m, v| m.max(v.abs()));
+        (max_u * max_u + max_v * max_v).sqrt()
+    }
+
+    /// Adds an empty dye field; returns its index in `dyes()`.
+    pub fn add_dye(&mut self, descriptor: &DyeDescriptor) -> usize {
+        self.dyes.push(DyeField {
+            name: descriptor.name.clone(),
+            color: descriptor.color,
+            dissipation: descriptor.dissipation,
+            sources: descriptor.sources.clone(),
+            values: Grid2D::new(self.width, self.height, GridLocation::CellCenter, T::zero()),
+        });
+        self.dyes.len() - 1
+    }
+
+    /// Bilinearly interpolated value of `field` at the world position `(x, y)`, measured
+    /// from the corner of the interior like `SceneUnits::World`. `None` for an unknown dye.
+    pub fn sample(&self, field: &ProbeField, x: T, y: T) -> Option<T> {
+        // the grids start at the corner of the border, one cell further out
+        let (x, y) = (x + self.spacing, y + self.spacing);
+        let value = match field {
+            ProbeField::U => self.sample_values(&self.u, x, y),
+            ProbeField::V => self.sample_values(&self.v, x, y),
+            ProbeField::Speed => {
+                let (u, v) = self.velocity_at(x, y);
+                (u * u + v * v).sqrt()
+            }
+            ProbeField::Pressure => self.sample_values(&self.pressure, x, y),
+            ProbeField::Smoke => self.sample_values(&self.smoke, x, y),
+            ProbeField::Temperature => self.sample_values(&self.temperature, x, y),
+            ProbeField::Dye(name) => self.sample_values(&self.dye(name)?.values, x, y),
+        };
+        Some(value)
+    }
+
+    /// Adds a probe recording its fields after every step and returns its index. Its
+    /// position is in world units, see `sample`.
+    pub fn add_probe(&mut self, descriptor: &ProbeDescriptor) -> usize {
+        self.probes.push(Probe::new(descriptor));
+        self.probes.len() - 1
+    }
+
+    pub fn probes(&self) -> &[Probe] {
+        &self.probes
+    }
+
+    pub fn probe(&self, name: &str) -> Option<&Probe> {
+        self.probes.iter().find(|probe| probe.name == name)
+    }
+
+    pub fn probe_mut(&mut self, name: &str) -> Option<&mut Probe> {
+        self.probes.iter_mut().find(|probe| probe.name == name)
+    }
+
+    /// Samples every probe at the current time. Unknown dyes record `NaN`.
+    fn record_probes(&mut self) {
+        let mut probes = std::mem::take(&mut self.probes);
+        for probe in &mut probes {
+            let [x, y] = probe.position.map(T::from_f32);
+            let values: Vec<_> = probe
+                .fields
+                .iter()
+                .map(|field| self.sample(field, x, y).map_or(f32::NAN, T::as_f32))
+                .collect();
+            probe.record(self.time.as_f32(), values);
+        }
+        self.probes = probes;
+    }
+
+    pub fn dyes(&self) -> &[DyeField<T>] {
+        &self.dyes
+    }
+
+    pub fn dye(&self, name: &str) -> Option<&DyeField<T>> {
+        self.dyes.iter().find(|dye| dye.name == name)
+    }
+
+    pub fn dye_mut(&mut self, name: &str) -> Option<&mut DyeField<T>> {
+        self.dyes.iter_mut().find(|dye| dye.name == name)
+    }
+
+    /// Sets the viscosity giving Reynolds number `reynolds` for flow at `speed` around
+    /// a body of size `length`.
+    pub fn set_reynolds_number(&mut self, reynolds: T, speed: T, length: T) {
+        self.viscosity = speed * length / reynolds;
+    }
+
+    pub fn reynolds_number(&self, speed: T, length: T) -> T {
+        speed * length / self.viscosity
+    }
+
+    pub fn set_pressure_solver(&mut self, solver: impl PressureSolver<T> + 'static) {
+        self.pressure_solver = Box::new(solver);
+    }
+
+    pub fn set_advection(&mut self, advection: AdvectionScheme) {
+        self.advection = advection;
+    }
+
+    pub fn set_vorticity_confinement(&mut self, vorticity_confinement: VorticityConfinement) {
+        self.vorticity_confinement = vorticity_confinement;
+    }
+
+    pub fn toggle_vorticity_confinement(&mut self) -> bool {
+        self.vorticity_confinement.enabled = !self.vorticity_confinement.enabled;
+        self.vorticity_confinement.enabled
+    }
+
+    /// Statistics of the pressure projection performed by the last `update`.
+    pub fn solver_stats(&self) -> SolverStats {
+        self.solver_stats
+    }
+
+    /// Enables computing the obstacle forces after every projection.
+    pub fn set_force_reference(&mut self, reference: Option<ForceReference>) {
+        self.force_reference = reference;
+        self.forces.clear();
+    }
+
+    /// Forces on every obstacle from the last step, empty without a force reference.
+    pub fn forces(&self) -> &[ObstacleForces] {
+        &self.forces
+    }
+
+    /// Integrates pressure, and viscous shear when the flow is viscous, over the surface
+    /// of every connected obstacle. Partially solid cells contribute in proportion to
+    /// the change in solid fraction across each face.
+    pub fn compute_forces(&self, reference: &ForceReference) -> Vec<ObstacleForces> {
+        let (labels, count) = label_obstacles(&self.solids);
+        let mut forces = vec![ObstacleForces::default(); count];
+
+        for (i, j) in self.solids.cells() {
+            if let Some(k) = labels[(i, j)] {
+                forces[k].cells += 1;
+                forces[k].centroid[0] += i as f32 + 0.5;
+                forces[k].centroid[1] += j as f32 + 0.5;
+            }
+            if i > 0 {
+                self.add_face_force(&mut forces, &labels, (i - 1, j), (i, j), 0);
             }
+            if j > 0 {
+                self.add_face_force(&mut forces, &labels, (i, j - 1), (i, j), 1);
+            }
+        }
+
+        for force in &mut forces {
+            force.centroid = force.centroid.map(|x| x / force.cells as f32);
+            force.finish(reference, self.density.as_f32());
+        }
+        forces
+    }
+
+    /// Adds the force on the face between cell `a` and the next cell `b` along `axis`.
+    fn add_face_force(
+        &self,
+        forces: &mut [ObstacleForces],
+        labels: &Grid2D<Option<usize>>,
+        a: (usize, usize),
+        b: (usize, usize),
+        axis: usize,
+    ) {
+        let h = self.spacing;
+        let half = T::from_f64(0.5);
+        // increase of the solid fraction from a to b
+        let step = self.solids[a] - self.solids[b];
+        if step.is_zero() {
+            return;
+        }
+        let (solid, fluid) = if step > T::zero() { (b, a) } else { (a, b) };
+        let Some(k) = labels[solid] else {
+            return;
+        };
+
+        let pressure = if self.solids[a] > T::zero() && self.solids[b] > T::zero() {
+            half * (self.pressure[a] + self.pressure[b])
+        } else {
+            self.pressure[fluid]
+        };
+        forces[k].pressure_force[axis] += (pressure * step * h).as_f32();
+
+        let (i, j) = fluid;
+        let interior = i > 0 && j > 0 && i < self.width - 1 && j < self.height - 1;
+        if self.viscosity > T::zero() && interior {
+            // tangential velocity at the fluid cell centre, zero on the surface half a cell away
+            let tangential = if axis == 0 {
+                half * (self.v[(i, j)] + self.v[(i, j + 1)])
+            } else {
+                half * (self.u[(i, j)] + self.u[(i + 1, j)])
+            };
+            let shear = self.density * self.viscosity * tangential / (half * h);
+            forces[k].viscous_force[1 - axis] += (shear * h * step.abs()).as_f32();
         }
     }
 
     pub fn update(&mut self, dt: Duration) {
-        let dt = dt.as_secs_f32();
-        for i in 0..self.width {
-            for j in 0..self.height {
-                if j > 41 && j < 60 && i == 1 {
-                    self.smoke[i * self.height + j] = 1.0;
+        let frame_dt = T::from_f64(dt.as_secs_f64());
+        self.substeps = 0;
+
+        match self.time_stepping {
+            TimeStepping::FrameTime => self.step(frame_dt),
+            TimeStepping::Fixed {
+                dt,
+                steps_per_frame,
+            } => {
+                for _ in 0..steps_per_frame {
+                    self.step(T::from_f32(dt));
+                }
+            }
+            TimeStepping::Adaptive {
+                cfl,
+                max_substeps,
+                frame_dt: fixed_frame_dt,
+            } => {
+                let mut remaining = fixed_frame_dt.map_or(frame_dt, T::from_f32);
+                while remaining > T::zero() && self.substeps < max_substeps {
+                    let mut dt = self.cfl_time_step(T::from_f32(cfl));
+                    if dt >= remaining {
+                        dt = remaining;
+                    } else if T::from_f64(2.0) * dt >= remaining {
+                        dt = T::from_f64(0.5) * remaining;
+                    }
+                    self.step(dt);
+                    remaining -= dt;
+                }
+            }
+        }
+
+        self.update_instances();
+    }
+
+    /// Advances the simulation by a single step of `dt` seconds.
+    fn step(&mut self, dt: T) {
+        for source in &self.smoke_sources {
+            for (i, j) in source.region.cells(self.width, self.height) {
+                self.smoke[(i, j)] = T::from_f32(source.value);
+            }
+        }
+        for source in &self.heat_sources {
+            for (i, j) in source.region.cells(self.width, self.height) {
+                self.temperature[(i, j)] = T::from_f32(source.temperature);
+            }
+        }
+        for dye in &mut self.dyes {
+            for source in &dye.sources {
+                for (i, j) in source.region.cells(self.width, self.height) {
+                    dye.values[(i, j)] = T::from_f32(source.value);
+                }
+            }
+        }
+
+        self.apply_boundaries();
+        self.apply_buoyancy(dt);
+
+        if self.vorticity_confinement.enabled {
+            self.apply_vorticity_confinement(dt);
+        }
+
+        if self.viscosity > T::zero() {
+            self.diffuse_velocity(dt);
+        }
+
+        for inflow in &self.marker_inflows {
+            for (i, j) in inflow.region.cells(self.width, self.height) {
+                if !self.solids[(i, j)].is_zero() {
+                    self.u[(i, j)] = T::from_f32(inflow.velocity[0]);
+                    self.v[(i, j)] = T::from_f32(inflow.velocity[1]);
                 }
             }
         }
 
-        self.pressure.fill(0.0);
+        self.pressure.fill(T::zero());
 
-        self.solve_incompressibility(20, dt);
+        self.solve_incompressibility(dt);
+        if let Some(reference) = self.force_reference {
+            self.forces = self.compute_forces(&reference);
+        }
 
-        self.extrapolate();
+        self.apply_boundaries();
         self.advect_vel(dt);
         self.advect_smoke(dt);
+        self.advect_temperature(dt);
+        self.advect_dyes(dt);
 
-        let min_p = self.pressure.iter().fold(f32::INFINITY, |a, &b| a.min(b));
+        self.time += dt;
+        self.substeps += 1;
+        self.steps += 1;
+        self.last_dt = dt;
+        self.record_probes();
+
+        if let Some(interval) = self.diagnostics_interval {
+            if interval > 0 && self.steps.is_multiple_of(interval) {
+                info!("{}", self.diagnostics());
+            }
+        }
+    }
+
+    /// Colours the grid by pressure masked with smoke, with the dyes composited on top.
+    pub fn update_instances(&mut self) {
+        let min_p = self.pressure.iter().fold(T::infinity(), |a, &b| a.min(b));
         let max_p = self
             .pressure
             .iter()
-            .fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+            .fold(T::neg_infinity(), |a, &b| a.max(b));
+        let (min_p, max_p) = (min_p.as_f32(), max_p.as_f32());
 
         for x in 0..self.width {
             for y in 0..self.height {
-                let p = self.pressure[y * self.width + x];
-                let s = self.smoke[y * self.width + x];
-                let color = Self::get_color(p, min_p, max_p);
-                let (r, g, b) = (
-                    f32::max(0.0, color.0 * s),
-                    f32::max(0.0, color.1 * s),
-                    f32::max(0.0, color.2 * s),
-                );
-
-                // let velocity = self.v[(y * self.width + x) as usize];
-                // self.instances[(y * self.width + x) as usize].color = [velocity, 0.0, 0.0];
-                self.instances[(y * self.width + x) as usize].color = [r, g, b];
-                // let smoke = self.smoke[(y * self.width + x) as usize];
-                // self.instances[(y * self.width + x) as usize].color = [smoke, smoke, smoke];
-            }
-        }
-    }
-
-    fn get_color(val: f32, min_val: f32, max_val: f32) -> (f32, f32, f32) {
-        let mut val = f32::min(f32::max(val, min_val), max_val - 0.01);
-        let d = max_val - min_val;
-        val = if d == 0.0 { 0.5 } else { (val - min_val) / d };
-        let m = 0.25;
-        let num = (val / m).floor();
-        let s = (val - num * m) / m;
-        let (mut r, mut g, mut b): (f32, f32, f32) = (0.0, 0.0, 0.0);
-        match num as i32 {
-            0 => {
-                r = 0.0;
-                g = s;
-                b = 1.0;
-            }
-            1 => {
-                r = 0.0;
-                g = 1.0;
-                b = 1.0 - s;
+                let p = self.pressure[(x, y)].as_f32();
+                let s = self.smoke[(x, y)].as_f32();
+                let color = scalar_color(p, min_p, max_p);
+                let [mut r, mut g, mut b] = color.map(|c| f32::max(0.0, c * s));
+
+                for dye in &self.dyes {
+                    let alpha = dye.values[(x, y)].as_f32().clamp(0.0, 1.0);
+                    r = r * (1.0 - alpha) + dye.color[0] * alpha;
+                    g = g * (1.0 - alpha) + dye.color[1] * alpha;
+                    b = b * (1.0 - alpha) + dye.color[2] * alpha;
+                }
+
+                // instances are laid out like the grid, column by column
+                self.instances[self.pressure.index(x, y)].color = [r, g, b];
             }
-            2 => {
-                r = s;
-                g = 1.0;
-                b = 0.0;
+        }
+    }
+
+    fn solve_incompressibility(&mut self, dt: T) {
+        let mut problem = PressureProblem {
+            u: &mut self.u,
+            v: &mut self.v,
+            pressure: &mut self.pressure,
+            solids: &self.solids,
+            air: None,
+            scale: self.density * self.spacing / dt,
+            periodic: [self.boundaries.periodic_x(), self.boundaries.periodic_y()],
+        };
+
+        let start = Instant::now();
+        let mut stats = self.pressure_solver.solve(&mut problem);
+        stats.wall_time = start.elapsed();
+
+        if !stats.converged {
+            warn!(
+                "Pressure solver did not converge: residual {} after {} iterations",
+                stats.final_residual, stats.iterations
+            );
+        }
+        self.solver_stats = stats;
+    }
+
+    fn apply_buoyancy(&mut self, dt: T) {
+        let Buoyancy {
+            ambient_temperature,
+            smoke_weight,
+            thermal_expansion,
+        } = self.buoyancy;
+        if smoke_weight == 0.0 && thermal_expansion == 0.0 {
+            return;
+        }
+        let ambient_temperature = T::from_f32(ambient_temperature);
+        let smoke_weight = T::from_f32(smoke_weight);
+        let thermal_expansion = T::from_f32(thermal_expansion);
+        let half = T::from_f64(0.5);
+
+        for i in 1..self.width - 1 {
+            for j in 1..self.height {
+                if !self.is_advected(i, j, GridLocation::VFace) {
+                    continue;
+                }
+                let smoke = half * (self.smoke[(i, j)] + self.smoke[(i, j - 1)]);
+                let temperature = half * (self.temperature[(i, j)] + self.temperature[(i, j - 1)]);
+                let force =
+                    -smoke_weight * smoke + thermal_expansion * (temperature - ambient_temperature);
+                self.v[(i, j)] += dt * force;
             }
-            3 => {
-                r = 1.0;
-                g = 1.0 - s;
-                b = 0.0;
+        }
+    }
+
+    /// Adds `strength * h * (N x w)` to the face velocities, where `w` is the cell
+    /// vorticity and `N` the normalized gradient of its magnitude.
+    fn apply_vorticity_confinement(&mut self, dt: T) {
+        let h = self.spacing;
+        let strength = T::from_f32(self.vorticity_confinement.strength);
+        let half = T::from_f64(0.5);
+
+        let mut curl = Grid2D::new(self.width, self.height, GridLocation::CellCenter, T::zero());
+        for (i, j) in self.solids.interior() {
+            if self.solids[(i, j)].is_zero() {
+                continue;
             }
-            _ => {
-                r = 0.0;
-                g = 0.0;
-                b = 0.0;
+            let v_right = self.v[(i + 1, j)] + self.v[(i + 1, j + 1)];
+            let v_left = self.v[(i - 1, j)] + self.v[(i - 1, j + 1)];
+            let u_top = self.u[(i, j + 1)] + self.u[(i + 1, j + 1)];
+            let u_bottom = self.u[(i, j - 1)] + self.u[(i + 1, j - 1)];
+            curl[(i, j)] = ((v_right - v_left) - (u_top - u_bottom)) * T::from_f64(0.25) / h;
+        }
+
+        let mut force_x = Grid2D::new(self.width, self.height, GridLocation::CellCenter, T::zero());
+        let mut force_y = force_x.clone();
+        for i in 2..self.width - 2 {
+            for j in 2..self.height - 2 {
+                if self.solids[(i, j)].is_zero() {
+                    continue;
+                }
+                let grad_x = (curl[(i + 1, j)].abs() - curl[(i - 1, j)].abs()) * half / h;
+                let grad_y = (curl[(i, j + 1)].abs() - curl[(i, j - 1)].abs()) * half / h;
+                let length = (grad_x * grad_x + grad_y * grad_y).sqrt() + T::from_f64(1e-6);
+                let (nx, ny) = (grad_x / length, grad_y / length);
+
+                force_x[(i, j)] = strength * h * ny * curl[(i, j)];
+                force_y[(i, j)] = -strength * h * nx * curl[(i, j)];
             }
         }
 
-        return (r, g, b);
+        for i in 1..self.width {
+            for j in 1..self.height {
+                if self.is_advected(i, j, GridLocation::UFace) {
+                    self.u[(i, j)] += dt * half * (force_x[(i, j)] + force_x[(i - 1, j)]);
+                }
+                if self.is_advected(i, j, GridLocation::VFace) {
+                    self.v[(i, j)] += dt * half * (force_y[(i, j)] + force_y[(i, j - 1)]);
+                }
+            }
+        }
     }
 
-    fn solve_incompressibility(&mut self, num_iters: usize, dt: f32) {
-        let n = self.height;
-        let cp = self.density * self.spacing / dt;
+    /// Implicit viscous step `(I - viscosity * dt * L) u' = u`, solved with Gauss-Seidel.
+    /// Faces next to solid cells see a mirrored ghost velocity, giving no-slip walls,
+    /// except along free-slip edges where the tangential velocity has zero gradient.
+    fn diffuse_velocity(&mut self, dt: T) {
+        let alpha = self.viscosity * dt / (self.spacing * self.spacing);
+        let two = T::from_f64(2.0);
+        let is_solid = |(i, j): (usize, usize)| self.solids[(i, j)].is_zero();
+        let is_free_slip = |(i, j): (usize, usize)| {
+            self.boundaries.at(i, j, self.width, self.height)
+                == Some(BoundaryCondition::FreeSlipWall)
+        };
 
-        for iteration in 0..num_iters {
-            for i in 1..self.width - 1 {
-                for j in 1..self.height - 1 {
-                    if self.solids[i * n + j] == 0.0 {
-                        continue;
+        let old_u = self.u.clone();
+        let old_v = self.v.clone();
+        let mut u = self.u.clone();
+        let mut v = self.v.clone();
+
+        for _ in 0..DIFFUSION_ITERATIONS {
+            for i in 1..self.width {
+                for j in 1..self.height {
+                    if self.is_advected(i, j, GridLocation::UFace) {
+                        let mut diag = T::one() + two * alpha;
+                        let mut sum = u[(i - 1, j)];
+                        match u.get(i + 1, j) {
+                            Some(&next) => sum += next,
+                            None => diag -= alpha,
+                        }
+                        for (ti, tj) in [(i, j - 1), (i, j + 1)] {
+                            let (t, behind) = ((ti, tj), (ti - 1, tj));
+                            if is_free_slip(t) && is_free_slip(behind) {
+                                continue;
+                            } else if is_solid(t) || is_solid(behind) {
+                                diag += two * alpha;
+                            } else {
+                                diag += alpha;
+                                sum += u[t];
+                            }
+                        }
+                        u[(i, j)] = (old_u[(i, j)] + alpha * sum) / diag;
                     }
 
-                    let sx0 = self.solids[(i - 1) * n + j];
-                    let sx1 = self.solids[(i + 1) * n + j];
-                    let sy0 = self.solids[i * n + j - 1];
-                    let sy1 = self.solids[i * n + j + 1];
+                    if self.is_advected(i, j, GridLocation::VFace) {
+                        let mut diag = T::one() + two * alpha;
+                        let mut sum = v[(i, j - 1)];
+                        match v.get(i, j + 1) {
+                            Some(&next) => sum += next,
+                            None => diag -= alpha,
+                        }
+                        for (ti, tj) in [(i - 1, j), (i + 1, j)] {
+                            let (t, below) = ((ti, tj), (ti, tj - 1));
+                            if is_free_slip(t) && is_free_slip(below) {
+                                continue;
+                            } else if is_solid(t) || is_solid(below) {
+                                diag += two * alpha;
+                            } else {
+                                diag += alpha;
+                                sum += v[t];
+                            }
+                        }
+                        v[(i, j)] = (old_v[(i, j)] + alpha * sum) / diag;
+                    }
+                }
+            }
+        }
 
-                    let s = sx0 + sx1 + sy0 + sy1;
+        self.u = u;
+        self.v = v;
+    }
 
-                    if s == 0.0 {
-                        continue;
-                    }
+    /// Fills the ghost cells along the domain border from the edge conditions.
+    fn apply_boundaries(&mut self) {
+        let (w, h) = (self.width, self.height);
+        let b = self.boundaries;
 
-                    let div = self.u[(i + 1) * n + j] - self.u[i * n + j] + self.v[i * n + j + 1]
-                        - self.v[i * n + j];
+        // (condition, x edge, ghost, adjacent interior cell, periodic partner)
+        let mut cells = Vec::with_capacity(2 * (w + h));
+        // the left and right edges own the corners and come last, so that they copy the
+        // bottom and top ghost cells of this call rather than those of the previous one
+        for i in 1..w - 1 {
+            cells.push((b.bottom, false, (i, 0), (i, 1), (i, h - 2)));
+            cells.push((b.top, false, (i, h - 1), (i, h - 2), (i, 1)));
+        }
+        for j in 0..h {
+            cells.push((b.left, true, (0, j), (1, j), (w - 2, j)));
+            cells.push((b.right, true, (w - 1, j), (w - 2, j), (1, j)));
+        }
 
-                    let mut p = -div / s;
-                    p *= 1.9;
-                    self.pressure[i * n + j] += cp * p;
+        for &(condition, x_edge, ghost, interior, partner) in &cells {
+            let (normal, tangential) = if x_edge {
+                (&mut self.u, &mut self.v)
+            } else {
+                (&mut self.v, &mut self.u)
+            };
+            // faces are indexed by the cell on their right (top) side, the ghost and the
+            // interior cell differ along a single axis
+            let face = ghost.max(interior);
 
-                    self.u[i * n + j] -= sx0 * p;
-                    self.u[(i + 1) * n + j] += sx1 * p;
-                    self.v[i * n + j] -= sy0 * p;
-                    self.v[i * n + j + 1] += sy1 * p;
+            match condition {
+                BoundaryCondition::NoSlipWall => tangential[ghost] = -tangential[interior],
+                BoundaryCondition::FreeSlipWall => tangential[ghost] = tangential[interior],
+                BoundaryCondition::Inflow { velocity } => {
+                    let [vn, vt] = if x_edge {
+                        velocity
+                    } else {
+                        [velocity[1], velocity[0]]
+                    }
+                    .map(T::from_f32);
+                    normal[face] = vn;
+                    normal[ghost] = vn;
+                    tangential[ghost] = vt;
+                }
+                BoundaryCondition::Outflow => {
+                    if face != ghost {
+                        normal[ghost] = normal[interior];
+                    }
+                    tangential[ghost] = tangential[interior];
+                }
+                BoundaryCondition::Periodic => {
+                    normal[ghost] = normal[partner];
+                    tangential[ghost] = tangential[partner];
                 }
             }
         }
-    }
-
-    fn extrapolate(&mut self) {
-        let n = self.height;
-        for i in 0..self.width {
-            self.u[i * n] = self.u[i * n + 1];
-            self.u[i * n + self.height - 1] = self.u[i * n + self.height - 2];
-        }
 
-        for j in 0..self.height {
-            self.v[j] = self.v[n + j];
-            self.v[(self.width - 1) * n + j] = self.v[(self.width - 2) * n + 1];
+        let mut scalars = vec![&mut self.smoke, &mut self.temperature];
+        scalars.extend(self.dyes.iter_mut().map(|dye| &mut dye.values));
+        for values in scalars {
+            for &(condition, _, ghost, interior, partner) in &cells {
+                match condition {
+                    BoundaryCondition::Outflow => values[ghost] = values[interior],
+                    BoundaryCondition::Periodic => values[ghost] = values[partner],
+                    _ => {}
+                }
+            }
         }
     }
 
-    fn avg_u(&mut self, i: usize, j: usize) -> f32 {
-        let n = self.height;
-        let u = (self.u[i * n + j - 1]
-            + self.u[i * n + j]
-            + self.u[(i + 1) * n + j - 1]
-            + self.u[(i + 1) * n + j])
-            * 0.25;
-        return u;
+    fn avg_u(&self, i: usize, j: usize) -> T {
+        (self.u[(i, j - 1)] + self.u[(i, j)] + self.u[(i + 1, j - 1)] + self.u[(i + 1, j)])
+            * T::from_f64(0.25)
     }
 
-    fn avg_v(&mut self, i: usize, j: usize) -> f32 {
-        let n = self.height;
-        let v = (self.v[(i - 1) * n + j]
-            + self.v[i * n + j]
-            + self.v[(i - 1) * n + j + 1]
-            + self.v[i * n + j + 1])
-            * 0.25;
-        return v;
+    fn avg_v(&self, i: usize, j: usize) -> T {
+        (self.v[(i - 1, j)] + self.v[(i, j)] + self.v[(i - 1, j + 1)] + self.v[(i, j + 1)])
+            * T::from_f64(0.25)
     }
 
-    fn sample_field(&mut self, x: f32, y: f32, field_type: FieldType) -> f32 {
-        let n = self.height;
+    /// Grid samples and bilinear weights used to interpolate `values` at `(x, y)`.
+    /// Positions are wrapped around periodic edges and clamped to the domain elsewhere.
+    fn sample_stencil(&self, values: &Grid2D<T>, x: T, y: T) -> ([usize; 4], [T; 4]) {
         let h = self.spacing;
-        let h1 = 1.0 / h;
-        let h2 = 0.5 * h;
+        let x = if self.boundaries.periodic_x() {
+            h + rem_euclid(x - h, T::from_usize(self.width - 2) * h)
+        } else {
+            x.max(h).min(T::from_usize(self.width) * h)
+        };
+        let y = if self.boundaries.periodic_y() {
+            h + rem_euclid(y - h, T::from_usize(self.height - 2) * h)
+        } else {
+            y.max(h).min(T::from_usize(self.height) * h)
+        };
+        values.stencil(x, y, h)
+    }
 
-        let mut dx = 0.0;
-        let mut dy = 0.0;
+    /// Bilinearly interpolates `values` at `(x, y)`.
+    fn sample_values(&self, values: &Grid2D<T>, x: T, y: T) -> T {
+        let (cells, weights) = self.sample_stencil(values, x, y);
+        cells.iter().zip(weights).map(|(c, w)| w * values[*c]).sum()
+    }
 
-        let mut f;
+    fn velocity_at(&self, x: T, y: T) -> (T, T) {
+        (
+            self.sample_values(&self.u, x, y),
+            self.sample_values(&self.v, x, y),
+        )
+    }
 
-        match field_type {
-            FieldType::UField => {
-                f = &self.u;
-                dy = h2;
+    /// Whether the sample at `(i, j)` of a field at `location` is carried by the flow.
+    fn is_advected(&self, i: usize, j: usize, location: GridLocation) -> bool {
+        match location {
+            GridLocation::UFace => {
+                i > 0
+                    && j > 0
+                    && j < self.height - 1
+                    && !self.solids[(i, j)].is_zero()
+                    && !self.solids[(i - 1, j)].is_zero()
             }
-            FieldType::VField => {
-                f = &self.v;
-                dx = h2;
+            GridLocation::VFace => {
+                i > 0
+                    && j > 0
+                    && i < self.width - 1
+                    && !self.solids[(i, j)].is_zero()
+                    && !self.solids[(i, j - 1)].is_zero()
             }
-            FieldType::SField => {
-                f = &self.smoke;
-                dx = h2;
-                dy = h2;
+            GridLocation::CellCenter => {
+                i < self.width - 1 && j < self.height - 1 && !self.solids[(i, j)].is_zero()
             }
         }
+    }
 
-        let x0 = f32::min(((x - dx) * h1).floor(), (self.width - 1) as f32);
-        let tx = ((x - dx) - x0 * h) * h1;
-        let x1 = f32::min(x0 + 1.0, (self.width - 1) as f32);
+    /// World position of sample `(i, j)` of `values` and the velocity there.
+    fn sample_point(&self, values: &Grid2D<T>, i: usize, j: usize) -> (T, T, T, T) {
+        let (x, y) = values.position(i, j, self.spacing);
+        let (u, v) = match values.location() {
+            GridLocation::UFace => (self.u[(i, j)], self.avg_v(i, j)),
+            GridLocation::VFace => (self.avg_u(i, j), self.v[(i, j)]),
+            GridLocation::CellCenter => (
+                (self.u[(i, j)] + self.u[(i + 1, j)]) * T::from_f64(0.5),
+                (self.v[(i, j)] + self.v[(i, j + 1)]) * T::from_f64(0.5),
+            ),
+        };
+        (x, y, u, v)
+    }
 
-        let y0 = f32::min(((y - dy) * h1).floor(), (self.height - 1) as f32);
-        let ty = ((y - dy) - y0 * h) * h1;
-        let y1 = f32::min(y0 + 1.0, (self.height - 1) as f32);
+    /// Traces the characteristic through `(x, y)` back by `dt` with a Runge-Kutta scheme
+    /// of the given `order` (1 to 3), starting with velocity `(u, v)`.
+    fn backtrace(&self, x: T, y: T, u: T, v: T, dt: T, order: usize) -> (T, T) {
+        let c = T::from_f64;
+        match order {
+            1 => (x - dt * u, y - dt * v),
+            2 => {
+                let (u2, v2) = self.velocity_at(x - c(0.5) * dt * u, y - c(0.5) * dt * v);
+                (x - dt * u2, y - dt * v2)
+            }
+            _ => {
+                let (u2, v2) = self.velocity_at(x - c(0.5) * dt * u, y - c(0.5) * dt * v);
+                let (u3, v3) = self.velocity_at(x - c(0.75) * dt * u2, y - c(0.75) * dt * v2);
+                (
+                    x - dt * (c(2.0) * u + c(3.0) * u2 + c(4.0) * u3) / c(9.0),
+                    y - dt * (c(2.0) * v + c(3.0) * v2 + c(4.0) * v3) / c(9.0),
+                )
+            }
+        }
+    }
 
-        let sx = 1.0 - tx;
-        let sy = 1.0 - ty;
+    /// One semi-Lagrangian step of `values` with the current velocity field.
+    fn semi_lagrangian(&self, values: &Grid2D<T>, dt: T, order: usize) -> Grid2D<T> {
+        let mut result = values.clone();
 
-        let val = sx * sy * f[(x0 * n as f32 + y0) as usize]
-            + tx * sy * f[(x1 * n as f32 + y0) as usize]
-            + tx * ty * f[(x1 * n as f32 + y1) as usize]
-            + sx * ty * f[(x0 * n as f32 + y1) as usize];
+        for (i, j) in values.cells() {
+            if self.is_advected(i, j, values.location()) {
+                let (x, y, u, v) = self.sample_point(values, i, j);
+                let (x, y) = self.backtrace(x, y, u, v, dt, order);
+                result[(i, j)] = self.sample_values(values, x, y);
+            }
+        }
 
-        return val;
+        result
     }
 
-    fn advect_vel(&mut self, dt: f32) {
-        let mut new_u = self.u.clone();
-        let mut new_v = self.v.clone();
-
-        let n = self.height;
-        let h = self.spacing;
-        let h2 = 0.5 * h;
+    /// Clamps `corrected` to the range of the values the plain backtrace interpolated
+    /// from, which keeps the error-compensating schemes free of new extrema.
+    fn clamp_to_stencil(&self, values: &Grid2D<T>, corrected: &mut Grid2D<T>, dt: T, order: usize) {
+        for (i, j) in values.cells() {
+            if self.is_advected(i, j, values.location()) {
+                let (x, y, u, v) = self.sample_point(values, i, j);
+                let (x, y) = self.backtrace(x, y, u, v, dt, order);
+                let (cells, _) = self.sample_stencil(values, x, y);
+                let min = cells.iter().fold(T::infinity(), |m, c| m.min(values[*c]));
+                let max = cells
+                    .iter()
+                    .fold(T::neg_infinity(), |m, c| m.max(values[*c]));
+                corrected[(i, j)] = corrected[(i, j)].max(min).min(max);
+            }
+        }
+    }
 
-        for i in 1..self.width {
-            for j in 1..self.height {
-                if self.solids[i * n + j] != 0.0
-                    && self.solids[(i - 1) * n + j] != 0.0
-                    && j < (self.height - 1)
-                {
-                    let mut x = i as f32 * h;
-                    let mut y = j as f32 * h + h2;
-                    let mut u = self.u[i * n + j];
-                    let v = self.avg_v(i, j);
-                    x = x - dt * u;
-                    y = y - dt * v;
-                    u = self.sample_field(x, y, FieldType::UField);
-                    new_u[i * n + j] = u;
+    fn advect_field(&self, values: &Grid2D<T>, dt: T) -> Grid2D<T> {
+        match self.advection {
+            AdvectionScheme::SemiLagrangian => self.semi_lagrangian(values, dt, 1),
+            AdvectionScheme::Rk2 => self.semi_lagrangian(values, dt, 2),
+            AdvectionScheme::Rk3 => self.semi_lagrangian(values, dt, 3),
+            AdvectionScheme::MacCormack => {
+                let forward = self.semi_lagrangian(values, dt, 2);
+                let backward = self.semi_lagrangian(&forward, -dt, 2);
+                let mut result = forward;
+                for ((r, v), b) in result.iter_mut().zip(values.iter()).zip(backward.iter()) {
+                    *r += T::from_f64(0.5) * (*v - *b);
                 }
-
-                if self.solids[i * n + j] != 0.0
-                    && self.solids[i * n + j - 1] != 0.0
-                    && i < (self.width - 1)
-                {
-                    let mut x = i as f32 * h + h2;
-                    let mut y = j as f32 * h;
-                    let mut u = self.avg_u(i, j);
-                    let mut v = self.v[i * n + j];
-                    x = x - dt * u;
-                    y = y - dt * v;
-                    v = self.sample_field(x, y, FieldType::VField);
-                    new_v[i * n + j] = v;
+                self.clamp_to_stencil(values, &mut result, dt, 2);
+                result
+            }
+            AdvectionScheme::Bfecc => {
+                let forward = self.semi_lagrangian(values, dt, 2);
+                let backward = self.semi_lagrangian(&forward, -dt, 2);
+                let mut compensated = values.clone();
+                for (c, b) in compensated.iter_mut().zip(backward.iter()) {
+                    *c += T::from_f64(0.5) * (*c - *b);
                 }
+                let mut result = self.semi_lagrangian(&compensated, dt, 2);
+                self.clamp_to_stencil(values, &mut result, dt, 2);
+                result
             }
         }
+    }
+
+    fn advect_vel(&mut self, dt: T) {
+        let new_u = self.advect_field(&self.u, dt);
+        let new_v = self.advect_field(&self.v, dt);
 
         self.u = new_u;
         self.v = new_v;
     }
 
-    fn advect_smoke(&mut self, dt: f32) {
-        let mut new_smoke = self.smoke.clone();
+    fn advect_smoke(&mut self, dt: T) {
+        self.smoke = self.advect_field(&self.smoke, dt);
+    }
 
-        let n = self.height;
-        let h = self.spacing;
-        let h2 = 0.5 * h;
-
-        for i in 0..self.width - 1 {
-            for j in 0..self.height - 1 {
-                if self.solids[i * n + j] != 0.0 {
-                    let u = (self.u[i * n + j] + self.u[(i + 1) * n + j]) * 0.5;
-                    let v = (self.v[i * n + j] + self.v[i * n + j + 1]) * 0.5;
-                    let x = i as f32 * h + h2 - dt * u;
-                    let y = j as f32 * h + h2 - dt * v;
-                    new_smoke[i * n + j] = self.sample_field(x, y, FieldType::SField);
-                }
+    fn advect_temperature(&mut self, dt: T) {
+        self.temperature = self.advect_field(&self.temperature, dt);
+    }
+
+    fn advect_dyes(&mut self, dt: T) {
+        for k in 0..self.dyes.len() {
+            let mut values = self.advect_field(&self.dyes[k].values, dt);
+            let decay = (-T::from_f32(self.dyes[k].dissipation) * dt).exp();
+            values.iter_mut().for_each(|value| *value *= decay);
+            self.dyes[k].values = values;
+        }
+    }
+}
+
+/// `x` wrapped into `[0, period)`, like `f32::rem_euclid`.
+fn rem_euclid<T: Real>(x: T, period: T) -> T {
+    let r = x % period;
+    if r < T::zero() {
+        r + period.abs()
+    } else {
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NacaAirfoil, PressureSolverConfig, ResidualNorm, SceneUnits};
+
+    /// Scene of `width` x `height` cells with nothing in it, in cell units.
+    fn empty_scene(width: usize, height: usize, boundaries: Boundaries) -> EulerScene {
+        EulerScene {
+            width,
+            height,
+            spacing: 1.0 / width as f32,
+            units: SceneUnits::Cells,
+            obstacles: Vec::new(),
+            inflows: Vec::new(),
+            smoke_sources: Vec::new(),
+            boundaries,
+            ..EulerScene::default()
+        }
+    }
+
+    fn all_edges(condition: BoundaryCondition) -> Boundaries {
+        Boundaries {
+            left: condition,
+            right: condition,
+            bottom: condition,
+            top: condition,
+        }
+    }
+
+    /// Simulation over `boundaries` with distinct values on every face and cell, after
+    /// its ghost cells were filled.
+    fn scrambled(boundaries: Boundaries) -> EulerSimulation<f64> {
+        let mut simulation = EulerSimulation::from_scene(&empty_scene(6, 5, boundaries));
+        for (i, j) in simulation.smoke.cells() {
+            let c = (7 * i + 3 * j) as f64;
+            simulation.u[(i, j)] = c.sin();
+            simulation.v[(i, j)] = c.cos();
+            simulation.smoke[(i, j)] = (0.5 * c).sin();
+        }
+        simulation.apply_boundaries();
+        simulation
+    }
+
+    #[test]
+    fn periodic_edges_copy_the_opposite_interior() {
+        let simulation = scrambled(all_edges(BoundaryCondition::Periodic));
+        let (w, h) = (simulation.width, simulation.height);
+        for field in [&simulation.u, &simulation.v, &simulation.smoke] {
+            // corners included, they wrap along both axes
+            for j in 0..h {
+                assert_eq!(field[(0, j)], field[(w - 2, j)]);
+                assert_eq!(field[(w - 1, j)], field[(1, j)]);
+            }
+            for i in 0..w {
+                assert_eq!(field[(i, 0)], field[(i, h - 2)]);
+                assert_eq!(field[(i, h - 1)], field[(i, 1)]);
+            }
+        }
+    }
+
+    #[test]
+    fn outflow_edges_have_zero_gradient() {
+        let before = scrambled(all_edges(BoundaryCondition::NoSlipWall));
+        let simulation = scrambled(all_edges(BoundaryCondition::Outflow));
+        let (w, h) = (simulation.width, simulation.height);
+        let (u, v, smoke) = (&simulation.u, &simulation.v, &simulation.smoke);
+        for j in 1..h - 1 {
+            for field in [u, v, smoke] {
+                assert_eq!(field[(0, j)], field[(1, j)]);
+            }
+            for field in [v, smoke] {
+                assert_eq!(field[(w - 1, j)], field[(w - 2, j)]);
+            }
+            // the outermost face is the open edge itself and keeps its velocity
+            assert_eq!(u[(w - 1, j)], before.u[(w - 1, j)]);
+        }
+        for i in 1..w - 1 {
+            for field in [u, v, smoke] {
+                assert_eq!(field[(i, 0)], field[(i, 1)]);
+            }
+            for field in [u, smoke] {
+                assert_eq!(field[(i, h - 1)], field[(i, h - 2)]);
+            }
+            assert_eq!(v[(i, h - 1)], before.v[(i, h - 1)]);
+        }
+    }
+
+    #[test]
+    fn walls_mirror_or_copy_the_tangential_velocity() {
+        let simulation = scrambled(Boundaries {
+            left: BoundaryCondition::NoSlipWall,
+            right: BoundaryCondition::NoSlipWall,
+            bottom: BoundaryCondition::FreeSlipWall,
+            top: BoundaryCondition::FreeSlipWall,
+        });
+        let (w, h) = (simulation.width, simulation.height);
+        for j in 1..h - 1 {
+            assert_eq!(simulation.v[(0, j)], -simulation.v[(1, j)]);
+            assert_eq!(simulation.v[(w - 1, j)], -simulation.v[(w - 2, j)]);
+        }
+        for i in 1..w - 1 {
+            assert_eq!(simulation.u[(i, 0)], simulation.u[(i, 1)]);
+            assert_eq!(simulation.u[(i, h - 1)], simulation.u[(i, h - 2)]);
+        }
+    }
+
+    /// Gaussian bump of width `sigma` centred on `(x, y)`, in world units.
+    fn bump(x: f64, y: f64, sigma: f64) -> impl Fn(f64, f64) -> f64 {
+        move |px, py| (-((px - x).powi(2) + (py - y).powi(2)) / (2.0 * sigma * sigma)).exp()
+    }
+
+    /// Fills the smoke of the interior cells from `f` at their centres.
+    fn fill_smoke(simulation: &mut EulerSimulation<f64>, f: impl Fn(f64, f64) -> f64) {
+        let h = simulation.spacing;
+        for (i, j) in simulation.smoke.interior() {
+            let (x, y) = simulation.smoke.position(i, j, h);
+            simulation.smoke[(i, j)] = f(x, y);
+        }
+    }
+
+    /// Mean absolute difference of the smoke from `f` over the interior cells.
+    fn smoke_error(simulation: &EulerSimulation<f64>, f: impl Fn(f64, f64) -> f64) -> f64 {
+        let h = simulation.spacing;
+        let cells: Vec<_> = simulation.smoke.interior().collect();
+        let total: f64 = cells
+            .iter()
+            .map(|&(i, j)| {
+                let (x, y) = simulation.smoke.position(i, j, h);
+                (simulation.smoke[(i, j)] - f(x, y)).abs()
+            })
+            .sum();
+        total / cells.len() as f64
+    }
+
+    const SCHEMES: [AdvectionScheme; 5] = [
+        AdvectionScheme::SemiLagrangian,
+        AdvectionScheme::Rk2,
+        AdvectionScheme::Rk3,
+        AdvectionScheme::MacCormack,
+        AdvectionScheme::Bfecc,
+    ];
+
+    /// Advects a bump twice across a periodic box with `scheme`, back to where it
+    /// started, and returns the error and the smoke range seen during the run.
+    fn advect_bump_around(scheme: AdvectionScheme) -> (f64, [f64; 2], [f64; 2]) {
+        let n = 32;
+        let scene = EulerScene {
+            initial_velocity: [1.0, 0.5],
+            advection: scheme,
+            ..empty_scene(n, n, all_edges(BoundaryCondition::Periodic))
+        };
+        let mut simulation: EulerSimulation<f64> = EulerSimulation::from_scene(&scene);
+        let h = simulation.spacing;
+        let exact = bump(0.5 + h, 0.5 + h, 0.12);
+        fill_smoke(&mut simulation, &exact);
+        simulation.apply_boundaries();
+
+        let range = |simulation: &EulerSimulation<f64>| {
+            let smoke = &simulation.smoke;
+            smoke
+                .interior()
+                .fold([f64::INFINITY, f64::NEG_INFINITY], |[lo, hi], c| {
+                    [lo.min(smoke[c]), hi.max(smoke[c])]
+                })
+        };
+        let initial = range(&simulation);
+        let mut seen = initial;
+
+        let dt = 0.4 * h;
+        for _ in 0..(2.0 / dt).round() as usize {
+            simulation.advect_smoke(dt);
+            simulation.apply_boundaries();
+            let [lo, hi] = range(&simulation);
+            seen = [seen[0].min(lo), seen[1].max(hi)];
+        }
+        (smoke_error(&simulation, &exact), initial, seen)
+    }
+
+    #[test]
+    fn error_compensation_beats_semi_lagrangian_in_uniform_flow() {
+        let (reference, _, _) = advect_bump_around(AdvectionScheme::SemiLagrangian);
+        // the backtrace is exact in a uniform flow whatever its order
+        for scheme in [AdvectionScheme::Rk2, AdvectionScheme::Rk3] {
+            let (error, _, _) = advect_bump_around(scheme);
+            assert!((error - reference).abs() < 1e-12, "{:?}: {}", scheme, error);
+        }
+        for scheme in [AdvectionScheme::MacCormack, AdvectionScheme::Bfecc] {
+            let (error, [lo, hi], [min, max]) = advect_bump_around(scheme);
+            assert!(
+                error < 0.4 * reference,
+                "{:?}: {} against {}",
+                scheme,
+                error,
+                reference
+            );
+            assert!(
+                min >= lo - 1e-12 && max <= hi + 1e-12,
+                "{:?}: {:?} outside of {:?}",
+                scheme,
+                [min, max],
+                [lo, hi]
+            );
+        }
+    }
+
+    #[test]
+    fn higher_order_backtraces_follow_a_rotation() {
+        let error = |scheme| {
+            let n = 64;
+            let scene = EulerScene {
+                advection: scheme,
+                ..empty_scene(n, n, all_edges(BoundaryCondition::FreeSlipWall))
+            };
+            let mut simulation: EulerSimulation<f64> = EulerSimulation::from_scene(&scene);
+            let h = simulation.spacing;
+            let (cx, cy) = (0.5 + h, 0.5 + h);
+            let omega = std::f64::consts::TAU;
+            for (i, j) in simulation.u.cells() {
+                let (_, y) = simulation.u.position(i, j, h);
+                simulation.u[(i, j)] = -omega * (y - cy);
+            }
+            for (i, j) in simulation.v.cells() {
+                let (x, _) = simulation.v.position(i, j, h);
+                simulation.v[(i, j)] = omega * (x - cx);
             }
+            let exact = bump(cx + 0.25, cy, 0.08);
+            fill_smoke(&mut simulation, &exact);
+
+            // one full turn in large steps, moving the bump about four cells each
+            let steps = 40;
+            for _ in 0..steps {
+                simulation.advect_smoke(1.0 / steps as f64);
+            }
+            smoke_error(&simulation, &exact)
+        };
+
+        let reference = error(AdvectionScheme::SemiLagrangian);
+        for scheme in SCHEMES.into_iter().skip(1) {
+            let error = error(scheme);
+            assert!(
+                error < 0.4 * reference,
+                "{:?}: {} against {}",
+                scheme,
+                error,
+                reference
+            );
         }
+    }
 
-        self.smoke = new_smoke;
+    #[test]
+    fn straight_edges_through_cell_centres_leave_half_the_cell() {
+        let mut simulation: EulerSimulation<f64> = EulerSimulation::from_scene(&empty_scene(
+            16,
+            16,
+            all_edges(BoundaryCondition::FreeSlipWall),
+        ));
+        // x from 4 to 10.5, y from 4 to 12, in cell units
+        simulation.add_obstacle(&Obstacle::Rectangle {
+            center: [7.25, 8.0],
+            size: [6.5, 8.0],
+            angle: 0.0,
+        });
+        for (i, j) in simulation.solids.interior() {
+            let expected = match (i, j) {
+                (4..=9, 4..=11) => 0.0,
+                (10, 4..=11) => 0.5,
+                _ => 1.0,
+            };
+            assert_eq!(simulation.solids[(i, j)], expected, "cell ({}, {})", i, j);
+        }
+    }
+
+    #[test]
+    fn uniform_pressure_exerts_no_net_force() {
+        let mut simulation: EulerSimulation<f64> = EulerSimulation::from_scene(&empty_scene(
+            32,
+            32,
+            all_edges(BoundaryCondition::FreeSlipWall),
+        ));
+        simulation.add_obstacle(&Obstacle::Circle {
+            center: [12.3, 17.6],
+            radius: 5.2,
+        });
+        simulation.pressure.fill(250.0);
+
+        let forces = simulation.compute_forces(&ForceReference {
+            speed: 1.0,
+            length: 1.0,
+        });
+        assert_eq!(forces.len(), 1);
+        let [x, y] = forces[0].pressure_force;
+        assert!(x.abs() < 1e-3 && y.abs() < 1e-3, "force ({}, {})", x, y);
+    }
+
+    #[test]
+    fn pressure_gradient_pushes_a_box_down_the_slope() {
+        let mut simulation: EulerSimulation<f64> = EulerSimulation::from_scene(&empty_scene(
+            32,
+            32,
+            all_edges(BoundaryCondition::FreeSlipWall),
+        ));
+        // 12 x 6 cells, from (8, 10) to (20, 16)
+        simulation.add_obstacle(&Obstacle::Rectangle {
+            center: [14.0, 13.0],
+            size: [12.0, 6.0],
+            angle: 0.0,
+        });
+        let h = simulation.spacing;
+        let gradient = [300.0, -200.0];
+        for (i, j) in simulation.pressure.cells() {
+            let (x, y) = simulation.pressure.position(i, j, h);
+            simulation.pressure[(i, j)] = gradient[0] * x + gradient[1] * y;
+        }
+
+        let forces = simulation.compute_forces(&ForceReference {
+            speed: 1.0,
+            length: 1.0,
+        });
+        // minus the gradient times the area, the pressure being read at the fluid cell
+        // centres half a cell off each face: the box spans 13 x 6 of them along x and
+        // 12 x 7 along y
+        let expected = [
+            -gradient[0] * 13.0 * 6.0 * h * h,
+            -gradient[1] * 12.0 * 7.0 * h * h,
+        ];
+        for (force, expected) in forces[0].pressure_force.into_iter().zip(expected) {
+            assert!(
+                (force as f64 - expected).abs() < 1e-4 * expected.abs(),
+                "force {} against {}",
+                force,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn symmetric_airfoil_at_zero_incidence_has_no_lift() {
+        let airfoil = NacaAirfoil::naca("0012", [0.3, 0.5], 0.4, 0.0).unwrap();
+        let scene = EulerScene {
+            time_stepping: TimeStepping::Fixed {
+                dt: 0.01,
+                steps_per_frame: 1,
+            },
+            pressure_solver: PressureSolverConfig::ConjugateGradient {
+                convergence: Convergence::tolerance(500, 1e-8, ResidualNorm::Max),
+            },
+            force_reference: Some(ForceReference {
+                speed: 10.0,
+                // 16 cells of 1.25
+                length: 20.0,
+            }),
+            ..EulerScene::airfoil_tunnel(airfoil).with_resolution(80, 40)
+        };
+        let mut simulation: EulerSimulation<f64> = EulerSimulation::from_scene(&scene);
+        for _ in 0..50 {
+            simulation.step(0.01);
+            let forces = simulation.forces()[0];
+            assert!(forces.drag_coefficient > 0.0);
+            assert!(
+                forces.lift_coefficient.abs() < 1e-4 * forces.drag_coefficient,
+                "lift {} with drag {}",
+                forces.lift_coefficient,
+                forces.drag_coefficient
+            );
+        }
+    }
+
+    #[test]
+    fn scene_and_runtime_probes_record_the_cell_they_sit_on() {
+        let h = 0.1;
+        // centre of the fourth interior column and sixth interior row, cell (4, 6)
+        let position = [3.5 * h, 5.5 * h];
+        let probe = |name: &str| ProbeDescriptor {
+            name: name.to_string(),
+            position,
+            fields: vec![ProbeField::Smoke, ProbeField::Pressure],
+        };
+        let scene = EulerScene {
+            spacing: h,
+            units: SceneUnits::World,
+            probes: vec![probe("scene")],
+            ..empty_scene(16, 8, all_edges(BoundaryCondition::FreeSlipWall))
+        };
+        let mut simulation: EulerSimulation<f64> = EulerSimulation::from_scene(&scene);
+        simulation.add_probe(&probe("runtime"));
+        assert_eq!(
+            simulation.probes()[1].position,
+            simulation.probes()[0].position
+        );
+
+        for (i, j) in simulation.smoke.cells() {
+            simulation.smoke[(i, j)] = (100 * i + j) as f64;
+            simulation.pressure[(i, j)] = -((100 * i + j) as f64);
+        }
+        simulation.record_probes();
+
+        for probe in simulation.probes() {
+            assert_eq!(probe.series(&ProbeField::Smoke), Some(&[406.0][..]));
+            assert_eq!(probe.series(&ProbeField::Pressure), Some(&[-406.0][..]));
+        }
+    }
+
+    #[test]
+    fn diagnostics_measure_speed_and_divergence_at_the_cell_centres() {
+        let mut simulation: EulerSimulation<f64> = EulerSimulation::from_scene(&empty_scene(
+            8,
+            6,
+            all_edges(BoundaryCondition::FreeSlipWall),
+        ));
+        let (w, h) = (simulation.width, simulation.spacing);
+        // uniform expansion along x, the fastest cell being the last interior column
+        for (i, j) in simulation.u.cells() {
+            simulation.u[(i, j)] = 0.5 * i as f64;
+        }
+
+        let diagnostics = simulation.diagnostics();
+        let expected = 0.5 / h;
+        assert!((diagnostics.max_divergence as f64 - expected).abs() < 1e-4 * expected);
+        assert!((diagnostics.mean_divergence as f64 - expected).abs() < 1e-4 * expected);
+        let fastest = 0.5 * (w as f64 - 1.5);
+        assert!((diagnostics.max_velocity as f64 - fastest).abs() < 1e-6);
+    }
+
+    /// Buffer collecting the output of a `tracing` subscriber.
+    #[derive(Clone, Default)]
+    struct Captured(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn diagnostics_are_logged_every_interval() {
+        let scene = EulerScene {
+            diagnostics_interval: Some(3),
+            ..empty_scene(8, 8, all_edges(BoundaryCondition::FreeSlipWall))
+        };
+        let mut simulation: EulerSimulation<f64> = EulerSimulation::from_scene(&scene);
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let logged = || {
+            let output = captured.0.lock().unwrap();
+            String::from_utf8_lossy(&output).matches("div max").count()
+        };
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..10 {
+                simulation.step(0.01);
+            }
+            // steps 3, 6 and 9
+            assert_eq!(logged(), 3);
+
+            simulation.set_diagnostics_logging(None);
+            for _ in 0..10 {
+                simulation.step(0.01);
+            }
+            assert_eq!(logged(), 3);
+        });
+        assert_eq!(simulation.steps(), 20);
+    }
+
+    /// Sum of the squared interior `u` faces, proportional to the kinetic energy of a
+    /// flow along x.
+    fn u_energy(simulation: &EulerSimulation<f64>) -> f64 {
+        simulation
+            .u
+            .interior()
+            .map(|c| simulation.u[c].powi(2))
+            .sum()
+    }
+
+    #[test]
+    fn viscosity_damps_a_shear_wave_at_the_analytic_rate() {
+        let n = 32;
+        let viscosity = 0.01;
+        let scene = EulerScene {
+            viscosity: viscosity as f32,
+            ..empty_scene(n, n, all_edges(BoundaryCondition::Periodic))
+        };
+        let mut simulation: EulerSimulation<f64> = EulerSimulation::from_scene(&scene);
+        let h = simulation.spacing;
+        let k = std::f64::consts::TAU;
+        let wave = |j: usize| (k * (j as f64 - 0.5) * h).sin();
+        for (i, j) in simulation.u.cells() {
+            simulation.u[(i, j)] = wave(j);
+        }
+        simulation.apply_boundaries();
+
+        let dt = 0.01;
+        for _ in 0..100 {
+            simulation.diffuse_velocity(dt);
+            simulation.apply_boundaries();
+        }
+
+        // amplitude of the wave left in the field, by projection
+        let (mut dot, mut norm) = (0.0, 0.0);
+        for (i, j) in simulation.u.interior() {
+            dot += simulation.u[(i, j)] * wave(j);
+            norm += wave(j) * wave(j);
+        }
+        let amplitude = dot / norm;
+        let expected = (-viscosity * k * k * 100.0 * dt).exp();
+        assert!(
+            (amplitude - expected).abs() < 0.01 * expected,
+            "amplitude {} against {}",
+            amplitude,
+            expected
+        );
+    }
+
+    #[test]
+    fn viscosity_dissipates_energy_at_no_slip_walls() {
+        let scene = EulerScene {
+            viscosity: 0.01,
+            initial_velocity: [1.0, 0.0],
+            boundaries: Boundaries {
+                bottom: BoundaryCondition::NoSlipWall,
+                top: BoundaryCondition::NoSlipWall,
+                ..all_edges(BoundaryCondition::Periodic)
+            },
+            ..empty_scene(16, 16, Boundaries::default())
+        };
+        let mut simulation: EulerSimulation<f64> = EulerSimulation::from_scene(&scene);
+        let (w, h) = (simulation.width, simulation.height);
+
+        let mut energy = u_energy(&simulation);
+        for _ in 0..50 {
+            simulation.diffuse_velocity(0.01);
+            simulation.apply_boundaries();
+            let next = u_energy(&simulation);
+            assert!(next < energy, "energy rose from {} to {}", energy, next);
+            energy = next;
+
+            for i in 0..w {
+                assert_eq!(simulation.v[(i, 1)], 0.0);
+                assert_eq!(simulation.v[(i, h - 1)], 0.0);
+            }
+        }
+        // slowed down most along the walls
+        let (wall, centre) = (simulation.u[(w / 2, 1)], simulation.u[(w / 2, h / 2)]);
+        assert!(
+            wall < 0.5 * centre,
+            "wall {} against centre {}",
+            wall,
+            centre
+        );
     }
 }
@@ -0,0 +1,130 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    parse_scene, parse_scene_file, Convergence, Obstacle, PressureSolverConfig, Region, SceneError,
+};
+
+/// Quantity drawn on the liquid cells of the instance grid. Air cells are black and solid
+/// cells grey whatever the field.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FLIPField {
+    #[default]
+    Speed,
+    Pressure,
+    /// Particles per cell over the number seeded in each cell, showing where the
+    /// particles bunch up or thin out.
+    ParticleDensity,
+}
+
+/// Oscillating acceleration `acceleration * sin(2 pi frequency t)` added to gravity, the
+/// inertial force felt by liquid in a tank shaken the opposite way.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TankExcitation {
+    pub acceleration: [f32; 2],
+    /// In hertz.
+    pub frequency: f32,
+}
+
+/// Description of a `FLIPSimulation` run: a closed tank of `width` x `height` cells,
+/// the liquid initially in it and the solids inside. Regions are boxes in fractions of
+/// the tank width and height holding the cells whose centre lies inside; shapes are in
+/// the same fractions, with their lengths in fractions of the height.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FLIPScene {
+    pub width: usize,
+    pub height: usize,
+    /// Cell size in metres.
+    pub spacing: f32,
+    pub density: f32,
+    pub gravity: [f32; 2],
+    pub excitation: Option<TankExcitation>,
+    /// Share of FLIP in the particle velocity update: 0 is pure PIC, stable but viscous,
+    /// and 1 pure FLIP, lively but noisy.
+    pub flip_ratio: f32,
+    /// Particles seeded along each axis of a liquid cell.
+    pub particles_per_axis: usize,
+    /// Passes pushing overlapping particles apart every step, zero to disable.
+    pub separation_iterations: usize,
+    /// Cells initially filled with liquid, at rest.
+    pub liquid: Vec<Region>,
+    pub obstacles: Vec<Region>,
+    /// Analytic obstacles, solid where they cover the cell centre.
+    pub shapes: Vec<Obstacle>,
+    pub pressure_solver: PressureSolverConfig,
+    /// Fraction of a cell the fastest particle may travel in one step.
+    pub cfl: f32,
+    /// Simulated time advanced every frame, the frame duration when unset.
+    pub frame_time: Option<f32>,
+    /// Time steps allowed per frame.
+    pub max_substeps: usize,
+    pub display: FLIPField,
+}
+
+impl Default for FLIPScene {
+    /// The dam break on a 100 x 50 grid.
+    fn default() -> Self {
+        Self::dam_break(50)
+    }
+}
+
+impl FLIPScene {
+    /// Collapse of a 1 m square water column against the left wall of a 4 m x 2 m tank,
+    /// `cells` cells high.
+    pub fn dam_break(cells: usize) -> Self {
+        Self {
+            width: 2 * cells,
+            height: cells,
+            spacing: 2.0 / cells as f32,
+            density: 1000.0,
+            gravity: [0.0, -9.81],
+            excitation: None,
+            flip_ratio: 0.95,
+            particles_per_axis: 2,
+            separation_iterations: 2,
+            liquid: vec![Region::new(0.0, 0.25, 0.0, 0.5)],
+            obstacles: Vec::new(),
+            shapes: Vec::new(),
+            pressure_solver: PressureSolverConfig::GaussSeidel {
+                over_relaxation: 1.9,
+                convergence: Convergence::fixed(100),
+            },
+            cfl: 0.8,
+            frame_time: None,
+            max_substeps: 20,
+            display: FLIPField::Speed,
+        }
+    }
+
+    /// A 1 m wide tank `cells` cells across, half as high and filled to half its height,
+    /// shaken sideways at the frequency of its first sloshing mode.
+    pub fn sloshing_tank(cells: usize) -> Self {
+        let (length, depth, g) = (1.0f32, 0.25f32, 9.81f32);
+        // linear wave theory for the longest standing wave, `k = pi / length`
+        let k = std::f32::consts::PI / length;
+        let omega = (g * k * (k * depth).tanh()).sqrt();
+
+        Self {
+            width: cells,
+            height: cells / 2,
+            spacing: length / cells as f32,
+            excitation: Some(TankExcitation {
+                acceleration: [0.5, 0.0],
+                frequency: omega / std::f32::consts::TAU,
+            }),
+            liquid: vec![Region::new(0.0, 1.0, 0.0, 0.5)],
+            ..Self::dam_break(cells)
+        }
+    }
+
+    /// Loads a scene, picking the format from the file extension (`toml`, `json` or `ron`).
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        parse_scene_file(path.as_ref())
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, SceneError> {
+        parse_scene(source, "toml")
+    }
+}
@@ -0,0 +1,819 @@
+use std::time::{Duration, Instant};
+
+use glam::{Vec2, Vec3};
+use tracing::warn;
+
+use crate::{
+    scalar_color, FLIPField, FLIPScene, Grid2D, GridLocation, Instance, Obstacle, PressureProblem,
+    PressureSolver, PressureSolverConfig, Real, Region, SolverStats, TankExcitation, Vector2,
+};
+
+/// Particle radius over the seeding spacing, a little under half so that freshly
+/// seeded particles do not overlap. Keeps particles apart and off the walls.
+const PARTICLE_RADIUS: f64 = 0.45;
+
+/// What fills a cell of a `FLIPSimulation` during a step.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LiquidCell {
+    Solid,
+    #[default]
+    Air,
+    /// Holds at least one particle.
+    Fluid,
+}
+
+/// Free surface liquid simulated with particles carrying the velocity and the staggered
+/// grid of `EulerSimulation` enforcing incompressibility (PIC/FLIP). Every step the
+/// particles move, their velocities are splatted on the `u` and `v` faces, gravity is
+/// added there, the cells holding particles are projected with air cells at zero
+/// pressure, and the particles pick the corrected velocities back up.
+///
+/// The grid has the one cell solid border of `EulerSimulation`; positions are in metres
+/// from its outer corner.
+pub struct FLIPSimulation<T: Real = f32> {
+    pub density: T,
+    pub width: usize,
+    pub height: usize,
+    pub instances: Vec<Instance>,
+
+    u: Grid2D<T>,
+    v: Grid2D<T>,
+    /// Face velocities before the projection, for the FLIP update.
+    previous_u: Grid2D<T>,
+    previous_v: Grid2D<T>,
+    pressure: Grid2D<T>,
+    /// 1 for open cells, 0 for solid, as in `EulerSimulation`.
+    solids: Grid2D<T>,
+    cells: Grid2D<LiquidCell>,
+    air: Grid2D<bool>,
+    positions: Vec<Vector2<T>>,
+    velocities: Vec<Vector2<T>>,
+    spacing: T,
+    gravity: Vector2<T>,
+    excitation: Option<TankExcitation>,
+    flip_ratio: T,
+    particles_per_cell: usize,
+    /// In metres.
+    particle_radius: T,
+    separation_iterations: usize,
+    pressure_solver: Box<dyn PressureSolver<T>>,
+    solver_stats: SolverStats,
+    cfl: T,
+    frame_time: Option<T>,
+    max_substeps: usize,
+    display: FLIPField,
+    time: T,
+    substeps: usize,
+    steps: usize,
+}
+
+impl<T: Real> FLIPSimulation<T> {
+    pub fn new(density: T, width: usize, height: usize, spacing: T) -> Self {
+        let displacement = Vec3::new(1.0, 1.0, 0.0);
+        let width = width + 2;
+        let height = height + 2;
+
+        let instances = (0..width)
+            .flat_map(move |x| {
+                (0..height).map(move |y| Instance {
+                    position: Vec3::new(x as f32, y as f32, 0.0) - displacement,
+                    color: [0.0, 0.0, 0.0],
+                })
+            })
+            .collect();
+
+        let u = Grid2D::new(width, height, GridLocation::UFace, T::zero());
+        let v = Grid2D::new(width, height, GridLocation::VFace, T::zero());
+        let cell = |value| Grid2D::new(width, height, GridLocation::CellCenter, value);
+        let mut solids = cell(T::one());
+        for (i, j) in solids.cells() {
+            if i == 0 || j == 0 || i == width - 1 || j == height - 1 {
+                solids[(i, j)] = T::zero();
+            }
+        }
+
+        let mut simulation = Self {
+            density,
+            width,
+            height,
+            instances,
+            previous_u: u.clone(),
+            previous_v: v.clone(),
+            u,
+            v,
+            pressure: cell(T::zero()),
+            solids,
+            cells: Grid2D::new(width, height, GridLocation::CellCenter, LiquidCell::Air),
+            air: Grid2D::new(width, height, GridLocation::CellCenter, true),
+            positions: Vec::new(),
+            velocities: Vec::new(),
+            spacing,
+            gravity: Vector2::new(T::zero(), T::from_f64(-9.81)),
+            excitation: None,
+            flip_ratio: T::from_f64(0.95),
+            particles_per_cell: 4,
+            particle_radius: T::from_f64(PARTICLE_RADIUS * 0.5) * spacing,
+            separation_iterations: 2,
+            pressure_solver: PressureSolverConfig::default().build(),
+            solver_stats: SolverStats::default(),
+            cfl: T::from_f64(0.8),
+            frame_time: None,
+            max_substeps: 20,
+            display: FLIPField::default(),
+            time: T::zero(),
+            substeps: 0,
+            steps: 0,
+        };
+        simulation.mark_cells();
+        simulation
+    }
+
+    pub fn from_scene(scene: &FLIPScene) -> Self {
+        let mut simulation = Self::new(
+            T::from_f32(scene.density),
+            scene.width,
+            scene.height,
+            T::from_f32(scene.spacing),
+        );
+        simulation.apply_scene(scene);
+        simulation
+    }
+
+    /// Empties the tank, lays out the solids of `scene` and fills its liquid regions with
+    /// particles at rest. The grid keeps its size.
+    pub fn apply_scene(&mut self, scene: &FLIPScene) {
+        let (w, h) = (self.width, self.height);
+        let (scale_x, scale_y) = ((w - 2) as f32, (h - 2) as f32);
+        // centre of interior cell (i, j) in fractions of the tank
+        let fraction =
+            |i: usize, j: usize| ((i as f32 - 0.5) / scale_x, (j as f32 - 0.5) / scale_y);
+        let inside = |region: &Region, (x, y): (f32, f32)| {
+            region.x[0] <= x && x < region.x[1] && region.y[0] <= y && y < region.y[1]
+        };
+
+        for (i, j) in self.solids.cells() {
+            let border = i == 0 || j == 0 || i == w - 1 || j == h - 1;
+            let obstacle = scene.obstacles.iter().any(|o| inside(o, fraction(i, j)));
+            self.solids[(i, j)] = if border || obstacle {
+                T::zero()
+            } else {
+                T::one()
+            };
+        }
+        for shape in &scene.shapes {
+            let shape = shape.mapped(|[x, y]| [x * scale_x + 1.0, y * scale_y + 1.0], scale_y);
+            self.add_obstacle(&shape);
+        }
+
+        self.density = T::from_f32(scene.density);
+        self.spacing = T::from_f32(scene.spacing);
+        self.gravity = Vector2::new(T::from_f32(scene.gravity[0]), T::from_f32(scene.gravity[1]));
+        self.excitation = scene.excitation;
+        self.flip_ratio = T::from_f32(scene.flip_ratio.clamp(0.0, 1.0));
+        self.separation_iterations = scene.separation_iterations;
+        self.pressure_solver = scene.pressure_solver.build();
+        self.cfl = T::from_f32(scene.cfl);
+        self.frame_time = scene.frame_time.map(T::from_f32);
+        self.max_substeps = scene.max_substeps;
+        self.display = scene.display;
+        self.time = T::zero();
+        self.steps = 0;
+
+        let n = scene.particles_per_axis.max(1);
+        self.particles_per_cell = n * n;
+        self.particle_radius = T::from_f64(PARTICLE_RADIUS) * self.spacing / T::from_usize(n);
+        self.positions.clear();
+        self.velocities.clear();
+        for (i, j) in self.solids.interior() {
+            let liquid = scene.liquid.iter().any(|r| inside(r, fraction(i, j)));
+            if !liquid || self.solids[(i, j)].is_zero() {
+                continue;
+            }
+            for a in 0..n {
+                for b in 0..n {
+                    let x =
+                        T::from_usize(i) + (T::from_usize(a) + T::from_f64(0.5)) / T::from_usize(n);
+                    let y =
+                        T::from_usize(j) + (T::from_usize(b) + T::from_f64(0.5)) / T::from_usize(n);
+                    self.positions.push(Vector2::new(x, y) * self.spacing);
+                    self.velocities.push(Vector2::zero());
+                }
+            }
+        }
+
+        self.u.fill(T::zero());
+        self.v.fill(T::zero());
+        self.pressure.fill(T::zero());
+        self.mark_cells();
+        self.update_instances();
+    }
+
+    /// Makes the interior cells whose centre `obstacle` covers solid. The obstacle is in
+    /// cell units, cell `(i, j)` spanning `[i, i + 1]` x `[j, j + 1]`.
+    pub fn add_obstacle(&mut self, obstacle: &Obstacle) {
+        if let Obstacle::Airfoil(airfoil) = obstacle {
+            // trace the outline once rather than for every cell
+            let polygon = airfoil.polygon();
+            self.add_obstacle_sdf(|p| polygon.signed_distance(p));
+        } else {
+            self.add_obstacle_sdf(|p| obstacle.signed_distance(p));
+        }
+    }
+
+    /// Makes the interior cells where `sdf` is negative at the centre solid and removes
+    /// the particles caught inside. Distances are in cell units.
+    pub fn add_obstacle_sdf(&mut self, sdf: impl Fn(Vec2) -> f32) {
+        for (i, j) in self.solids.interior() {
+            if sdf(Vec2::new(i as f32 + 0.5, j as f32 + 0.5)) < 0.0 {
+                self.solids[(i, j)] = T::zero();
+            }
+        }
+
+        let mut k = 0;
+        while k < self.positions.len() {
+            let (i, j) = self.cell_of(self.positions[k]);
+            if self.solids[(i, j)].is_zero() {
+                self.positions.swap_remove(k);
+                self.velocities.swap_remove(k);
+            } else {
+                k += 1;
+            }
+        }
+        self.mark_cells();
+    }
+
+    pub fn set_flip_ratio(&mut self, flip_ratio: T) {
+        self.flip_ratio = flip_ratio.max(T::zero()).min(T::one());
+    }
+
+    pub fn set_pressure_solver(&mut self, solver: impl PressureSolver<T> + 'static) {
+        self.pressure_solver = Box::new(solver);
+    }
+
+    pub fn set_display(&mut self, display: FLIPField) {
+        self.display = display;
+        self.update_instances();
+    }
+
+    pub fn solver_stats(&self) -> SolverStats {
+        self.solver_stats
+    }
+
+    pub fn positions(&self) -> &[Vector2<T>] {
+        &self.positions
+    }
+
+    pub fn velocities(&self) -> &[Vector2<T>] {
+        &self.velocities
+    }
+
+    pub fn cells(&self) -> &Grid2D<LiquidCell> {
+        &self.cells
+    }
+
+    pub fn pressure(&self) -> &Grid2D<T> {
+        &self.pressure
+    }
+
+    /// Area of the cells holding liquid, which drifts from the initial volume as the
+    /// particles bunch up or spread out.
+    pub fn liquid_area(&self) -> T {
+        let fluid = self
+            .cells
+            .iter()
+            .filter(|&&c| c == LiquidCell::Fluid)
+            .count();
+        T::from_usize(fluid) * self.spacing * self.spacing
+    }
+
+    /// Simulated time since the scene was applied.
+    pub fn time(&self) -> T {
+        self.time
+    }
+
+    /// Number of steps taken by the last `update`.
+    pub fn substeps(&self) -> usize {
+        self.substeps
+    }
+
+    /// Total number of steps since the scene was applied.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Largest step that moves the fastest particle by at most `cfl` cells.
+    pub fn cfl_time_step(&self, cfl: T) -> T {
+        let max_speed = self
+            .velocities
+            .iter()
+            .fold(T::zero(), |m, velocity| m.max(velocity.length()));
+        if max_speed > T::zero() {
+            cfl * self.spacing / max_speed
+        } else {
+            T::infinity()
+        }
+    }
+
+    /// Advances the scene's frame time, or `dt` when it has none, in steps kept below
+    /// its Courant number.
+    pub fn update(&mut self, dt: Duration) {
+        let mut remaining = self.frame_time.unwrap_or(T::from_f64(dt.as_secs_f64()));
+        self.substeps = 0;
+
+        // particles at rest still fall `g dt^2` within a step
+        let g = self.gravity.length() + self.max_excitation();
+        while remaining > T::zero() && self.substeps < self.max_substeps {
+            let mut dt = self.cfl_time_step(self.cfl);
+            if g > T::zero() {
+                dt = dt.min((self.cfl * self.spacing / g).sqrt());
+            }
+            if dt >= remaining {
+                dt = remaining;
+            } else if T::from_f64(2.0) * dt >= remaining {
+                dt = T::from_f64(0.5) * remaining;
+            }
+            self.step(dt);
+            remaining -= dt;
+        }
+
+        self.update_instances();
+    }
+
+    /// Advances the simulation by a single step of `dt` seconds.
+    pub fn step(&mut self, dt: T) {
+        self.move_particles(dt);
+        for _ in 0..self.separation_iterations {
+            self.separate_particles();
+        }
+
+        self.particles_to_grid();
+        self.apply_forces(dt);
+        self.solve_incompressibility(dt);
+        self.grid_to_particles();
+
+        self.time += dt;
+        self.substeps += 1;
+        self.steps += 1;
+    }
+
+    fn excitation_at(&self, time: T) -> Vector2<T> {
+        match self.excitation {
+            Some(TankExcitation {
+                acceleration,
+                frequency,
+            }) => {
+                let phase = T::from_f32(std::f32::consts::TAU * frequency) * time;
+                Vector2::new(T::from_f32(acceleration[0]), T::from_f32(acceleration[1]))
+                    * phase.sin()
+            }
+            None => Vector2::zero(),
+        }
+    }
+
+    fn max_excitation(&self) -> T {
+        self.excitation.map_or(T::zero(), |excitation| {
+            let [x, y] = excitation.acceleration;
+            T::from_f32((x * x + y * y).sqrt())
+        })
+    }
+
+    /// Cell holding the point `position`, clamped to the grid.
+    fn cell_of(&self, position: Vector2<T>) -> (usize, usize) {
+        let index = |x: T, size: usize| {
+            let cell = (x / self.spacing).floor().max(T::zero());
+            cell.to_usize().unwrap_or(0).min(size - 1)
+        };
+        (
+            index(position.x, self.width),
+            index(position.y, self.height),
+        )
+    }
+
+    /// Moves particle `k` by `delta`, one axis at a time. A particle running into a solid
+    /// cell stops a radius short of it and loses its velocity along that axis.
+    fn displace(&mut self, k: usize, delta: Vector2<T>) {
+        let h = self.spacing;
+        let radius = self.particle_radius;
+
+        for axis in 0..2 {
+            let position = self.positions[k];
+            let (start, step) = if axis == 0 {
+                (position.x, delta.x)
+            } else {
+                (position.y, delta.y)
+            };
+            let mut target = position;
+            if axis == 0 {
+                target.x += step;
+            } else {
+                target.y += step;
+            }
+
+            let (i, j) = self.cell_of(target);
+            let moved = if self.solids[(i, j)].is_zero() {
+                // the wall is the face of the cell the particle starts in
+                let (ci, cj) = self.cell_of(position);
+                let cell = T::from_usize(if axis == 0 { ci } else { cj });
+                let limit = if step > T::zero() {
+                    ((cell + T::one()) * h - radius).max(start)
+                } else {
+                    (cell * h + radius).min(start)
+                };
+                if axis == 0 {
+                    self.velocities[k].x = T::zero();
+                } else {
+                    self.velocities[k].y = T::zero();
+                }
+                limit
+            } else {
+                start + step
+            };
+
+            if axis == 0 {
+                self.positions[k].x = moved;
+            } else {
+                self.positions[k].y = moved;
+            }
+        }
+    }
+
+    fn move_particles(&mut self, dt: T) {
+        for k in 0..self.positions.len() {
+            self.displace(k, self.velocities[k] * dt);
+        }
+    }
+
+    /// Pushes apart particles closer than two radii, each by half the overlap, to keep
+    /// them spread evenly through the liquid.
+    fn separate_particles(&mut self) {
+        let diameter = T::from_f64(2.0) * self.particle_radius;
+
+        // particles sorted by cell, `first[c]..first[c + 1]` in cell `c`
+        let mut first = vec![0; self.width * self.height + 1];
+        let cell_indices: Vec<usize> = self
+            .positions
+            .iter()
+            .map(|&p| {
+                let (i, j) = self.cell_of(p);
+                self.cells.index(i, j)
+            })
+            .collect();
+        for &c in &cell_indices {
+            first[c + 1] += 1;
+        }
+        for c in 0..self.width * self.height {
+            first[c + 1] += first[c];
+        }
+        let mut next = first.clone();
+        let mut sorted = vec![0; self.positions.len()];
+        for (k, &c) in cell_indices.iter().enumerate() {
+            sorted[next[c]] = k;
+            next[c] += 1;
+        }
+
+        for k in 0..self.positions.len() {
+            let (i, j) = self.cell_of(self.positions[k]);
+            for ni in i.saturating_sub(1)..(i + 2).min(self.width) {
+                for nj in j.saturating_sub(1)..(j + 2).min(self.height) {
+                    let c = self.cells.index(ni, nj);
+                    for &other in &sorted[first[c]..first[c + 1]] {
+                        if other <= k {
+                            continue;
+                        }
+                        let offset = self.positions[other] - self.positions[k];
+                        let distance = offset.length();
+                        if distance >= diameter || distance == T::zero() {
+                            continue;
+                        }
+                        let push = offset * (T::from_f64(0.5) * (diameter - distance) / distance);
+                        let velocity = (self.velocities[k], self.velocities[other]);
+                        self.displace(k, -push);
+                        self.displace(other, push);
+                        // separation moves particles without changing their velocity
+                        self.velocities[k] = velocity.0;
+                        self.velocities[other] = velocity.1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Marks the open cells holding a particle as fluid and the others as air.
+    fn mark_cells(&mut self) {
+        for c in 0..self.cells.len() {
+            self.cells[c] = if self.solids[c].is_zero() {
+                LiquidCell::Solid
+            } else {
+                LiquidCell::Air
+            };
+        }
+        for &position in &self.positions {
+            let (i, j) = self.cell_of(position);
+            if self.cells[(i, j)] == LiquidCell::Air {
+                self.cells[(i, j)] = LiquidCell::Fluid;
+            }
+        }
+        for c in 0..self.cells.len() {
+            self.air[c] = self.cells[c] == LiquidCell::Air;
+        }
+    }
+
+    /// Splats the particle velocities on the faces with bilinear weights, marks the
+    /// cells, stops the faces of solid cells and keeps the result for the FLIP update.
+    fn particles_to_grid(&mut self) {
+        let h = self.spacing;
+        for (values, axis) in [(&mut self.u, 0), (&mut self.v, 1)] {
+            let mut weights = vec![T::zero(); values.len()];
+            values.fill(T::zero());
+            for (position, velocity) in self.positions.iter().zip(&self.velocities) {
+                let component = if axis == 0 { velocity.x } else { velocity.y };
+                let (samples, sample_weights) = values.stencil(position.x, position.y, h);
+                for (c, w) in samples.into_iter().zip(sample_weights) {
+                    values[c] += w * component;
+                    weights[c] += w;
+                }
+            }
+            for (value, weight) in values.iter_mut().zip(weights) {
+                if weight > T::zero() {
+                    *value /= weight;
+                }
+            }
+        }
+
+        self.mark_cells();
+        self.stop_solid_faces();
+        self.previous_u.clone_from(&self.u);
+        self.previous_v.clone_from(&self.v);
+    }
+
+    /// Accelerates the faces by gravity and the tank excitation. Applied on the grid
+    /// after the splat so that the particles see it through the FLIP change.
+    fn apply_forces(&mut self, dt: T) {
+        let acceleration = (self.gravity + self.excitation_at(self.time)) * dt;
+        for u in self.u.iter_mut() {
+            *u += acceleration.x;
+        }
+        for v in self.v.iter_mut() {
+            *v += acceleration.y;
+        }
+        self.stop_solid_faces();
+    }
+
+    fn stop_solid_faces(&mut self) {
+        let n = self.height;
+        for i in 0..self.width {
+            for j in 0..self.height {
+                let c = self.solids.index(i, j);
+                if !self.solids[c].is_zero() {
+                    continue;
+                }
+                self.u[c] = T::zero();
+                self.v[c] = T::zero();
+                if i + 1 < self.width {
+                    self.u[c + n] = T::zero();
+                }
+                if j + 1 < self.height {
+                    self.v[c + 1] = T::zero();
+                }
+            }
+        }
+    }
+
+    fn solve_incompressibility(&mut self, dt: T) {
+        self.pressure.fill(T::zero());
+        let mut problem = PressureProblem {
+            u: &mut self.u,
+            v: &mut self.v,
+            pressure: &mut self.pressure,
+            solids: &self.solids,
+            air: Some(&self.air),
+            scale: self.density * self.spacing / dt,
+            periodic: [false, false],
+        };
+
+        let start = Instant::now();
+        let mut stats = self.pressure_solver.solve(&mut problem);
+        stats.wall_time = start.elapsed();
+
+        if !stats.converged {
+            warn!(
+                "Pressure solver did not converge: residual {} after {} iterations",
+                stats.final_residual, stats.iterations
+            );
+        }
+        self.solver_stats = stats;
+    }
+
+    /// Gives the particles the projected face velocities, blending the interpolated
+    /// velocity (PIC) with their own velocity plus the interpolated change (FLIP). Only
+    /// faces next to a fluid cell carry a meaningful velocity and are sampled.
+    fn grid_to_particles(&mut self) {
+        let h = self.spacing;
+        let flip = self.flip_ratio;
+        let n = self.height;
+
+        for (position, velocity) in self.positions.iter().zip(&mut self.velocities) {
+            for axis in 0..2 {
+                let (values, previous, neighbour) = if axis == 0 {
+                    (&self.u, &self.previous_u, n)
+                } else {
+                    (&self.v, &self.previous_v, 1)
+                };
+                let (samples, weights) = values.stencil(position.x, position.y, h);
+
+                let (mut total, mut pic, mut change) = (T::zero(), T::zero(), T::zero());
+                for (c, w) in samples.into_iter().zip(weights) {
+                    let fluid = |c: usize| self.cells[c] == LiquidCell::Fluid;
+                    if !(fluid(c) || (c >= neighbour && fluid(c - neighbour))) {
+                        continue;
+                    }
+                    total += w;
+                    pic += w * values[c];
+                    change += w * (values[c] - previous[c]);
+                }
+                if total <= T::zero() {
+                    continue;
+                }
+
+                let component = if axis == 0 {
+                    &mut velocity.x
+                } else {
+                    &mut velocity.y
+                };
+                let pic = pic / total;
+                let flip_velocity = *component + change / total;
+                *component = (T::one() - flip) * pic + flip * flip_velocity;
+            }
+        }
+    }
+
+    /// Colours the liquid cells by the displayed field over its range, air in black and
+    /// solids in grey.
+    pub fn update_instances(&mut self) {
+        let mut counts = vec![0usize; self.cells.len()];
+        for &position in &self.positions {
+            let (i, j) = self.cell_of(position);
+            counts[self.cells.index(i, j)] += 1;
+        }
+
+        let values: Vec<f32> = self
+            .cells
+            .cells()
+            .map(|(i, j)| self.display_value(i, j, counts[self.cells.index(i, j)]))
+            .collect();
+        let fluid = || {
+            values
+                .iter()
+                .zip(self.cells.iter())
+                .filter(|(_, &cell)| cell == LiquidCell::Fluid)
+                .map(|(&value, _)| value)
+        };
+        let (min, max) = match self.display {
+            FLIPField::ParticleDensity => (0.0, 2.0),
+            _ => (
+                fluid().fold(f32::INFINITY, f32::min),
+                fluid().fold(f32::NEG_INFINITY, f32::max),
+            ),
+        };
+
+        // instances are laid out like the grid, column by column
+        for ((instance, &value), &cell) in self
+            .instances
+            .iter_mut()
+            .zip(&values)
+            .zip(self.cells.iter())
+        {
+            instance.color = match cell {
+                LiquidCell::Solid => [0.5, 0.5, 0.5],
+                LiquidCell::Air => [0.0, 0.0, 0.0],
+                LiquidCell::Fluid => scalar_color(value, min, max),
+            };
+        }
+    }
+
+    fn display_value(&self, i: usize, j: usize, count: usize) -> f32 {
+        match self.display {
+            FLIPField::Speed => {
+                if i + 1 >= self.width || j + 1 >= self.height {
+                    return 0.0;
+                }
+                let half = T::from_f64(0.5);
+                let u = half * (self.u[(i, j)] + self.u[(i + 1, j)]);
+                let v = half * (self.v[(i, j)] + self.v[(i, j + 1)]);
+                (u * u + v * v).sqrt().as_f32()
+            }
+            FLIPField::Pressure => self.pressure[(i, j)].as_f32(),
+            FLIPField::ParticleDensity => count as f32 / self.particles_per_cell as f32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Convergence, ResidualNorm};
+
+    const TOLERANCE: f32 = 1e-8;
+
+    /// A 20 x 10 tank holding `liquid`, projected with the Conjugate Gradient to
+    /// `TOLERANCE`.
+    fn tank(liquid: Region, flip_ratio: f32) -> FLIPSimulation<f64> {
+        let scene = FLIPScene {
+            liquid: vec![liquid],
+            flip_ratio,
+            pressure_solver: PressureSolverConfig::ConjugateGradient {
+                convergence: Convergence::tolerance(500, TOLERANCE, ResidualNorm::Max),
+            },
+            ..FLIPScene::dam_break(20)
+        };
+        FLIPSimulation::from_scene(&scene)
+    }
+
+    fn run(simulation: &mut FLIPSimulation<f64>, steps: usize) {
+        for _ in 0..steps {
+            simulation.step(2e-3);
+        }
+    }
+
+    fn kinetic_energy(simulation: &FLIPSimulation<f64>) -> f64 {
+        simulation
+            .velocities()
+            .iter()
+            .map(|v| 0.5 * (v.x * v.x + v.y * v.y))
+            .sum()
+    }
+
+    /// Kinetic and potential energy of the particles, per unit mass of each.
+    fn energy(simulation: &FLIPSimulation<f64>) -> f64 {
+        let potential: f64 = simulation.positions().iter().map(|p| 9.81 * p.y).sum();
+        kinetic_energy(simulation) + potential
+    }
+
+    fn max_speed(simulation: &FLIPSimulation<f64>) -> f64 {
+        simulation
+            .velocities()
+            .iter()
+            .fold(0.0, |m, v| m.max(v.length()))
+    }
+
+    /// Largest velocity divergence, in face velocity units, of the liquid cells.
+    fn max_divergence(simulation: &FLIPSimulation<f64>) -> f64 {
+        let (u, v) = (&simulation.u, &simulation.v);
+        simulation
+            .cells
+            .interior()
+            .filter(|&(i, j)| simulation.cells[(i, j)] == LiquidCell::Fluid)
+            .map(|(i, j)| (u[(i + 1, j)] - u[(i, j)] + v[(i, j + 1)] - v[(i, j)]).abs())
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn tank_at_rest_stays_still() {
+        let mut simulation = tank(Region::new(0.0, 1.0, 0.0, 0.5), 0.95);
+        let area = simulation.liquid_area();
+        run(&mut simulation, 500);
+        let speed = max_speed(&simulation);
+        assert!(speed < 1e-6, "particles moving at {}", speed);
+        assert_eq!(simulation.liquid_area(), area);
+    }
+
+    #[test]
+    fn dam_break_keeps_particles_and_volume() {
+        let mut simulation = tank(Region::new(0.0, 0.25, 0.0, 0.5), 0.95);
+        let (particles, area) = (simulation.positions().len(), simulation.liquid_area());
+        for _ in 0..5 {
+            run(&mut simulation, 100);
+            assert_eq!(simulation.positions().len(), particles);
+            let change = (simulation.liquid_area() - area).abs() / area;
+            assert!(change < 0.2, "liquid area changed by {}", change);
+        }
+    }
+
+    #[test]
+    fn projection_removes_divergence_in_liquid() {
+        let mut simulation = tank(Region::new(0.0, 0.25, 0.0, 0.5), 0.95);
+        for _ in 0..5 {
+            run(&mut simulation, 20);
+            assert!(simulation.solver_stats().converged);
+            let divergence = max_divergence(&simulation);
+            assert!(
+                divergence < 10.0 * TOLERANCE as f64,
+                "divergence {} left",
+                divergence
+            );
+        }
+    }
+
+    #[test]
+    fn pic_dissipates_more_than_flip() {
+        let loss = |flip_ratio: f32| {
+            let mut simulation = tank(Region::new(0.0, 0.25, 0.0, 0.5), flip_ratio);
+            let initial = energy(&simulation);
+            run(&mut simulation, 300);
+            (initial - energy(&simulation)) / initial
+        };
+        let (pic, flip) = (loss(0.0), loss(1.0));
+        assert!(flip > 0.0, "FLIP gained energy: {}", flip);
+        assert!(pic > 2.0 * flip, "PIC lost {}, FLIP {}", pic, flip);
+    }
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Grid2D, Real};
+
+/// Free stream used to turn obstacle forces into drag and lift coefficients. The flow
+/// is assumed to come in along +x, lift points along +y.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ForceReference {
+    pub speed: f32,
+    /// Reference length (diameter, chord) in the units of the grid spacing.
+    pub length: f32,
+}
+
+impl ForceReference {
+    /// Dynamic pressure times reference length, `0.5 * density * speed^2 * length`.
+    pub fn scale(&self, density: f32) -> f32 {
+        0.5 * density * self.speed * self.speed * self.length
+    }
+}
+
+/// Force per unit span acting on one connected obstacle of the grid.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ObstacleForces {
+    /// Number of (partially) solid cells in the obstacle.
+    pub cells: usize,
+    /// Centre of the obstacle in cell units.
+    pub centroid: [f32; 2],
+    pub pressure_force: [f32; 2],
+    pub viscous_force: [f32; 2],
+    pub drag: f32,
+    pub lift: f32,
+    pub drag_coefficient: f32,
+    pub lift_coefficient: f32,
+}
+
+impl ObstacleForces {
+    pub(crate) fn finish(&mut self, reference: &ForceReference, density: f32) {
+        self.drag = self.pressure_force[0] + self.viscous_force[0];
+        self.lift = self.pressure_force[1] + self.viscous_force[1];
+
+        let scale = reference.scale(density);
+        if scale > 0.0 {
+            self.drag_coefficient = self.drag / scale;
+            self.lift_coefficient = self.lift / scale;
+        }
+    }
+}
+
+/// Labels the 4-connected groups of interior cells that are not entirely fluid, one
+/// label per obstacle. The domain border is left unlabelled.
+pub fn label_obstacles<T: Real>(solids: &Grid2D<T>) -> (Grid2D<Option<usize>>, usize) {
+    let (width, height) = (solids.width(), solids.height());
+    let mut labels = solids.map(|_| None);
+    let mut count = 0;
+    let mut stack = Vec::new();
+
+    for (i, j) in solids.interior() {
+        if solids[(i, j)] >= T::one() || labels[(i, j)].is_some() {
+            continue;
+        }
+
+        labels[(i, j)] = Some(count);
+        stack.push((i, j));
+        while let Some((i, j)) = stack.pop() {
+            for (ni, nj) in [(i - 1, j), (i + 1, j), (i, j - 1), (i, j + 1)] {
+                let interior = ni > 0 && nj > 0 && ni < width - 1 && nj < height - 1;
+                if interior && solids[(ni, nj)] < T::one() && labels[(ni, nj)].is_none() {
+                    labels[(ni, nj)] = Some(count);
+                    stack.push((ni, nj));
+                }
+            }
+        }
+        count += 1;
+    }
+
+    (labels, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GridLocation;
+
+    #[test]
+    fn labels_each_connected_obstacle_once() {
+        let mut solids = Grid2D::new(8, 6, GridLocation::CellCenter, 1.0f64);
+        for (i, j) in [(1, 1), (2, 1), (2, 2), (5, 3), (5, 4), (6, 4)] {
+            solids[(i, j)] = 0.0;
+        }
+        // partially solid, joining the first obstacle
+        solids[(3, 2)] = 0.5;
+        // diagonal neighbours stay apart
+        solids[(1, 3)] = 0.0;
+        // the border is never an obstacle
+        solids[(0, 3)] = 0.0;
+
+        let (labels, count) = label_obstacles(&solids);
+        assert_eq!(count, 3);
+        assert_eq!(labels[(1, 1)], Some(0));
+        assert_eq!(labels[(3, 2)], Some(0));
+        assert_eq!(labels[(1, 3)], Some(1));
+        assert_eq!(labels[(5, 3)], Some(2));
+        assert_eq!(labels[(6, 4)], Some(2));
+        assert_eq!(labels[(0, 3)], None);
+        assert_eq!(labels[(3, 3)], None);
+    }
+
+    #[test]
+    fn coefficients_divide_by_the_dynamic_pressure() {
+        let mut forces = ObstacleForces {
+            pressure_force: [30.0, -8.0],
+            viscous_force: [10.0, 2.0],
+            ..ObstacleForces::default()
+        };
+        forces.finish(
+            &ForceReference {
+                speed: 2.0,
+                length: 5.0,
+            },
+            2.0,
+        );
+        assert_eq!((forces.drag, forces.lift), (40.0, -6.0));
+        assert_eq!(forces.drag_coefficient, 2.0);
+        assert_eq!(forces.lift_coefficient, -0.3);
+    }
+}
@@ -0,0 +1,298 @@
+use std::ops::{Deref, DerefMut, Index, IndexMut};
+
+use crate::Real;
+
+/// Where the samples of a field sit within a grid cell on the staggered (MAC) grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridLocation {
+    /// Cell centres: pressure, smoke, temperature, dyes.
+    CellCenter,
+    /// Centre of the left face of each cell: horizontal velocity.
+    UFace,
+    /// Centre of the bottom face of each cell: vertical velocity.
+    VFace,
+}
+
+impl GridLocation {
+    /// Offset of sample `(0, 0)` from the corner of the grid, in cells.
+    pub fn offset(&self) -> (f32, f32) {
+        match self {
+            GridLocation::CellCenter => (0.5, 0.5),
+            GridLocation::UFace => (0.0, 0.5),
+            GridLocation::VFace => (0.5, 0.0),
+        }
+    }
+}
+
+/// Field of `width` x `height` samples stored column by column, so that `(i, j)` lives
+/// at `i * height + j`. Derefs to the flat storage for whole-field operations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid2D<T> {
+    width: usize,
+    height: usize,
+    location: GridLocation,
+    data: Vec<T>,
+}
+
+impl<T: Clone> Grid2D<T> {
+    pub fn new(width: usize, height: usize, location: GridLocation, value: T) -> Self {
+        Self {
+            width,
+            height,
+            location,
+            data: vec![value; width * height],
+        }
+    }
+}
+
+impl<T> Grid2D<T> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn location(&self) -> GridLocation {
+        self.location
+    }
+
+    pub fn contains(&self, i: usize, j: usize) -> bool {
+        i < self.width && j < self.height
+    }
+
+    /// Offset of `(i, j)` in the flat storage. Panics outside of the grid.
+    pub fn index(&self, i: usize, j: usize) -> usize {
+        assert!(self.contains(i, j), "({}, {}) outside of the grid", i, j);
+        i * self.height + j
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> Option<&T> {
+        self.contains(i, j).then(|| &self.data[i * self.height + j])
+    }
+
+    pub fn get_mut(&mut self, i: usize, j: usize) -> Option<&mut T> {
+        if self.contains(i, j) {
+            Some(&mut self.data[i * self.height + j])
+        } else {
+            None
+        }
+    }
+
+    /// Stores `value` at `(i, j)`, returning `false` and dropping it when out of bounds.
+    pub fn set(&mut self, i: usize, j: usize, value: T) -> bool {
+        match self.get_mut(i, j) {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// World position of sample `(i, j)` for cells of size `spacing`.
+    pub fn position<S: Real>(&self, i: usize, j: usize, spacing: S) -> (S, S) {
+        let (dx, dy) = self.location.offset();
+        (
+            (S::from_usize(i) + S::from_f32(dx)) * spacing,
+            (S::from_usize(j) + S::from_f32(dy)) * spacing,
+        )
+    }
+
+    /// All `(i, j)` in storage order.
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize)> {
+        let height = self.height;
+        (0..self.width).flat_map(move |i| (0..height).map(move |j| (i, j)))
+    }
+
+    /// `(i, j)` of all samples but the outermost ring.
+    pub fn interior(&self) -> impl Iterator<Item = (usize, usize)> {
+        let height = self.height;
+        (1..self.width.saturating_sub(1))
+            .flat_map(move |i| (1..height.saturating_sub(1)).map(move |j| (i, j)))
+    }
+
+    /// Samples with their `(i, j)`, in storage order.
+    pub fn indexed_iter(&self) -> impl Iterator<Item = ((usize, usize), &T)> {
+        self.cells().zip(self.data.iter())
+    }
+
+    /// Same layout and location with every sample mapped through `f`.
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Grid2D<U> {
+        Grid2D {
+            width: self.width,
+            height: self.height,
+            location: self.location,
+            data: self.data.iter().map(f).collect(),
+        }
+    }
+}
+
+impl<T: Real> Grid2D<T> {
+    /// Samples and bilinear weights interpolating the field at world position `(x, y)`.
+    /// Positions past the last sample use it with full weight; callers keep `(x, y)`
+    /// inside the domain.
+    pub fn stencil(&self, x: T, y: T, spacing: T) -> ([usize; 4], [T; 4]) {
+        let (dx, dy) = self.location.offset();
+        let h1 = T::one() / spacing;
+        let x = x * h1 - T::from_f32(dx);
+        let y = y * h1 - T::from_f32(dy);
+
+        let (zero, one) = (T::zero(), T::one());
+        let last_x = T::from_usize(self.width - 1);
+        let last_y = T::from_usize(self.height - 1);
+        let x0 = x.floor().max(zero).min(last_x);
+        let tx = (x - x0).max(zero).min(one);
+        let x1 = (x0 + one).min(last_x);
+        let y0 = y.floor().max(zero).min(last_y);
+        let ty = (y - y0).max(zero).min(one);
+        let y1 = (y0 + one).min(last_y);
+
+        let sx = one - tx;
+        let sy = one - ty;
+
+        let n = self.height;
+        let index = |value: T| value.to_usize().unwrap_or(0);
+        let (x0, x1, y0, y1) = (index(x0), index(x1), index(y0), index(y1));
+        (
+            [x0 * n + y0, x1 * n + y0, x1 * n + y1, x0 * n + y1],
+            [sx * sy, tx * sy, tx * ty, sx * ty],
+        )
+    }
+
+    /// Bilinearly interpolates the field at world position `(x, y)`.
+    pub fn interpolate(&self, x: T, y: T, spacing: T) -> T {
+        let (cells, weights) = self.stencil(x, y, spacing);
+        cells
+            .iter()
+            .zip(weights)
+            .map(|(c, w)| w * self.data[*c])
+            .sum()
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid2D<T> {
+    type Output = T;
+
+    fn index(&self, (i, j): (usize, usize)) -> &T {
+        &self.data[Grid2D::index(self, i, j)]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid2D<T> {
+    fn index_mut(&mut self, (i, j): (usize, usize)) -> &mut T {
+        let c = Grid2D::index(self, i, j);
+        &mut self.data[c]
+    }
+}
+
+impl<T> Index<usize> for Grid2D<T> {
+    type Output = T;
+
+    fn index(&self, c: usize) -> &T {
+        &self.data[c]
+    }
+}
+
+impl<T> IndexMut<usize> for Grid2D<T> {
+    fn index_mut(&mut self, c: usize) -> &mut T {
+        &mut self.data[c]
+    }
+}
+
+impl<T> Deref for Grid2D<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.data
+    }
+}
+
+impl<T> DerefMut for Grid2D<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3 x 4 grid holding `10 * i + j` at `(i, j)`.
+    fn numbered(location: GridLocation) -> Grid2D<f64> {
+        let mut grid = Grid2D::new(3, 4, location, 0.0);
+        for (i, j) in grid.cells() {
+            grid[(i, j)] = (10 * i + j) as f64;
+        }
+        grid
+    }
+
+    #[test]
+    fn indexes_column_by_column() {
+        let grid = numbered(GridLocation::CellCenter);
+        assert_eq!(grid.index(0, 3), 3);
+        assert_eq!(grid.index(1, 0), 4);
+        assert_eq!(grid.index(2, 3), 11);
+        assert_eq!(grid[grid.index(2, 1)], 21.0);
+        assert_eq!(grid.get(1, 2), Some(&12.0));
+        assert_eq!(grid.get(3, 0), None);
+        assert_eq!(grid.get(0, 4), None);
+
+        let mut grid = grid;
+        assert!(grid.set(2, 3, -1.0));
+        assert!(!grid.set(2, 4, -1.0));
+        assert_eq!(grid[(2, 3)], -1.0);
+        assert_eq!(grid[(0, 0)], 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside of the grid")]
+    fn rejects_rows_past_the_top() {
+        let grid = numbered(GridLocation::CellCenter);
+        // would alias (1, 0) in the flat storage
+        let _ = grid[(0, 4)];
+    }
+
+    #[test]
+    fn iterates_in_storage_order() {
+        let grid = numbered(GridLocation::CellCenter);
+        let cells: Vec<_> = grid.cells().collect();
+        assert_eq!(cells.len(), 12);
+        for (c, &(i, j)) in cells.iter().enumerate() {
+            assert_eq!(grid.index(i, j), c);
+        }
+        assert_eq!(grid.interior().collect::<Vec<_>>(), [(1, 1), (1, 2)]);
+        for ((i, j), value) in grid.indexed_iter() {
+            assert_eq!(*value, grid[(i, j)]);
+        }
+    }
+
+    #[test]
+    fn interpolates_face_samples_at_their_offsets() {
+        let spacing = 0.5;
+        for location in [
+            GridLocation::CellCenter,
+            GridLocation::UFace,
+            GridLocation::VFace,
+        ] {
+            let grid = numbered(location);
+            let (dx, dy) = location.offset();
+            // the field is linear in the sample indices, so interpolation is exact
+            let expected =
+                |x: f64, y: f64| 10.0 * (x / spacing - dx as f64) + y / spacing - dy as f64;
+            for (i, j) in grid.cells() {
+                let (x, y) = grid.position(i, j, spacing);
+                assert_eq!(grid.interpolate(x, y, spacing), grid[(i, j)]);
+            }
+            let (x, y) = (0.6, 0.9);
+            let value = grid.interpolate(x, y, spacing);
+            assert!(
+                (value - expected(x, y)).abs() < 1e-12,
+                "{:?}: {}",
+                location,
+                value
+            );
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::ops::{Deref, DerefMut, Index, IndexMut};
+
+use crate::Real;
+
+/// Where the samples of a field sit within a cell of the 3D staggered (MAC) grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridLocation3D {
+    /// Cell centres: pressure, smoke, temperature.
+    CellCenter,
+    /// Centre of the left (low x) face of each cell: x velocity.
+    UFace,
+    /// Centre of the bottom (low y) face of each cell: y velocity.
+    VFace,
+    /// Centre of the back (low z) face of each cell: z velocity.
+    WFace,
+}
+
+impl GridLocation3D {
+    /// Offset of sample `(0, 0, 0)` from the corner of the grid, in cells.
+    pub fn offset(&self) -> (f32, f32, f32) {
+        match self {
+            GridLocation3D::CellCenter => (0.5, 0.5, 0.5),
+            GridLocation3D::UFace => (0.0, 0.5, 0.5),
+            GridLocation3D::VFace => (0.5, 0.0, 0.5),
+            GridLocation3D::WFace => (0.5, 0.5, 0.0),
+        }
+    }
+}
+
+/// Field of `width` x `height` x `depth` samples, stored so that `(i, j, k)` lives at
+/// `(i * height + j) * depth + k`. Derefs to the flat storage for whole-field
+/// operations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid3D<T> {
+    width: usize,
+    height: usize,
+    depth: usize,
+    location: GridLocation3D,
+    data: Vec<T>,
+}
+
+impl<T: Clone> Grid3D<T> {
+    pub fn new(
+        width: usize,
+        height: usize,
+        depth: usize,
+        location: GridLocation3D,
+        value: T,
+    ) -> Self {
+        Self {
+            width,
+            height,
+            depth,
+            location,
+            data: vec![value; width * height * depth],
+        }
+    }
+}
+
+impl<T> Grid3D<T> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn location(&self) -> GridLocation3D {
+        self.location
+    }
+
+    pub fn contains(&self, i: usize, j: usize, k: usize) -> bool {
+        i < self.width && j < self.height && k < self.depth
+    }
+
+    /// Offset of `(i, j, k)` in the flat storage. Panics outside of the grid.
+    pub fn index(&self, i: usize, j: usize, k: usize) -> usize {
+        assert!(
+            self.contains(i, j, k),
+            "({}, {}, {}) outside of the grid",
+            i,
+            j,
+            k
+        );
+        (i * self.height + j) * self.depth + k
+    }
+
+    /// Distances in the flat storage between neighbours along x, y and z.
+    pub fn strides(&self) -> [usize; 3] {
+        [self.height * self.depth, self.depth, 1]
+    }
+
+    pub fn get(&self, i: usize, j: usize, k: usize) -> Option<&T> {
+        self.contains(i, j, k)
+            .then(|| &self.data[(i * self.height + j) * self.depth + k])
+    }
+
+    /// World position of sample `(i, j, k)` for cells of size `spacing`.
+    pub fn position<S: Real>(&self, i: usize, j: usize, k: usize, spacing: S) -> (S, S, S) {
+        let (dx, dy, dz) = self.location.offset();
+        (
+            (S::from_usize(i) + S::from_f32(dx)) * spacing,
+            (S::from_usize(j) + S::from_f32(dy)) * spacing,
+            (S::from_usize(k) + S::from_f32(dz)) * spacing,
+        )
+    }
+
+    /// All `(i, j, k)` in storage order.
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize, usize)> {
+        let (height, depth) = (self.height, self.depth);
+        (0..self.width)
+            .flat_map(move |i| (0..height).flat_map(move |j| (0..depth).map(move |k| (i, j, k))))
+    }
+
+    /// `(i, j, k)` of all samples but the outermost layer.
+    pub fn interior(&self) -> impl Iterator<Item = (usize, usize, usize)> {
+        let (height, depth) = (self.height, self.depth);
+        (1..self.width.saturating_sub(1)).flat_map(move |i| {
+            (1..height.saturating_sub(1))
+                .flat_map(move |j| (1..depth.saturating_sub(1)).map(move |k| (i, j, k)))
+        })
+    }
+
+    /// Same layout and location with every sample mapped through `f`.
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Grid3D<U> {
+        Grid3D {
+            width: self.width,
+            height: self.height,
+            depth: self.depth,
+            location: self.location,
+            data: self.data.iter().map(f).collect(),
+        }
+    }
+}
+
+impl<T: Real> Grid3D<T> {
+    /// Samples and trilinear weights interpolating the field at world position
+    /// `(x, y, z)`. Positions past the last sample use it with full weight; callers keep
+    /// the position inside the domain.
+    pub fn stencil(&self, x: T, y: T, z: T, spacing: T) -> ([usize; 8], [T; 8]) {
+        let (dx, dy, dz) = self.location.offset();
+        let h1 = T::one() / spacing;
+        let (zero, one) = (T::zero(), T::one());
+
+        // lower sample, upper sample and weight of the upper one along an axis
+        let axis = |p: T, offset: f32, size: usize| {
+            let p = p * h1 - T::from_f32(offset);
+            let last = T::from_usize(size - 1);
+            let p0 = p.floor().max(zero).min(last);
+            let t = (p - p0).max(zero).min(one);
+            let p1 = (p0 + one).min(last);
+            let index = |value: T| value.to_usize().unwrap_or(0);
+            (index(p0), index(p1), t)
+        };
+        let (x0, x1, tx) = axis(x, dx, self.width);
+        let (y0, y1, ty) = axis(y, dy, self.height);
+        let (z0, z1, tz) = axis(z, dz, self.depth);
+
+        let mut cells = [0; 8];
+        let mut weights = [zero; 8];
+        for (corner, (cell, weight)) in cells.iter_mut().zip(&mut weights).enumerate() {
+            let (i, wx) = if corner & 1 == 0 {
+                (x0, one - tx)
+            } else {
+                (x1, tx)
+            };
+            let (j, wy) = if corner & 2 == 0 {
+                (y0, one - ty)
+            } else {
+                (y1, ty)
+            };
+            let (k, wz) = if corner & 4 == 0 {
+                (z0, one - tz)
+            } else {
+                (z1, tz)
+            };
+            *cell = (i * self.height + j) * self.depth + k;
+            *weight = wx * wy * wz;
+        }
+        (cells, weights)
+    }
+
+    /// Trilinearly interpolates the field at world position `(x, y, z)`.
+    pub fn interpolate(&self, x: T, y: T, z: T, spacing: T) -> T {
+        let (cells, weights) = self.stencil(x, y, z, spacing);
+        cells
+            .iter()
+            .zip(weights)
+            .map(|(c, w)| w * self.data[*c])
+            .sum()
+    }
+}
+
+impl<T> Index<(usize, usize, usize)> for Grid3D<T> {
+    type Output = T;
+
+    fn index(&self, (i, j, k): (usize, usize, usize)) -> &T {
+        &self.data[Grid3D::index(self, i, j, k)]
+    }
+}
+
+impl<T> IndexMut<(usize, usize, usize)> for Grid3D<T> {
+    fn index_mut(&mut self, (i, j, k): (usize, usize, usize)) -> &mut T {
+        let c = Grid3D::index(self, i, j, k);
+        &mut self.data[c]
+    }
+}
+
+impl<T> Index<usize> for Grid3D<T> {
+    type Output = T;
+
+    fn index(&self, c: usize) -> &T {
+        &self.data[c]
+    }
+}
+
+impl<T> IndexMut<usize> for Grid3D<T> {
+    fn index_mut(&mut self, c: usize) -> &mut T {
+        &mut self.data[c]
+    }
+}
+
+impl<T> Deref for Grid3D<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.data
+    }
+}
+
+impl<T> DerefMut for Grid3D<T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Region, SceneError};
+
+/// Decoded RGB image, rows stored from top to bottom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaskImage {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<[u8; 3]>,
+}
+
+impl MaskImage {
+    pub fn new(width: usize, height: usize, pixels: Vec<[u8; 3]>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Loads a PGM/PPM (`pgm`, `ppm`, `pnm`) or PNG image, picking the format from the
+    /// file extension.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        match extension.as_str() {
+            "pgm" | "ppm" | "pnm" => Self::from_pnm(&bytes),
+            "png" => Self::from_png(&bytes),
+            _ => Err(SceneError::UnsupportedFormat(extension)),
+        }
+    }
+
+    /// Parses a plain (`P2`, `P3`) or binary (`P5`, `P6`) PGM/PPM image.
+    pub fn from_pnm(bytes: &[u8]) -> Result<Self, SceneError> {
+        let mut pos = 0;
+        let mut header = [0usize; 3];
+        let magic = next_token(bytes, &mut pos).unwrap_or_default();
+        let (channels, binary) = match magic {
+            b"P2" => (1, false),
+            b"P3" => (3, false),
+            b"P5" => (1, true),
+            b"P6" => (3, true),
+            _ => return Err(SceneError::Image("not a PGM/PPM image".to_string())),
+        };
+        for value in &mut header {
+            *value = next_token(bytes, &mut pos)
+                .and_then(|token| std::str::from_utf8(token).ok()?.parse().ok())
+                .ok_or_else(|| SceneError::Image("invalid PGM/PPM header".to_string()))?;
+        }
+        let [width, height, max_value] = header;
+        if max_value == 0 || max_value > u16::MAX as usize {
+            return Err(SceneError::Image(format!(
+                "invalid maximum value {}",
+                max_value
+            )));
+        }
+
+        let count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(channels))
+            .ok_or_else(|| {
+                SceneError::Image(format!("image of {} x {} is too large", width, height))
+            })?;
+        let samples: Vec<usize> = if binary {
+            // a single whitespace separates the header from the raster
+            let data = bytes.get(pos + 1..).unwrap_or_default();
+            if max_value < 256 {
+                data.iter().take(count).map(|b| *b as usize).collect()
+            } else {
+                data.chunks_exact(2)
+                    .take(count)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+                    .collect()
+            }
+        } else {
+            std::iter::from_fn(|| next_token(bytes, &mut pos))
+                .take(count)
+                .map(|token| std::str::from_utf8(token).ok()?.parse().ok())
+                .collect::<Option<_>>()
+                .ok_or_else(|| SceneError::Image("invalid PGM/PPM sample".to_string()))?
+        };
+        if samples.len() < count {
+            return Err(SceneError::Image("truncated PGM/PPM raster".to_string()));
+        }
+
+        let scale = |sample: usize| (sample.min(max_value) * 255 / max_value) as u8;
+        let pixels = samples
+            .chunks_exact(channels)
+            .map(|p| match p {
+                [l] => [scale(*l); 3],
+                _ => [scale(p[0]), scale(p[1]), scale(p[2])],
+            })
+            .collect();
+
+        Ok(Self::new(width, height, pixels))
+    }
+
+    /// Decodes a PNG image. Transparent pixels are composited over white.
+    pub fn from_png(bytes: &[u8]) -> Result<Self, SceneError> {
+        let error = |err: png::DecodingError| SceneError::Image(err.to_string());
+
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().map_err(error)?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(error)?;
+
+        let over_white =
+            |c: u8, a: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+        let channels = info.color_type.samples();
+        let pixels = buffer[..info.buffer_size()]
+            .chunks_exact(channels)
+            .map(|p| match *p {
+                [l] => [l; 3],
+                [l, a] => [over_white(l, a); 3],
+                [r, g, b] => [r, g, b],
+                [r, g, b, a] => [over_white(r, a), over_white(g, a), over_white(b, a)],
+                _ => unreachable!(),
+            })
+            .collect();
+
+        Ok(Self::new(info.width as usize, info.height as usize, pixels))
+    }
+
+    /// Pixel in column `x` of row `y`, counted from the top.
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        self.pixels[y * self.width + x]
+    }
+}
+
+/// Next whitespace separated token of a PNM header, skipping `#` comments.
+fn next_token<'a>(bytes: &'a [u8], pos: &mut usize) -> Option<&'a [u8]> {
+    loop {
+        while bytes.get(*pos)?.is_ascii_whitespace() {
+            *pos += 1;
+        }
+        if bytes[*pos] != b'#' {
+            break;
+        }
+        while *bytes.get(*pos)? != b'\n' {
+            *pos += 1;
+        }
+    }
+    let start = *pos;
+    while bytes.get(*pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+        *pos += 1;
+    }
+    Some(&bytes[start..*pos])
+}
+
+/// Pixel colour marking a source instead of an obstacle.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum MaskMarker {
+    Smoke {
+        color: [u8; 3],
+        #[serde(default = "MaskMarker::default_value")]
+        value: f32,
+    },
+    /// Holds the marked cells at `velocity`, imposed every step before the projection.
+    Inflow { color: [u8; 3], velocity: [f32; 2] },
+}
+
+impl MaskMarker {
+    fn default_value() -> f32 {
+        1.0
+    }
+
+    pub fn color(&self) -> [u8; 3] {
+        match *self {
+            MaskMarker::Smoke { color, .. } | MaskMarker::Inflow { color, .. } => color,
+        }
+    }
+}
+
+/// Obstacle mask read from an image. The image is stretched over `region` (the grid
+/// interior when unset); pixels darker than `threshold` are solid and each cell keeps the
+/// fraction of its pixels that are not.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageMask {
+    pub path: PathBuf,
+    /// Luminance in `[0, 1]` below which a pixel is solid.
+    #[serde(default = "ImageMask::default_threshold")]
+    pub threshold: f32,
+    #[serde(default)]
+    pub region: Option<Region>,
+    #[serde(default)]
+    pub markers: Vec<MaskMarker>,
+    /// Largest per channel difference for a pixel to match a marker colour.
+    #[serde(default = "ImageMask::default_color_tolerance")]
+    pub color_tolerance: u8,
+}
+
+impl ImageMask {
+    fn default_threshold() -> f32 {
+        0.5
+    }
+
+    fn default_color_tolerance() -> u8 {
+        32
+    }
+
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            threshold: Self::default_threshold(),
+            region: None,
+            markers: Vec::new(),
+            color_tolerance: Self::default_color_tolerance(),
+        }
+    }
+
+    pub fn load(&self) -> Result<MaskImage, SceneError> {
+        MaskImage::from_file(&self.path)
+    }
+
+    pub fn is_solid(&self, pixel: [u8; 3]) -> bool {
+        let [r, g, b] = pixel.map(|c| c as f32 / 255.0);
+        0.299 * r + 0.587 * g + 0.114 * b < self.threshold
+    }
+
+    /// Marker whose colour `pixel` matches, if any.
+    pub fn marker(&self, pixel: [u8; 3]) -> Option<&MaskMarker> {
+        self.markers.iter().find(|marker| {
+            marker
+                .color()
+                .iter()
+                .zip(pixel)
+                .all(|(a, b)| a.abs_diff(b) <= self.color_tolerance)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_and_binary_images() {
+        let plain = MaskImage::from_pnm(b"P2\n# comment\n2 1\n4\n0 4\n").unwrap();
+        assert_eq!((plain.width, plain.height), (2, 1));
+        assert_eq!(plain.pixel(0, 0), [0; 3]);
+        assert_eq!(plain.pixel(1, 0), [255; 3]);
+
+        let binary = MaskImage::from_pnm(b"P6 1 1 255\n\x01\x02\x03").unwrap();
+        assert_eq!(binary.pixel(0, 0), [1, 2, 3]);
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        for bytes in [
+            &b"P5 4 4"[..],
+            b"P5 4 4 255\n0123456789",
+            b"P3 2 2 255\n0 0 0 1 1 1",
+            b"P2 2 2 255\n0 0 x 0",
+        ] {
+            assert!(
+                matches!(MaskImage::from_pnm(bytes), Err(SceneError::Image(_))),
+                "{:?}",
+                String::from_utf8_lossy(bytes)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_headers() {
+        let huge = format!("P6 {} 2 255\n", usize::MAX / 2);
+        assert!(matches!(
+            MaskImage::from_pnm(huge.as_bytes()),
+            Err(SceneError::Image(_))
+        ));
+        let too_long = format!("P5 1 1{} 255\n", "0".repeat(30));
+        assert!(matches!(
+            MaskImage::from_pnm(too_long.as_bytes()),
+            Err(SceneError::Image(_))
+        ));
+    }
+}
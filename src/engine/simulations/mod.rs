@@ -1,5 +1,51 @@
+pub mod boundary;
+pub mod colormap;
+pub mod compressible_scene;
+pub mod compressible_simulation;
+pub mod diagnostics;
+pub mod euler3d_scene;
+pub mod euler3d_simulation;
+pub mod euler_scene;
 pub mod euler_simulation;
+pub mod flip_scene;
+pub mod flip_simulation;
+pub mod forces;
+pub mod grid;
+pub mod grid3d;
+pub mod image_mask;
+pub mod lbm_scene;
+pub mod lbm_simulation;
+pub mod obstacle;
+pub mod pressure;
+pub mod probe;
+pub mod real;
+pub mod shallow_water_scene;
+pub mod shallow_water_simulation;
+pub mod shedding;
 pub mod sph_simulation;
 
+pub use boundary::*;
+pub use colormap::*;
+pub use compressible_scene::*;
+pub use compressible_simulation::*;
+pub use diagnostics::*;
+pub use euler3d_scene::*;
+pub use euler3d_simulation::*;
+pub use euler_scene::*;
 pub use euler_simulation::*;
+pub use flip_scene::*;
+pub use flip_simulation::*;
+pub use forces::*;
+pub use grid::*;
+pub use grid3d::*;
+pub use image_mask::*;
+pub use lbm_scene::*;
+pub use lbm_simulation::*;
+pub use obstacle::*;
+pub use pressure::*;
+pub use probe::*;
+pub use real::*;
+pub use shallow_water_scene::*;
+pub use shallow_water_simulation::*;
+pub use shedding::*;
 pub use sph_simulation::*;
@@ -0,0 +1,400 @@
+use std::f32::consts::PI;
+
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// Points traced along each surface of an airfoil.
+const AIRFOIL_POINTS: usize = 64;
+
+/// Analytic solid shape, positioned in the units of its scene (see `SceneUnits`). In cell
+/// units cell `(i, j)` spans `[i, i + 1]` x `[j, j + 1]`, including the one cell border of
+/// the grid. Angles are in degrees, counter-clockwise.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "shape")]
+pub enum Obstacle {
+    Circle {
+        center: [f32; 2],
+        radius: f32,
+    },
+    Rectangle {
+        center: [f32; 2],
+        size: [f32; 2],
+        #[serde(default)]
+        angle: f32,
+    },
+    /// Closed polygon with `vertices` relative to `center`, rotated by `angle` around it.
+    Polygon {
+        center: [f32; 2],
+        vertices: Vec<[f32; 2]>,
+        #[serde(default)]
+        angle: f32,
+    },
+    Airfoil(NacaAirfoil),
+}
+
+impl Obstacle {
+    /// Signed distance from `p` to the boundary of the shape, negative inside.
+    pub fn signed_distance(&self, p: Vec2) -> f32 {
+        match self {
+            Obstacle::Circle { center, radius } => (p - Vec2::from(*center)).length() - radius,
+            Obstacle::Rectangle {
+                center,
+                size,
+                angle,
+            } => {
+                let q = to_local(p, *center, *angle).abs() - 0.5 * Vec2::from(*size);
+                q.max(Vec2::ZERO).length() + q.max_element().min(0.0)
+            }
+            Obstacle::Polygon {
+                center,
+                vertices,
+                angle,
+            } => polygon_distance(to_local(p, *center, *angle), vertices),
+            Obstacle::Airfoil(airfoil) => airfoil.polygon().signed_distance(p),
+        }
+    }
+
+    /// The shape with its anchor point moved by `position` and its dimensions scaled
+    /// by `length`, for changing the units it is described in.
+    pub fn mapped(&self, position: impl Fn([f32; 2]) -> [f32; 2], length: f32) -> Self {
+        let scale = |v: [f32; 2]| [v[0] * length, v[1] * length];
+        match self {
+            Obstacle::Circle { center, radius } => Obstacle::Circle {
+                center: position(*center),
+                radius: radius * length,
+            },
+            Obstacle::Rectangle {
+                center,
+                size,
+                angle,
+            } => Obstacle::Rectangle {
+                center: position(*center),
+                size: scale(*size),
+                angle: *angle,
+            },
+            Obstacle::Polygon {
+                center,
+                vertices,
+                angle,
+            } => Obstacle::Polygon {
+                center: position(*center),
+                vertices: vertices.iter().map(|v| scale(*v)).collect(),
+                angle: *angle,
+            },
+            Obstacle::Airfoil(airfoil) => Obstacle::Airfoil(NacaAirfoil {
+                position: position(airfoil.position),
+                chord: airfoil.chord * length,
+                ..*airfoil
+            }),
+        }
+    }
+}
+
+/// NACA 4-digit airfoil. It pitches around its quarter chord point `position`, a
+/// positive `angle_of_attack` (degrees) raising the nose into a flow along +x.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NacaAirfoil {
+    pub position: [f32; 2],
+    /// Chord length, in the units of `position`.
+    pub chord: f32,
+    /// Maximum camber as a fraction of the chord, the first digit over 100.
+    #[serde(default)]
+    pub camber: f32,
+    /// Chordwise location of the maximum camber, the second digit over 10.
+    #[serde(default)]
+    pub camber_position: f32,
+    /// Maximum thickness as a fraction of the chord, the last two digits over 100.
+    pub thickness: f32,
+    #[serde(default)]
+    pub angle_of_attack: f32,
+}
+
+impl NacaAirfoil {
+    /// Airfoil from a 4-digit designation such as `"2412"`.
+    pub fn naca(
+        designation: &str,
+        position: [f32; 2],
+        chord: f32,
+        angle_of_attack: f32,
+    ) -> Option<Self> {
+        let digits = designation
+            .chars()
+            .map(|c| c.to_digit(10).map(|d| d as f32))
+            .collect::<Option<Vec<_>>>()?;
+        let [m, p, t0, t1] = digits[..] else {
+            return None;
+        };
+
+        Some(Self {
+            position,
+            chord,
+            camber: m / 100.0,
+            camber_position: p / 10.0,
+            thickness: (10.0 * t0 + t1) / 100.0,
+            angle_of_attack,
+        })
+    }
+
+    /// Half thickness at chordwise fraction `x`, with a closed trailing edge.
+    fn half_thickness(&self, x: f32) -> f32 {
+        5.0 * self.thickness
+            * (0.2969 * x.sqrt() - 0.1260 * x - 0.3516 * x.powi(2) + 0.2843 * x.powi(3)
+                - 0.1036 * x.powi(4))
+    }
+
+    /// Camber line height and slope at chordwise fraction `x`.
+    fn camber_line(&self, x: f32) -> (f32, f32) {
+        let (m, p) = (self.camber, self.camber_position);
+        if m == 0.0 || p <= 0.0 || p >= 1.0 {
+            (0.0, 0.0)
+        } else if x < p {
+            (
+                m / p.powi(2) * (2.0 * p * x - x * x),
+                2.0 * m / p.powi(2) * (p - x),
+            )
+        } else {
+            let q = (1.0 - p).powi(2);
+            (
+                m / q * (1.0 - 2.0 * p + 2.0 * p * x - x * x),
+                2.0 * m / q * (p - x),
+            )
+        }
+    }
+
+    /// Outline relative to `position` at zero angle of attack, scaled by `chord`: the upper
+    /// surface from the trailing to the leading edge, then the lower one back.
+    pub fn outline(&self) -> Vec<[f32; 2]> {
+        let point = |k: usize, upper: bool| {
+            // cosine spacing clusters points at the leading and trailing edges
+            let x = 0.5 * (1.0 - (PI * k as f32 / AIRFOIL_POINTS as f32).cos());
+            let (yc, slope) = self.camber_line(x);
+            let (sin, cos) = slope.atan().sin_cos();
+            let yt = if upper {
+                self.half_thickness(x)
+            } else {
+                -self.half_thickness(x)
+            };
+            [
+                (x - yt * sin - 0.25) * self.chord,
+                (yc + yt * cos) * self.chord,
+            ]
+        };
+
+        let upper = (0..=AIRFOIL_POINTS).rev().map(|k| point(k, true));
+        let lower = (1..AIRFOIL_POINTS).map(|k| point(k, false));
+        upper.chain(lower).collect()
+    }
+
+    /// The airfoil as a polygon obstacle.
+    pub fn polygon(&self) -> Obstacle {
+        Obstacle::Polygon {
+            center: self.position,
+            vertices: self.outline(),
+            angle: -self.angle_of_attack,
+        }
+    }
+}
+
+/// `p` in the frame of a shape placed at `center` and rotated by `angle` degrees.
+fn to_local(p: Vec2, center: [f32; 2], angle: f32) -> Vec2 {
+    Vec2::from_angle(-angle.to_radians()).rotate(p - Vec2::from(center))
+}
+
+/// Signed distance to a closed polygon, negative inside (even-odd rule).
+fn polygon_distance(p: Vec2, vertices: &[[f32; 2]]) -> f32 {
+    let Some(last) = vertices.last() else {
+        return f32::INFINITY;
+    };
+
+    let mut distance = f32::INFINITY;
+    let mut inside = false;
+    let mut a = Vec2::from(*last);
+    for b in vertices.iter().map(|v| Vec2::from(*v)) {
+        let edge = b - a;
+        let t = ((p - a).dot(edge) / edge.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+        distance = distance.min((p - a - edge * t).length());
+
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * edge.x {
+            inside = !inside;
+        }
+        a = b;
+    }
+
+    if inside {
+        -distance
+    } else {
+        distance
+    }
+}
+
+/// Fraction of a unit cell left fluid by a boundary at signed distance `distance` from
+/// its centre, treating the boundary as a straight line through the cell.
+pub fn fluid_fraction(distance: f32) -> f32 {
+    (distance + 0.5).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "{} instead of {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn circle_distance_is_measured_from_the_rim() {
+        let circle = Obstacle::Circle {
+            center: [2.0, 3.0],
+            radius: 1.0,
+        };
+        assert_close(circle.signed_distance(Vec2::new(2.0, 3.0)), -1.0);
+        assert_close(circle.signed_distance(Vec2::new(2.0, 4.0)), 0.0);
+        assert_close(circle.signed_distance(Vec2::new(5.0, 7.0)), 4.0);
+    }
+
+    #[test]
+    fn rectangle_distance_follows_its_rotation() {
+        let rectangle = |angle| Obstacle::Rectangle {
+            center: [0.0, 0.0],
+            size: [4.0, 2.0],
+            angle,
+        };
+        let flat = rectangle(0.0);
+        assert_close(flat.signed_distance(Vec2::ZERO), -1.0);
+        assert_close(flat.signed_distance(Vec2::new(3.0, 0.0)), 1.0);
+        // nearest to the corner (2, 1)
+        assert_close(flat.signed_distance(Vec2::new(5.0, 5.0)), 5.0);
+
+        let upright = rectangle(90.0);
+        assert_close(upright.signed_distance(Vec2::new(0.0, 1.5)), -0.5);
+        assert_close(upright.signed_distance(Vec2::new(1.5, 0.0)), 0.5);
+    }
+
+    #[test]
+    fn polygon_matches_the_rectangle_it_outlines() {
+        let square = |angle| Obstacle::Polygon {
+            center: [1.0, -1.0],
+            vertices: vec![[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]],
+            angle,
+        };
+        let rectangle = |angle| Obstacle::Rectangle {
+            center: [1.0, -1.0],
+            size: [2.0, 2.0],
+            angle,
+        };
+        for angle in [0.0, 30.0, 45.0] {
+            for (x, y) in [
+                (1.0, -1.0),
+                (1.5, -0.5),
+                (2.5, -1.0),
+                (0.0, 1.0),
+                (3.0, -3.5),
+            ] {
+                let p = Vec2::new(x, y);
+                assert_close(
+                    square(angle).signed_distance(p),
+                    rectangle(angle).signed_distance(p),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mapping_moves_the_anchor_and_scales_the_size() {
+        let circle = Obstacle::Circle {
+            center: [1.0, 2.0],
+            radius: 0.5,
+        };
+        let mapped = circle.mapped(|[x, y]| [4.0 * x + 1.0, 4.0 * y + 1.0], 4.0);
+        assert_eq!(
+            mapped,
+            Obstacle::Circle {
+                center: [5.0, 9.0],
+                radius: 2.0
+            }
+        );
+    }
+
+    #[test]
+    fn fluid_fraction_is_linear_across_the_cell() {
+        assert_eq!(fluid_fraction(0.0), 0.5);
+        assert_eq!(fluid_fraction(0.25), 0.75);
+        assert_eq!(fluid_fraction(-0.5), 0.0);
+        assert_eq!(fluid_fraction(-2.0), 0.0);
+        assert_eq!(fluid_fraction(2.0), 1.0);
+    }
+
+    #[test]
+    fn naca_designation_gives_the_four_digits() {
+        let airfoil = NacaAirfoil::naca("2412", [0.0, 0.0], 1.0, 0.0).unwrap();
+        assert_close(airfoil.camber, 0.02);
+        assert_close(airfoil.camber_position, 0.4);
+        assert_close(airfoil.thickness, 0.12);
+        assert_eq!(NacaAirfoil::naca("012", [0.0, 0.0], 1.0, 0.0), None);
+        assert_eq!(NacaAirfoil::naca("00x2", [0.0, 0.0], 1.0, 0.0), None);
+    }
+
+    #[test]
+    fn naca_0012_is_twelve_percent_thick_at_thirty_percent_chord() {
+        let airfoil = NacaAirfoil::naca("0012", [0.0, 0.0], 2.0, 0.0).unwrap();
+        assert!((airfoil.half_thickness(0.3) - 0.06).abs() < 1e-4);
+
+        let outline = airfoil.outline();
+        let (top, x) = outline
+            .iter()
+            .map(|&[x, y]| (y, x))
+            .fold((f32::MIN, 0.0), |a, b| if b.0 > a.0 { b } else { a });
+        // the quarter chord point sits at the origin
+        assert!((x / 2.0 + 0.25 - 0.3).abs() < 0.02, "thickest at {}", x);
+        assert!((top - 0.12).abs() < 1e-3, "half thickness {}", top);
+    }
+
+    #[test]
+    fn symmetric_airfoil_mirrors_across_its_chord() {
+        let airfoil = NacaAirfoil::naca("0012", [0.0, 0.0], 1.0, 0.0).unwrap();
+        let outline = airfoil.outline();
+        for &[x, y] in &outline {
+            assert!(outline
+                .iter()
+                .any(|&[mx, my]| (mx - x).abs() < 1e-6 && (my + y).abs() < 1e-6));
+        }
+
+        let polygon = airfoil.polygon();
+        for (x, y) in [(-0.2, 0.01), (0.0, 0.05), (0.4, 0.02), (0.3, 0.2)] {
+            assert_close(
+                polygon.signed_distance(Vec2::new(x, y)),
+                polygon.signed_distance(Vec2::new(x, -y)),
+            );
+        }
+    }
+
+    #[test]
+    fn angle_of_attack_pitches_the_nose_up_around_the_quarter_chord() {
+        let position = Vec2::new(3.0, 2.0);
+        let level = NacaAirfoil::naca("2412", position.into(), 1.0, 0.0).unwrap();
+        let pitched = NacaAirfoil {
+            angle_of_attack: 10.0,
+            ..level
+        };
+        let (level, pitched) = (level.polygon(), pitched.polygon());
+
+        // the pitched shape is the level one turned clockwise
+        let turn = Vec2::from_angle(10f32.to_radians());
+        for (x, y) in [(-0.2, 0.01), (0.0, 0.05), (0.5, -0.02), (0.3, 0.2)] {
+            let p = Vec2::new(x, y);
+            assert_close(
+                pitched.signed_distance(position + p),
+                level.signed_distance(position + turn.rotate(p)),
+            );
+        }
+
+        let nose = 0.24 * Vec2::new(-10f32.to_radians().cos(), 10f32.to_radians().sin());
+        assert!(pitched.signed_distance(position + nose) < 0.0);
+        assert!(pitched.signed_distance(position + nose * Vec2::new(1.0, -1.0)) > 0.0);
+    }
+}
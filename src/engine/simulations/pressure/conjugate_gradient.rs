@@ -0,0 +1,184 @@
+use crate::{Convergence, PressureProblem, PressureSolver, Real, SolverStats};
+
+use super::{Laplacian, LinearOperator};
+
+const MIC_TUNING: f32 = 0.97;
+const MIC_SAFETY: f32 = 0.25;
+
+/// Conjugate Gradient preconditioned with modified incomplete Cholesky, MIC(0).
+pub struct ConjugateGradientSolver {
+    pub convergence: Convergence,
+}
+
+impl ConjugateGradientSolver {
+    pub fn new(convergence: Convergence) -> Self {
+        Self { convergence }
+    }
+
+    /// Coupling of `c` to `(i + 1, j)` when both cells are unknowns.
+    fn plus_i<T: Real>(lap: &Laplacian<T>, c: usize) -> T {
+        let n = lap.height;
+        if lap.unknown[c] && lap.unknown[c + n] {
+            lap.wx[c + n]
+        } else {
+            T::zero()
+        }
+    }
+
+    /// Coupling of `c` to `(i, j + 1)` when both cells are unknowns.
+    fn plus_j<T: Real>(lap: &Laplacian<T>, c: usize) -> T {
+        if lap.unknown[c] && lap.unknown[c + 1] {
+            lap.wy[c + 1]
+        } else {
+            T::zero()
+        }
+    }
+
+    fn mic_preconditioner<T: Real>(lap: &Laplacian<T>) -> Vec<T> {
+        let n = lap.height;
+        let tuning = T::from_f32(MIC_TUNING);
+        let safety = T::from_f32(MIC_SAFETY);
+        let mut precon = vec![T::zero(); lap.width * lap.height];
+
+        for i in 1..lap.width - 1 {
+            for j in 1..lap.height - 1 {
+                let c = i * n + j;
+                if !lap.unknown[c] {
+                    continue;
+                }
+
+                let pi = Self::plus_i(lap, c - n);
+                let pj = Self::plus_j(lap, c - 1);
+                let left = precon[c - n];
+                let below = precon[c - 1];
+
+                let mut e = lap.diag[c]
+                    - (pi * left).powi(2)
+                    - (pj * below).powi(2)
+                    - tuning
+                        * (pi * Self::plus_j(lap, c - n) * left * left
+                            + pj * Self::plus_i(lap, c - 1) * below * below);
+
+                if e < safety * lap.diag[c] {
+                    e = lap.diag[c];
+                }
+                precon[c] = if e > T::zero() {
+                    e.sqrt().recip()
+                } else {
+                    T::zero()
+                };
+            }
+        }
+
+        precon
+    }
+
+    /// Solves `M z = r` with the incomplete Cholesky factors.
+    fn apply_preconditioner<T: Real>(lap: &Laplacian<T>, precon: &[T], r: &[T], z: &mut [T]) {
+        let n = lap.height;
+        let mut q = vec![T::zero(); r.len()];
+
+        for i in 1..lap.width - 1 {
+            for j in 1..lap.height - 1 {
+                let c = i * n + j;
+                if !lap.unknown[c] {
+                    continue;
+                }
+                let t = r[c]
+                    + Self::plus_i(lap, c - n) * precon[c - n] * q[c - n]
+                    + Self::plus_j(lap, c - 1) * precon[c - 1] * q[c - 1];
+                q[c] = t * precon[c];
+            }
+        }
+
+        z.fill(T::zero());
+        for i in (1..lap.width - 1).rev() {
+            for j in (1..lap.height - 1).rev() {
+                let c = i * n + j;
+                if !lap.unknown[c] {
+                    continue;
+                }
+                let t = q[c]
+                    + Self::plus_i(lap, c) * precon[c] * z[c + n]
+                    + Self::plus_j(lap, c) * precon[c] * z[c + 1];
+                z[c] = t * precon[c];
+            }
+        }
+    }
+}
+
+/// Dot product accumulated in `f64`.
+fn dot<T: Real>(a: &[T], b: &[T]) -> T {
+    T::from_f64(a.iter().zip(b).map(|(a, b)| a.as_f64() * b.as_f64()).sum())
+}
+
+/// Preconditioned Conjugate Gradient on `lap`, starting from a zero guess.
+/// Returns the solution together with its statistics.
+pub(super) fn preconditioned_cg<T: Real>(
+    lap: &impl LinearOperator<T>,
+    rhs: Vec<T>,
+    convergence: &Convergence,
+    mut precondition: impl FnMut(&[T], &mut [T]),
+) -> (Vec<T>, SolverStats) {
+    let count = lap.unknown_count();
+    let measure = |r: &[T]| convergence.norm.measure(r, count);
+
+    let mut phi = vec![T::zero(); rhs.len()];
+    let mut r = rhs;
+    let initial_residual = measure(&r);
+    let mut residual = initial_residual;
+    let mut iterations = 0;
+
+    if convergence.is_converged(residual) {
+        return (phi, SolverStats::new(convergence, 0, residual, residual));
+    }
+
+    let mut z = vec![T::zero(); r.len()];
+    precondition(&r, &mut z);
+    let mut s = z.clone();
+    let mut sigma = dot(&z, &r);
+
+    while iterations < convergence.max_iterations {
+        lap.apply(&s, &mut z);
+        let denom = dot(&z, &s);
+        if denom == T::zero() {
+            break;
+        }
+        let alpha = sigma / denom;
+        iterations += 1;
+
+        for ((phi, r), (s, z)) in phi.iter_mut().zip(r.iter_mut()).zip(s.iter().zip(&z)) {
+            *phi += alpha * *s;
+            *r -= alpha * *z;
+        }
+
+        residual = measure(&r);
+        if convergence.is_converged(residual) {
+            break;
+        }
+
+        precondition(&r, &mut z);
+        let sigma_new = dot(&z, &r);
+        let beta = sigma_new / sigma;
+        for (s, z) in s.iter_mut().zip(&z) {
+            *s = *z + beta * *s;
+        }
+        sigma = sigma_new;
+    }
+
+    let stats = SolverStats::new(convergence, iterations, initial_residual, residual);
+    (phi, stats)
+}
+
+impl<T: Real> PressureSolver<T> for ConjugateGradientSolver {
+    fn solve(&mut self, problem: &mut PressureProblem<T>) -> SolverStats {
+        let lap = Laplacian::from_problem(problem);
+        let precon = Self::mic_preconditioner(&lap);
+        let (phi, stats) = preconditioned_cg(&lap, problem.rhs(), &self.convergence, |r, z| {
+            Self::apply_preconditioner(&lap, &precon, r, z)
+        });
+
+        problem.apply_potential(&phi);
+        stats
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{Convergence, PressureProblem, PressureSolver, Real, SolverStats};
+
+/// Over-relaxed Gauss-Seidel sweeps (SOR).
+pub struct GaussSeidelSolver {
+    pub convergence: Convergence,
+    pub over_relaxation: f32,
+}
+
+impl GaussSeidelSolver {
+    pub fn new(convergence: Convergence, over_relaxation: f32) -> Self {
+        Self {
+            convergence,
+            over_relaxation,
+        }
+    }
+}
+
+impl<T: Real> PressureSolver<T> for GaussSeidelSolver {
+    fn solve(&mut self, problem: &mut PressureProblem<T>) -> SolverStats {
+        let over_relaxation = T::from_f32(self.over_relaxation);
+        let norm = self.convergence.norm;
+        let initial_residual = problem.residual(norm);
+
+        let mut residual = initial_residual;
+        let mut iterations = 0;
+
+        while iterations < self.convergence.max_iterations
+            && !self.convergence.is_converged(residual)
+        {
+            for i in 1..problem.width() - 1 {
+                for j in 1..problem.height() - 1 {
+                    if !problem.is_unknown(i, j) {
+                        continue;
+                    }
+
+                    let sx0 = problem.solids[(i - 1, j)];
+                    let sx1 = problem.solids[(i + 1, j)];
+                    let sy0 = problem.solids[(i, j - 1)];
+                    let sy1 = problem.solids[(i, j + 1)];
+
+                    let s = sx0 + sx1 + sy0 + sy1;
+
+                    if s == T::zero() {
+                        continue;
+                    }
+
+                    let div = problem.divergence(i, j);
+
+                    let mut p = -div / s;
+                    p *= over_relaxation;
+                    problem.pressure[(i, j)] += problem.scale * p;
+
+                    problem.add_u(i, j, -sx0 * p);
+                    problem.add_u(i + 1, j, sx1 * p);
+                    problem.add_v(i, j, -sy0 * p);
+                    problem.add_v(i, j + 1, sy1 * p);
+                }
+            }
+
+            iterations += 1;
+            if self.convergence.tolerance.is_some() {
+                residual = problem.residual(norm);
+            }
+        }
+
+        if self.convergence.tolerance.is_none() {
+            residual = problem.residual(norm);
+        }
+
+        SolverStats::new(&self.convergence, iterations, initial_residual, residual)
+    }
+}
@@ -0,0 +1,511 @@
+mod conjugate_gradient;
+mod gauss_seidel;
+mod multigrid;
+mod problem_3d;
+
+pub use conjugate_gradient::*;
+pub use gauss_seidel::*;
+pub use multigrid::*;
+pub use problem_3d::*;
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Grid2D, Real};
+
+/// Staggered grid state handed to a `PressureSolver` for a single projection.
+///
+/// Cells on the domain border are never solved for; fluid border cells act as a zero
+/// pressure (open) boundary, like the air cells of a free surface flow.
+pub struct PressureProblem<'a, T> {
+    pub u: &'a mut Grid2D<T>,
+    pub v: &'a mut Grid2D<T>,
+    pub pressure: &'a mut Grid2D<T>,
+    pub solids: &'a Grid2D<T>,
+    /// Cells holding no liquid in a free surface flow, kept at zero pressure. `None` when
+    /// the fluid fills every non-solid cell.
+    pub air: Option<&'a Grid2D<bool>>,
+    /// Converts the solved velocity potential to pressure (`density * spacing / dt`).
+    pub scale: T,
+    /// Whether the x and y axes wrap around. The border cells of a periodic axis are
+    /// copies of the opposite interior column (row) and couple to it.
+    pub periodic: [bool; 2],
+}
+
+/// Maps border cell `k` of an axis with `size` cells onto the interior cell it copies
+/// when the axis is periodic.
+fn wrap(k: usize, size: usize, periodic: bool) -> usize {
+    if periodic && k == 0 {
+        size - 2
+    } else if periodic && k == size - 1 {
+        1
+    } else {
+        k
+    }
+}
+
+impl<T: Real> PressureProblem<'_, T> {
+    pub fn width(&self) -> usize {
+        self.solids.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.solids.height()
+    }
+
+    /// Offset of cell `(i, j)` in the flat vectors the solvers iterate on.
+    pub fn index(&self, i: usize, j: usize) -> usize {
+        self.solids.index(i, j)
+    }
+
+    /// Whether the pressure of cell `(i, j)` is an unknown of the linear system.
+    pub fn is_unknown(&self, i: usize, j: usize) -> bool {
+        i > 0
+            && j > 0
+            && i < self.width() - 1
+            && j < self.height() - 1
+            && self.solids[(i, j)] != T::zero()
+            && !self.air.is_some_and(|air| air[(i, j)])
+    }
+
+    pub fn unknown_count(&self) -> usize {
+        self.solids
+            .interior()
+            .filter(|&(i, j)| self.is_unknown(i, j))
+            .count()
+    }
+
+    /// Remaining divergence over all unknown cells, measured with `norm`.
+    pub fn residual(&self, norm: ResidualNorm) -> T {
+        norm.measure(&self.rhs(), self.unknown_count())
+    }
+
+    /// Adds `delta` to the face `u[i, j]` and to its periodic twin on the opposite edge.
+    pub fn add_u(&mut self, i: usize, j: usize, delta: T) {
+        let width = self.width();
+        self.u[(i, j)] += delta;
+        if self.periodic[0] && (i == 1 || i == width - 1) {
+            self.u[(width - i, j)] += delta;
+        }
+    }
+
+    /// Adds `delta` to the face `v[i, j]` and to its periodic twin on the opposite edge.
+    pub fn add_v(&mut self, i: usize, j: usize, delta: T) {
+        let height = self.height();
+        self.v[(i, j)] += delta;
+        if self.periodic[1] && (j == 1 || j == height - 1) {
+            self.v[(i, height - j)] += delta;
+        }
+    }
+
+    pub fn divergence(&self, i: usize, j: usize) -> T {
+        self.u[(i + 1, j)] - self.u[(i, j)] + self.v[(i, j + 1)] - self.v[(i, j)]
+    }
+
+    /// Coefficient of the face between cell `(i, j)` and its neighbour `(ni, nj)`.
+    fn face_weight(&self, i: usize, j: usize, ni: usize, nj: usize) -> T {
+        self.solids[(i, j)] * self.solids[(ni, nj)]
+    }
+
+    /// Right hand side `-div` of the pressure equation for every unknown cell.
+    fn rhs(&self) -> Vec<T> {
+        let mut rhs = vec![T::zero(); self.solids.len()];
+        for (i, j) in self.solids.interior() {
+            if self.is_unknown(i, j) {
+                rhs[self.index(i, j)] = -self.divergence(i, j);
+            }
+        }
+        rhs
+    }
+
+    /// Subtracts the gradient of the potential `phi` from the face velocities and
+    /// stores the resulting pressure.
+    fn apply_potential(&mut self, phi: &[T]) {
+        let (solids, periodic) = (self.solids, self.periodic);
+        let (width, height) = (solids.width(), solids.height());
+        let phi_at = |i: usize, j: usize| {
+            let i = wrap(i, width, periodic[0]);
+            let j = wrap(j, height, periodic[1]);
+            phi[solids.index(i, j)]
+        };
+
+        for i in 1..width {
+            for j in 1..height {
+                if self.is_unknown(i, j) || self.is_unknown(i - 1, j) {
+                    let w = self.face_weight(i, j, i - 1, j);
+                    self.u[(i, j)] -= w * (phi_at(i, j) - phi_at(i - 1, j));
+                }
+                if self.is_unknown(i, j) || self.is_unknown(i, j - 1) {
+                    let w = self.face_weight(i, j, i, j - 1);
+                    self.v[(i, j)] -= w * (phi_at(i, j) - phi_at(i, j - 1));
+                }
+            }
+        }
+
+        for (pressure, phi) in self.pressure.iter_mut().zip(phi) {
+            *pressure = self.scale * *phi;
+        }
+    }
+}
+
+/// Face coefficients of the pressure Laplacian. `wx` holds the face between `(i - 1, j)`
+/// and `(i, j)`, `wy` the face between `(i, j - 1)` and `(i, j)`.
+struct Laplacian<T> {
+    width: usize,
+    height: usize,
+    periodic: [bool; 2],
+    unknown: Vec<bool>,
+    wx: Vec<T>,
+    wy: Vec<T>,
+    diag: Vec<T>,
+}
+
+impl<T: Real> Laplacian<T> {
+    fn from_problem(problem: &PressureProblem<T>) -> Self {
+        let (width, height) = (problem.width(), problem.height());
+        let cells_num = width * height;
+
+        let unknown: Vec<bool> = problem
+            .solids
+            .cells()
+            .map(|(i, j)| problem.is_unknown(i, j))
+            .collect();
+        let mut wx = vec![T::zero(); cells_num];
+        let mut wy = vec![T::zero(); cells_num];
+
+        for (i, j) in problem.solids.cells().filter(|&(i, j)| i > 0 && j > 0) {
+            let c = problem.index(i, j);
+            if unknown[c] || unknown[problem.index(i - 1, j)] {
+                wx[c] = problem.face_weight(i, j, i - 1, j);
+            }
+            if unknown[c] || unknown[problem.index(i, j - 1)] {
+                wy[c] = problem.face_weight(i, j, i, j - 1);
+            }
+        }
+
+        Self::with_faces(width, height, problem.periodic, unknown, wx, wy)
+    }
+
+    fn with_faces(
+        width: usize,
+        height: usize,
+        periodic: [bool; 2],
+        unknown: Vec<bool>,
+        wx: Vec<T>,
+        wy: Vec<T>,
+    ) -> Self {
+        let n = height;
+        let mut diag = vec![T::zero(); width * height];
+        for i in 0..width {
+            for j in 0..height {
+                let c = i * n + j;
+                if !unknown[c] {
+                    continue;
+                }
+                diag[c] = wx[c] + wy[c];
+                if i + 1 < width {
+                    diag[c] += wx[c + n];
+                }
+                if j + 1 < height {
+                    diag[c] += wy[c + 1];
+                }
+            }
+        }
+
+        Self {
+            width,
+            height,
+            periodic,
+            unknown,
+            wx,
+            wy,
+            diag,
+        }
+    }
+
+    /// Value of `x` at cell `(i, j)` seen through the periodic edges, zero unless the
+    /// cell is an unknown.
+    fn value(&self, x: &[T], i: usize, j: usize) -> T {
+        let i = wrap(i, self.width, self.periodic[0]);
+        let j = wrap(j, self.height, self.periodic[1]);
+        let c = i * self.height + j;
+        if self.unknown[c] {
+            x[c]
+        } else {
+            T::zero()
+        }
+    }
+
+    /// Sum of the off-diagonal couplings of `c`, weighted by `x`.
+    fn neighbours(&self, x: &[T], c: usize) -> T {
+        let n = self.height;
+        let (i, j) = (c / n, c % n);
+        let mut sum = T::zero();
+        if i > 0 {
+            sum += self.wx[c] * self.value(x, i - 1, j);
+        }
+        if i + 1 < self.width {
+            sum += self.wx[c + n] * self.value(x, i + 1, j);
+        }
+        if j > 0 {
+            sum += self.wy[c] * self.value(x, i, j - 1);
+        }
+        if j + 1 < self.height {
+            sum += self.wy[c + 1] * self.value(x, i, j + 1);
+        }
+        sum
+    }
+
+    /// `out = A * x` for every unknown cell.
+    fn apply(&self, x: &[T], out: &mut [T]) {
+        for c in 0..self.width * self.height {
+            out[c] = if self.unknown[c] {
+                self.diag[c] * x[c] - self.neighbours(x, c)
+            } else {
+                T::zero()
+            };
+        }
+    }
+
+    /// `out = b - A * x` for every unknown cell.
+    fn residual(&self, x: &[T], b: &[T], out: &mut [T]) {
+        self.apply(x, out);
+        for (r, b) in out.iter_mut().zip(b) {
+            *r = *b - *r;
+        }
+    }
+}
+
+/// Symmetric pressure matrix the Conjugate Gradient iterates on.
+trait LinearOperator<T> {
+    /// Number of unknowns, for averaging the residual norms.
+    fn unknown_count(&self) -> usize;
+
+    /// `out = A * x` for every unknown cell, zero elsewhere.
+    fn apply(&self, x: &[T], out: &mut [T]);
+}
+
+impl<T: Real> LinearOperator<T> for Laplacian<T> {
+    fn unknown_count(&self) -> usize {
+        self.unknown.iter().filter(|unknown| **unknown).count()
+    }
+
+    fn apply(&self, x: &[T], out: &mut [T]) {
+        Laplacian::apply(self, x, out)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResidualNorm {
+    /// Largest absolute cell divergence.
+    #[default]
+    Max,
+    /// Root mean square of the cell divergence (discrete L2 norm).
+    L2,
+}
+
+impl ResidualNorm {
+    /// Measures `residuals`, of which `count` belong to unknown cells (others are zero).
+    pub fn measure<T: Real>(self, residuals: &[T], count: usize) -> T {
+        match self {
+            ResidualNorm::Max => residuals.iter().fold(T::zero(), |m, r| m.max(r.abs())),
+            ResidualNorm::L2 => {
+                let sum: f64 = residuals.iter().map(|r| r.as_f64().powi(2)).sum();
+                T::from_f64((sum / count.max(1) as f64).sqrt())
+            }
+        }
+    }
+}
+
+/// When a pressure solver stops iterating.
+///
+/// Without a `tolerance` exactly `max_iterations` iterations are run. With one, the
+/// solver stops as soon as the residual measured with `norm` drops below it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Convergence {
+    pub max_iterations: usize,
+    pub tolerance: Option<f32>,
+    pub norm: ResidualNorm,
+}
+
+impl Default for Convergence {
+    fn default() -> Self {
+        Self::fixed(20)
+    }
+}
+
+impl Convergence {
+    pub fn fixed(iterations: usize) -> Self {
+        Self {
+            max_iterations: iterations,
+            tolerance: None,
+            norm: ResidualNorm::Max,
+        }
+    }
+
+    pub fn tolerance(max_iterations: usize, tolerance: f32, norm: ResidualNorm) -> Self {
+        Self {
+            max_iterations,
+            tolerance: Some(tolerance),
+            norm,
+        }
+    }
+
+    pub fn is_converged<T: Real>(&self, residual: T) -> bool {
+        self.tolerance
+            .is_some_and(|tolerance| residual <= T::from_f32(tolerance))
+    }
+}
+
+/// Statistics of a single pressure projection, with residuals in `f32` whatever the
+/// precision of the solve.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SolverStats {
+    pub iterations: usize,
+    pub initial_residual: f32,
+    pub final_residual: f32,
+    /// False only when a tolerance was requested and not reached.
+    pub converged: bool,
+    pub wall_time: Duration,
+}
+
+impl SolverStats {
+    fn new<T: Real>(
+        convergence: &Convergence,
+        iterations: usize,
+        initial_residual: T,
+        final_residual: T,
+    ) -> Self {
+        Self {
+            iterations,
+            initial_residual: initial_residual.as_f32(),
+            final_residual: final_residual.as_f32(),
+            converged: convergence.tolerance.is_none() || convergence.is_converged(final_residual),
+            wall_time: Duration::ZERO,
+        }
+    }
+}
+
+/// Enforces incompressibility by solving for pressure and correcting `u` and `v`.
+pub trait PressureSolver<T: Real> {
+    fn solve(&mut self, problem: &mut PressureProblem<T>) -> SolverStats;
+}
+
+/// Serializable choice of pressure solver, used by `EulerScene`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum PressureSolverConfig {
+    GaussSeidel {
+        over_relaxation: f32,
+        #[serde(default)]
+        convergence: Convergence,
+    },
+    ConjugateGradient {
+        #[serde(default)]
+        convergence: Convergence,
+    },
+    Multigrid {
+        smoothing_steps: usize,
+        #[serde(default)]
+        convergence: Convergence,
+    },
+}
+
+impl Default for PressureSolverConfig {
+    fn default() -> Self {
+        PressureSolverConfig::GaussSeidel {
+            over_relaxation: 1.9,
+            convergence: Convergence::fixed(20),
+        }
+    }
+}
+
+impl PressureSolverConfig {
+    pub fn build<T: Real>(&self) -> Box<dyn PressureSolver<T>> {
+        match *self {
+            PressureSolverConfig::GaussSeidel {
+                over_relaxation,
+                convergence,
+            } => Box::new(GaussSeidelSolver::new(convergence, over_relaxation)),
+            PressureSolverConfig::ConjugateGradient { convergence } => {
+                Box::new(ConjugateGradientSolver::new(convergence))
+            }
+            PressureSolverConfig::Multigrid {
+                smoothing_steps,
+                convergence,
+            } => Box::new(MultigridSolver::new(convergence, smoothing_steps)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GridLocation;
+
+    const SIZE: usize = 24;
+    const TOLERANCE: f32 = 1e-8;
+
+    /// Projects a swirling flow around a block in a box open at the top with `solver`,
+    /// and returns the largest cell divergence before and after.
+    fn project(solver: &mut dyn PressureSolver<f64>) -> (f64, f64) {
+        let n = SIZE;
+        let mut solids = Grid2D::new(n, n, GridLocation::CellCenter, 1.0);
+        for (i, j) in solids.cells() {
+            let walls = i == 0 || j == 0 || i == n - 1;
+            let block = (8..12).contains(&i) && (10..16).contains(&j);
+            if walls || block {
+                solids[(i, j)] = 0.0;
+            }
+        }
+        let field = |location: GridLocation, a: f64, b: f64| {
+            let mut field = Grid2D::new(n, n, location, 0.0);
+            for (i, j) in solids.cells() {
+                field[(i, j)] = (i as f64 * a).sin() * (j as f64 * b).cos();
+            }
+            field
+        };
+        let mut u = field(GridLocation::UFace, 0.3, 0.5);
+        let mut v = field(GridLocation::VFace, 0.7, 0.2);
+        let mut pressure = Grid2D::new(n, n, GridLocation::CellCenter, 0.0);
+
+        let mut problem = PressureProblem {
+            u: &mut u,
+            v: &mut v,
+            pressure: &mut pressure,
+            solids: &solids,
+            air: None,
+            scale: 1.0,
+            periodic: [false; 2],
+        };
+        let before = problem.residual(ResidualNorm::Max);
+        solver.solve(&mut problem);
+        (before, problem.residual(ResidualNorm::Max))
+    }
+
+    fn assert_projected(solver: &mut dyn PressureSolver<f64>) {
+        let (before, after) = project(solver);
+        assert!(before > 0.1, "divergence {before} before the projection");
+        assert!(after < 10.0 * TOLERANCE as f64, "divergence {after} left");
+    }
+
+    #[test]
+    fn gauss_seidel_removes_divergence() {
+        let convergence = Convergence::tolerance(5000, TOLERANCE, ResidualNorm::Max);
+        assert_projected(&mut GaussSeidelSolver::new(convergence, 1.9));
+    }
+
+    #[test]
+    fn conjugate_gradient_removes_divergence() {
+        let convergence = Convergence::tolerance(200, TOLERANCE, ResidualNorm::Max);
+        assert_projected(&mut ConjugateGradientSolver::new(convergence));
+    }
+
+    #[test]
+    fn multigrid_removes_divergence() {
+        let convergence = Convergence::tolerance(50, TOLERANCE, ResidualNorm::Max);
+        assert_projected(&mut MultigridSolver::new(convergence, 2));
+    }
+}
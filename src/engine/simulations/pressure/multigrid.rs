@@ -0,0 +1,187 @@
+use crate::{Convergence, PressureProblem, PressureSolver, Real, SolverStats};
+
+use super::{conjugate_gradient::preconditioned_cg, Laplacian};
+
+const COARSEST_SIZE: usize = 4;
+const COARSEST_SWEEPS: usize = 50;
+
+/// Scratch vectors of one level of the hierarchy, the size of its operator.
+struct Level<T> {
+    phi: Vec<T>,
+    rhs: Vec<T>,
+    residual: Vec<T>,
+}
+
+impl<T: Real> Level<T> {
+    fn new(lap: &Laplacian<T>) -> Self {
+        let cells_num = lap.width * lap.height;
+        Self {
+            phi: vec![T::zero(); cells_num],
+            rhs: vec![T::zero(); cells_num],
+            residual: vec![T::zero(); cells_num],
+        }
+    }
+
+    /// Red-black Gauss-Seidel sweeps with the operator `lap` of this level. `reverse`
+    /// swaps the colour order, which keeps the V-cycle symmetric when it is used as a
+    /// preconditioner.
+    fn smooth(&mut self, lap: &Laplacian<T>, sweeps: usize, reverse: bool) {
+        let n = lap.height;
+        let colors = if reverse { [1, 0] } else { [0, 1] };
+        for _ in 0..sweeps {
+            for color in colors {
+                for i in 0..lap.width {
+                    for j in ((i + color) % 2..lap.height).step_by(2) {
+                        let c = i * n + j;
+                        if lap.unknown[c] && lap.diag[c] > T::zero() {
+                            self.phi[c] =
+                                (self.rhs[c] + lap.neighbours(&self.phi, c)) / lap.diag[c];
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T: Real> Laplacian<T> {
+    /// Galerkin coarse operator for piecewise constant interpolation onto a grid with
+    /// twice the spacing. Periodic couplings are dropped, the coarse levels only
+    /// precondition the fine one.
+    fn coarsen(&self) -> Laplacian<T> {
+        let (width, height) = (self.width.div_ceil(2), self.height.div_ceil(2));
+        let (n, cn) = (self.height, height);
+
+        let mut unknown = vec![false; width * height];
+        let mut wx = vec![T::zero(); width * height];
+        let mut wy = vec![T::zero(); width * height];
+        let mut diag = vec![T::zero(); width * height];
+        let two = T::from_f32(2.0);
+
+        for i in 0..self.width {
+            for j in 0..self.height {
+                let c = i * n + j;
+                if !self.unknown[c] {
+                    continue;
+                }
+                let cc = (i / 2) * cn + j / 2;
+                unknown[cc] = true;
+                diag[cc] += self.diag[c];
+
+                if i > 0 && self.unknown[c - n] {
+                    if i % 2 == 0 {
+                        wx[cc] += self.wx[c];
+                    } else {
+                        diag[cc] -= two * self.wx[c];
+                    }
+                }
+                if j > 0 && self.unknown[c - 1] {
+                    if j % 2 == 0 {
+                        wy[cc] += self.wy[c];
+                    } else {
+                        diag[cc] -= two * self.wy[c];
+                    }
+                }
+            }
+        }
+
+        Laplacian {
+            width,
+            height,
+            periodic: [false; 2],
+            unknown,
+            wx,
+            wy,
+            diag,
+        }
+    }
+}
+
+/// Conjugate Gradient preconditioned with a geometric multigrid V-cycle (MGPCG).
+///
+/// Coarse levels use the Galerkin operator of piecewise constant interpolation, which
+/// keeps the V-cycle symmetric positive definite on arbitrary solid masks.
+pub struct MultigridSolver {
+    pub convergence: Convergence,
+    pub smoothing_steps: usize,
+}
+
+impl MultigridSolver {
+    pub fn new(convergence: Convergence, smoothing_steps: usize) -> Self {
+        Self {
+            convergence,
+            smoothing_steps,
+        }
+    }
+
+    /// Operators from the fine one `fine` down to the coarsest level.
+    fn build_operators<T: Real>(fine: Laplacian<T>) -> Vec<Laplacian<T>> {
+        let mut laps = vec![fine];
+        loop {
+            let lap = &laps[laps.len() - 1];
+            if lap.width <= COARSEST_SIZE || lap.height <= COARSEST_SIZE {
+                break;
+            }
+            let coarse = lap.coarsen();
+            laps.push(coarse);
+        }
+        laps
+    }
+
+    /// Improves `levels[0].phi` for `levels[0].rhs` with one V-cycle over the operators
+    /// `laps` of the levels.
+    fn v_cycle<T: Real>(&self, laps: &[Laplacian<T>], levels: &mut [Level<T>]) {
+        let (lap, coarse_laps) = laps.split_first().unwrap();
+        let (fine, coarser) = levels.split_first_mut().unwrap();
+
+        let (Some(coarse_lap), Some(coarse)) = (coarse_laps.first(), coarser.first_mut()) else {
+            fine.smooth(lap, COARSEST_SWEEPS, false);
+            fine.smooth(lap, COARSEST_SWEEPS, true);
+            return;
+        };
+
+        fine.smooth(lap, self.smoothing_steps, false);
+
+        lap.residual(&fine.phi, &fine.rhs, &mut fine.residual);
+        let (n, cn) = (lap.height, coarse_lap.height);
+        coarse.rhs.fill(T::zero());
+        coarse.phi.fill(T::zero());
+        for i in 0..lap.width {
+            for j in 0..lap.height {
+                coarse.rhs[(i / 2) * cn + j / 2] += fine.residual[i * n + j];
+            }
+        }
+
+        self.v_cycle(coarse_laps, coarser);
+
+        let coarse = &coarser[0];
+        for i in 0..lap.width {
+            for j in 0..lap.height {
+                let c = i * n + j;
+                if lap.unknown[c] {
+                    fine.phi[c] += coarse.phi[(i / 2) * cn + j / 2];
+                }
+            }
+        }
+
+        fine.smooth(lap, self.smoothing_steps, true);
+    }
+}
+
+impl<T: Real> PressureSolver<T> for MultigridSolver {
+    fn solve(&mut self, problem: &mut PressureProblem<T>) -> SolverStats {
+        // the fine operator serves both the outer iteration and the top of the V-cycle
+        let laps = Self::build_operators(Laplacian::from_problem(problem));
+        let mut levels: Vec<_> = laps.iter().map(Level::new).collect();
+
+        let (phi, stats) = preconditioned_cg(&laps[0], problem.rhs(), &self.convergence, |r, z| {
+            levels[0].rhs.copy_from_slice(r);
+            levels[0].phi.fill(T::zero());
+            self.v_cycle(&laps, &mut levels);
+            z.copy_from_slice(&levels[0].phi);
+        });
+
+        problem.apply_potential(&phi);
+        stats
+    }
+}
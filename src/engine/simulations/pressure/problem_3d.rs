@@ -0,0 +1,333 @@
+use tracing::warn;
+
+use crate::{
+    ConjugateGradientSolver, GaussSeidelSolver, PressureSolverConfig, Real, ResidualNorm,
+    SolverStats,
+};
+
+use super::{conjugate_gradient::preconditioned_cg, LinearOperator};
+
+const MIC_TUNING: f32 = 0.97;
+const MIC_SAFETY: f32 = 0.25;
+
+/// Staggered 3D grid state handed to a `PressureSolver3D` for a single projection.
+///
+/// Fields are indexed with `(i * height + j) * depth + k`. As in `PressureProblem`,
+/// cells on the domain border are never solved for and fluid border cells act as a
+/// zero pressure (open) boundary.
+pub struct PressureProblem3D<'a, T> {
+    pub width: usize,
+    pub height: usize,
+    pub depth: usize,
+    pub u: &'a mut [T],
+    pub v: &'a mut [T],
+    pub w: &'a mut [T],
+    pub pressure: &'a mut [T],
+    pub solids: &'a [T],
+    /// Converts the solved velocity potential to pressure (`density * spacing / dt`).
+    pub scale: T,
+}
+
+impl<T: Real> PressureProblem3D<'_, T> {
+    pub fn index(&self, i: usize, j: usize, k: usize) -> usize {
+        (i * self.height + j) * self.depth + k
+    }
+
+    /// Distances in the flat storage between neighbours along x, y and z.
+    pub fn strides(&self) -> [usize; 3] {
+        [self.height * self.depth, self.depth, 1]
+    }
+
+    /// Whether the pressure of cell `(i, j, k)` is an unknown of the linear system.
+    pub fn is_unknown(&self, i: usize, j: usize, k: usize) -> bool {
+        i > 0
+            && j > 0
+            && k > 0
+            && i < self.width - 1
+            && j < self.height - 1
+            && k < self.depth - 1
+            && self.solids[self.index(i, j, k)] != T::zero()
+    }
+
+    /// Unknown cells, in storage order.
+    fn unknowns(&self) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        (1..self.width - 1).flat_map(move |i| {
+            (1..self.height - 1).flat_map(move |j| {
+                (1..self.depth - 1)
+                    .map(move |k| (i, j, k))
+                    .filter(move |&(i, j, k)| self.is_unknown(i, j, k))
+            })
+        })
+    }
+
+    pub fn unknown_count(&self) -> usize {
+        self.unknowns().count()
+    }
+
+    /// Remaining divergence over all unknown cells, measured with `norm`.
+    pub fn residual(&self, norm: ResidualNorm) -> T {
+        norm.measure(&self.rhs(), self.unknown_count())
+    }
+
+    pub fn divergence(&self, i: usize, j: usize, k: usize) -> T {
+        let c = self.index(i, j, k);
+        let [sx, sy, sz] = self.strides();
+        self.u[c + sx] - self.u[c] + self.v[c + sy] - self.v[c] + self.w[c + sz] - self.w[c]
+    }
+
+    /// Right hand side `-div` of the pressure equation for every unknown cell.
+    fn rhs(&self) -> Vec<T> {
+        let mut rhs = vec![T::zero(); self.solids.len()];
+        for (i, j, k) in self.unknowns() {
+            rhs[self.index(i, j, k)] = -self.divergence(i, j, k);
+        }
+        rhs
+    }
+
+    /// Subtracts the gradient of the potential `phi` from the face velocities and
+    /// stores the resulting pressure.
+    fn apply_potential(&mut self, phi: &[T], lap: &Laplacian3D<T>) {
+        let strides = self.strides();
+        for c in 0..self.solids.len() {
+            for (axis, &stride) in strides.iter().enumerate() {
+                let weight = lap.faces[axis][c];
+                if weight == T::zero() {
+                    continue;
+                }
+                let value = |c: usize| if lap.unknown[c] { phi[c] } else { T::zero() };
+                let delta = weight * (value(c) - value(c - stride));
+                match axis {
+                    0 => self.u[c] -= delta,
+                    1 => self.v[c] -= delta,
+                    _ => self.w[c] -= delta,
+                }
+            }
+        }
+
+        for (pressure, phi) in self.pressure.iter_mut().zip(phi) {
+            *pressure = self.scale * *phi;
+        }
+    }
+}
+
+/// Face coefficients of the 3D pressure Laplacian. `faces[axis][c]` holds the face
+/// between cell `c` and its lower neighbour along `axis`, and is only set next to an
+/// unknown cell.
+struct Laplacian3D<T> {
+    strides: [usize; 3],
+    unknown: Vec<bool>,
+    faces: [Vec<T>; 3],
+    diag: Vec<T>,
+    /// Coupling of each unknown to the next cell along each axis when that cell is an
+    /// unknown too, zero otherwise.
+    plus: Vec<[T; 3]>,
+}
+
+impl<T: Real> Laplacian3D<T> {
+    fn from_problem(problem: &PressureProblem3D<T>) -> Self {
+        let cells_num = problem.solids.len();
+        let strides = problem.strides();
+
+        let mut unknown = vec![false; cells_num];
+        for (i, j, k) in problem.unknowns() {
+            unknown[problem.index(i, j, k)] = true;
+        }
+
+        let mut faces = [
+            vec![T::zero(); cells_num],
+            vec![T::zero(); cells_num],
+            vec![T::zero(); cells_num],
+        ];
+        let mut diag = vec![T::zero(); cells_num];
+        for c in (0..cells_num).filter(|&c| unknown[c]) {
+            // unknown cells are interior, all six neighbours exist
+            for (axis, &stride) in strides.iter().enumerate() {
+                for (face, neighbour) in [(c, c - stride), (c + stride, c + stride)] {
+                    let weight = problem.solids[c] * problem.solids[neighbour];
+                    faces[axis][face] = weight;
+                    diag[c] += weight;
+                }
+            }
+        }
+
+        let mut plus = vec![[T::zero(); 3]; cells_num];
+        for c in (0..cells_num).filter(|&c| unknown[c]) {
+            for (axis, &stride) in strides.iter().enumerate() {
+                if unknown[c + stride] {
+                    plus[c][axis] = faces[axis][c + stride];
+                }
+            }
+        }
+
+        Self {
+            strides,
+            unknown,
+            faces,
+            diag,
+            plus,
+        }
+    }
+
+    /// Modified incomplete Cholesky, MIC(0), over the unknowns in storage order.
+    fn mic_preconditioner(&self) -> Vec<T> {
+        let tuning = T::from_f32(MIC_TUNING);
+        let safety = T::from_f32(MIC_SAFETY);
+        let mut precon = vec![T::zero(); self.unknown.len()];
+
+        for c in (0..self.unknown.len()).filter(|&c| self.unknown[c]) {
+            let mut e = self.diag[c];
+            for axis in 0..3 {
+                let before = c - self.strides[axis];
+                let coupling = self.plus[before][axis];
+                let others: T = (0..3)
+                    .filter(|&other| other != axis)
+                    .map(|other| self.plus[before][other])
+                    .sum();
+                let p = precon[before];
+                e -= (coupling * p).powi(2) + tuning * coupling * others * p * p;
+            }
+
+            if e < safety * self.diag[c] {
+                e = self.diag[c];
+            }
+            precon[c] = if e > T::zero() {
+                e.sqrt().recip()
+            } else {
+                T::zero()
+            };
+        }
+        precon
+    }
+
+    /// Solves `M z = r` with the incomplete Cholesky factors.
+    fn apply_preconditioner(&self, precon: &[T], r: &[T], z: &mut [T]) {
+        let cells_num = self.unknown.len();
+        let mut q = vec![T::zero(); cells_num];
+        for c in (0..cells_num).filter(|&c| self.unknown[c]) {
+            let mut t = r[c];
+            for axis in 0..3 {
+                let before = c - self.strides[axis];
+                t += self.plus[before][axis] * precon[before] * q[before];
+            }
+            q[c] = t * precon[c];
+        }
+
+        z.fill(T::zero());
+        for c in (0..cells_num).rev().filter(|&c| self.unknown[c]) {
+            let mut t = q[c];
+            for axis in 0..3 {
+                t += self.plus[c][axis] * precon[c] * z[c + self.strides[axis]];
+            }
+            z[c] = t * precon[c];
+        }
+    }
+}
+
+impl<T: Real> LinearOperator<T> for Laplacian3D<T> {
+    fn unknown_count(&self) -> usize {
+        self.unknown.iter().filter(|unknown| **unknown).count()
+    }
+
+    fn apply(&self, x: &[T], out: &mut [T]) {
+        let value = |c: usize| if self.unknown[c] { x[c] } else { T::zero() };
+        for c in 0..self.unknown.len() {
+            out[c] = T::zero();
+            if !self.unknown[c] {
+                continue;
+            }
+            let mut sum = T::zero();
+            for (axis, &stride) in self.strides.iter().enumerate() {
+                sum += self.faces[axis][c] * value(c - stride);
+                sum += self.faces[axis][c + stride] * value(c + stride);
+            }
+            out[c] = self.diag[c] * x[c] - sum;
+        }
+    }
+}
+
+/// Enforces incompressibility on a 3D grid by solving for pressure and correcting
+/// `u`, `v` and `w`.
+pub trait PressureSolver3D<T: Real> {
+    fn solve(&mut self, problem: &mut PressureProblem3D<T>) -> SolverStats;
+}
+
+impl<T: Real> PressureSolver3D<T> for GaussSeidelSolver {
+    fn solve(&mut self, problem: &mut PressureProblem3D<T>) -> SolverStats {
+        let over_relaxation = T::from_f32(self.over_relaxation);
+        let norm = self.convergence.norm;
+        let strides = problem.strides();
+        let cells: Vec<_> = problem.unknowns().collect();
+        let initial_residual = problem.residual(norm);
+
+        let mut residual = initial_residual;
+        let mut iterations = 0;
+
+        while iterations < self.convergence.max_iterations
+            && !self.convergence.is_converged(residual)
+        {
+            for &(i, j, k) in &cells {
+                let c = problem.index(i, j, k);
+                let before = strides.map(|stride| problem.solids[c - stride]);
+                let after = strides.map(|stride| problem.solids[c + stride]);
+                let s = before.iter().chain(&after).fold(T::zero(), |s, &w| s + w);
+                if s == T::zero() {
+                    continue;
+                }
+
+                let p = -problem.divergence(i, j, k) / s * over_relaxation;
+                problem.pressure[c] += problem.scale * p;
+
+                problem.u[c] -= before[0] * p;
+                problem.u[c + strides[0]] += after[0] * p;
+                problem.v[c] -= before[1] * p;
+                problem.v[c + strides[1]] += after[1] * p;
+                problem.w[c] -= before[2] * p;
+                problem.w[c + strides[2]] += after[2] * p;
+            }
+
+            iterations += 1;
+            if self.convergence.tolerance.is_some() {
+                residual = problem.residual(norm);
+            }
+        }
+
+        if self.convergence.tolerance.is_none() {
+            residual = problem.residual(norm);
+        }
+
+        SolverStats::new(&self.convergence, iterations, initial_residual, residual)
+    }
+}
+
+impl<T: Real> PressureSolver3D<T> for ConjugateGradientSolver {
+    fn solve(&mut self, problem: &mut PressureProblem3D<T>) -> SolverStats {
+        let lap = Laplacian3D::from_problem(problem);
+        let precon = lap.mic_preconditioner();
+        let (phi, stats) = preconditioned_cg(&lap, problem.rhs(), &self.convergence, |r, z| {
+            lap.apply_preconditioner(&precon, r, z)
+        });
+
+        problem.apply_potential(&phi, &lap);
+        stats
+    }
+}
+
+impl PressureSolverConfig {
+    /// The configured solver for a 3D grid. Multigrid has no 3D hierarchy and falls back,
+    /// with a warning, to the Conjugate Gradient with the same convergence criteria.
+    pub fn build_3d<T: Real>(&self) -> Box<dyn PressureSolver3D<T>> {
+        match *self {
+            PressureSolverConfig::GaussSeidel {
+                over_relaxation,
+                convergence,
+            } => Box::new(GaussSeidelSolver::new(convergence, over_relaxation)),
+            PressureSolverConfig::ConjugateGradient { convergence } => {
+                Box::new(ConjugateGradientSolver::new(convergence))
+            }
+            PressureSolverConfig::Multigrid { convergence, .. } => {
+                warn!("multigrid is not available in 3D, using the conjugate gradient");
+                Box::new(ConjugateGradientSolver::new(convergence))
+            }
+        }
+    }
+}
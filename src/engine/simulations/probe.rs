@@ -0,0 +1,125 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::SheddingEstimate;
+
+/// Quantity sampled by a probe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProbeField {
+    U,
+    V,
+    Speed,
+    Pressure,
+    Smoke,
+    Temperature,
+    /// Concentration of the dye with this name.
+    Dye(String),
+}
+
+impl ProbeField {
+    /// Column name used in CSV headers.
+    pub fn column(&self) -> String {
+        match self {
+            ProbeField::U => "u".to_string(),
+            ProbeField::V => "v".to_string(),
+            ProbeField::Speed => "speed".to_string(),
+            ProbeField::Pressure => "pressure".to_string(),
+            ProbeField::Smoke => "smoke".to_string(),
+            ProbeField::Temperature => "temperature".to_string(),
+            ProbeField::Dye(name) => format!("dye:{}", name),
+        }
+    }
+}
+
+/// Where and what a probe samples. `position` is in the units of the scene like its
+/// regions and shapes. Probes added at runtime take world units, measured from the
+/// corner of the interior like `SceneUnits::World`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProbeDescriptor {
+    pub name: String,
+    pub position: [f32; 2],
+    pub fields: Vec<ProbeField>,
+}
+
+/// Time series recorded by a probe, one sample of every field per simulation step.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    pub name: String,
+    pub position: [f32; 2],
+    pub fields: Vec<ProbeField>,
+    times: Vec<f32>,
+    series: Vec<Vec<f32>>,
+}
+
+impl Probe {
+    pub fn new(descriptor: &ProbeDescriptor) -> Self {
+        Self {
+            name: descriptor.name.clone(),
+            position: descriptor.position,
+            fields: descriptor.fields.clone(),
+            times: Vec::new(),
+            series: vec![Vec::new(); descriptor.fields.len()],
+        }
+    }
+
+    pub(crate) fn record(&mut self, time: f32, values: impl IntoIterator<Item = f32>) {
+        self.times.push(time);
+        for (series, value) in self.series.iter_mut().zip(values) {
+            series.push(value);
+        }
+    }
+
+    pub fn times(&self) -> &[f32] {
+        &self.times
+    }
+
+    /// Recorded values of `field`, if the probe samples it.
+    pub fn series(&self, field: &ProbeField) -> Option<&[f32]> {
+        let index = self.fields.iter().position(|f| f == field)?;
+        Some(&self.series[index])
+    }
+
+    /// Dominant oscillation of the transverse velocity, for a probe placed in a wake and
+    /// sampling `V`. See `SheddingEstimate::from_series`.
+    pub fn shedding(&self, settle_time: f32) -> Option<SheddingEstimate> {
+        SheddingEstimate::from_series(&self.times, self.series(&ProbeField::V)?, settle_time)
+    }
+
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.times.clear();
+        self.series.iter_mut().for_each(Vec::clear);
+    }
+
+    /// Writes the series as CSV with a `time` column followed by one column per field.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        let columns: Vec<_> = self.fields.iter().map(ProbeField::column).collect();
+        writeln!(writer, "time,{}", columns.join(","))?;
+        for (k, time) in self.times.iter().enumerate() {
+            write!(writer, "{}", time)?;
+            for series in &self.series {
+                write!(writer, ",{}", series[k])?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    pub fn save_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_csv(&mut writer)?;
+        writer.flush()
+    }
+}
@@ -0,0 +1,346 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    parse_scene, parse_scene_file, Boundaries, BoundaryCondition, MaskImage, Region, RiemannSolver,
+    SceneError, SlopeLimiter,
+};
+
+/// Gaussian hill `height * exp(-(r / radius)^2)` raised on the ground.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Hill {
+    /// In fractions of the domain width and height.
+    pub center: [f32; 2],
+    /// In fractions of the domain width.
+    pub radius: f32,
+    /// In metres, negative for a hollow.
+    pub height: f32,
+}
+
+/// Terrain elevations read from a greyscale image (PGM/PPM or PNG) stretched over the
+/// domain, the top image row along the top edge. Luminance is interpolated between the
+/// pixel centres and mapped linearly from black to white onto `elevation`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeightMap {
+    pub path: PathBuf,
+    /// Elevations of black and white pixels, in metres.
+    #[serde(default = "HeightMap::default_elevation")]
+    pub elevation: [f32; 2],
+}
+
+impl HeightMap {
+    fn default_elevation() -> [f32; 2] {
+        [0.0, 1.0]
+    }
+
+    pub fn new(path: impl Into<PathBuf>, elevation: [f32; 2]) -> Self {
+        Self {
+            path: path.into(),
+            elevation,
+        }
+    }
+
+    pub fn load(&self) -> Result<MaskImage, SceneError> {
+        MaskImage::from_file(&self.path)
+    }
+
+    /// Elevation of `image` at the point `(x, y)` given in fractions of the domain.
+    pub fn elevation_at(&self, image: &MaskImage, x: f32, y: f32) -> f32 {
+        if image.width == 0 || image.height == 0 {
+            return self.elevation[0];
+        }
+        let luminance = |px: usize, py: usize| {
+            let [r, g, b] = image.pixel(px, py).map(|c| c as f32 / 255.0);
+            0.299 * r + 0.587 * g + 0.114 * b
+        };
+
+        // pixel coordinates from the centre of the top left pixel
+        let px = (x * image.width as f32 - 0.5).clamp(0.0, (image.width - 1) as f32);
+        let py = ((1.0 - y) * image.height as f32 - 0.5).clamp(0.0, (image.height - 1) as f32);
+        let (x0, y0) = (px as usize, py as usize);
+        let (x1, y1) = (
+            (x0 + 1).min(image.width - 1),
+            (y0 + 1).min(image.height - 1),
+        );
+        let (fx, fy) = (px - x0 as f32, py - y0 as f32);
+        let top = luminance(x0, y0) * (1.0 - fx) + luminance(x1, y0) * fx;
+        let bottom = luminance(x0, y1) * (1.0 - fx) + luminance(x1, y1) * fx;
+        let value = top * (1.0 - fy) + bottom * fy;
+
+        self.elevation[0] + value * (self.elevation[1] - self.elevation[0])
+    }
+}
+
+/// Ground elevation under the water, in metres.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Terrain {
+    Flat {
+        elevation: f32,
+    },
+    /// Plane through `elevation` at the lower left corner, rising by `gradient` metres
+    /// per metre along x and y.
+    Slope {
+        elevation: f32,
+        gradient: [f32; 2],
+    },
+    /// Hills on flat ground at `elevation`.
+    Hills {
+        #[serde(default)]
+        elevation: f32,
+        hills: Vec<Hill>,
+    },
+    HeightMap(HeightMap),
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Terrain::Flat { elevation: 0.0 }
+    }
+}
+
+impl Terrain {
+    /// Elevation at the point `(x, y)` given in fractions of a domain `size` metres
+    /// across, `None` for height maps which need their image loaded.
+    pub fn elevation_at(&self, x: f32, y: f32, size: [f32; 2]) -> Option<f32> {
+        match self {
+            Terrain::Flat { elevation } => Some(*elevation),
+            Terrain::Slope {
+                elevation,
+                gradient,
+            } => Some(elevation + gradient[0] * x * size[0] + gradient[1] * y * size[1]),
+            Terrain::Hills { elevation, hills } => {
+                let aspect = size[1] / size[0];
+                let raised: f32 = hills
+                    .iter()
+                    .map(|hill| {
+                        let dx = x - hill.center[0];
+                        let dy = (y - hill.center[1]) * aspect;
+                        let r2 = (dx * dx + dy * dy) / (hill.radius * hill.radius);
+                        hill.height * (-r2).exp()
+                    })
+                    .sum();
+                Some(elevation + raised)
+            }
+            Terrain::HeightMap(_) => None,
+        }
+    }
+}
+
+/// Water filling a region of the initial condition up to a surface elevation.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WaterRegion {
+    /// Box in fractions of the domain width and height; it holds the cells whose centre
+    /// lies inside, the lower and left edges included.
+    pub region: Region,
+    /// Surface elevation in metres. Cells whose ground lies above it stay dry.
+    pub level: f32,
+    #[serde(default)]
+    pub velocity: [f32; 2],
+}
+
+/// Quantity drawn on the wet cells of the instance grid. Dry cells are shaded by their
+/// ground elevation whatever the field.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShallowWaterField {
+    #[default]
+    Depth,
+    /// Elevation of the water surface.
+    Surface,
+    Speed,
+    /// Speed over the gravity wave speed, above one where the flow is supercritical.
+    Froude,
+}
+
+/// Description of a `ShallowWaterSimulation` run: domain, terrain, numerics and the
+/// initial water. Edges use the shared `Boundaries`: walls reflect the water, free-slip
+/// keeping the tangential momentum and no-slip reversing it, outflow edges are
+/// transmissive and inflow edges impose their velocity on the water next to them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShallowWaterScene {
+    pub width: usize,
+    pub height: usize,
+    /// Cell size in metres.
+    pub spacing: f32,
+    pub gravity: f32,
+    /// Manning roughness of the ground in s/m^(1/3), zero for frictionless flow.
+    pub manning: f32,
+    /// Courant number of the explicit time steps, at most 0.5 to keep depths positive.
+    pub cfl: f32,
+    pub riemann_solver: RiemannSolver,
+    pub limiter: SlopeLimiter,
+    /// Depth in metres below which a cell counts as dry and loses its velocity.
+    pub dry_depth: f32,
+    pub boundaries: Boundaries,
+    pub terrain: Terrain,
+    /// Still water surface elevation outside of `water`, no water when unset.
+    pub level: Option<f32>,
+    /// Initial water regions, later ones painted over earlier ones.
+    pub water: Vec<WaterRegion>,
+    pub display: ShallowWaterField,
+    /// Exaggeration of the surface elevation raising the instances; 1 draws it to scale
+    /// with the cell size.
+    pub vertical_scale: f32,
+    /// Simulated time advanced every frame, the frame duration when unset.
+    pub frame_time: Option<f32>,
+    /// Time steps allowed per frame.
+    pub max_substeps: usize,
+    /// Time at which the simulation stops advancing, for comparing with reference
+    /// solutions.
+    pub end_time: Option<f32>,
+}
+
+impl Default for ShallowWaterScene {
+    /// The reservoir flooding a hilly valley on a 200 x 100 grid.
+    fn default() -> Self {
+        Self::flood(100)
+    }
+}
+
+impl ShallowWaterScene {
+    /// Ritter's dam break: water 1 m deep over `[0, 5]` of a 10 m channel `cells` cells
+    /// long released over a dry frictionless bed at `t = 0`, run until `t = 0.6` as the
+    /// front nears the end of the channel.
+    pub fn dam_break(cells: usize) -> Self {
+        Self {
+            width: cells,
+            height: 4,
+            spacing: 10.0 / cells as f32,
+            gravity: 9.81,
+            manning: 0.0,
+            cfl: 0.45,
+            riemann_solver: RiemannSolver::default(),
+            limiter: SlopeLimiter::default(),
+            dry_depth: 1e-4,
+            boundaries: Boundaries {
+                left: BoundaryCondition::FreeSlipWall,
+                right: BoundaryCondition::Outflow,
+                bottom: BoundaryCondition::FreeSlipWall,
+                top: BoundaryCondition::FreeSlipWall,
+            },
+            terrain: Terrain::default(),
+            level: None,
+            water: vec![WaterRegion {
+                region: Region::new(0.0, 0.5, 0.0, 1.0),
+                level: 1.0,
+                velocity: [0.0, 0.0],
+            }],
+            display: ShallowWaterField::Depth,
+            vertical_scale: 1.0,
+            frame_time: Some(0.01),
+            max_substeps: 100,
+            end_time: Some(0.6),
+        }
+    }
+
+    /// Still water 0.5 m deep in a closed 1 m square basin, `cells` cells across, around
+    /// a hill rising above the surface. A well balanced scheme keeps it at rest.
+    pub fn lake_at_rest(cells: usize) -> Self {
+        Self {
+            width: cells,
+            height: cells,
+            spacing: 1.0 / cells as f32,
+            boundaries: Boundaries {
+                left: BoundaryCondition::FreeSlipWall,
+                right: BoundaryCondition::FreeSlipWall,
+                bottom: BoundaryCondition::FreeSlipWall,
+                top: BoundaryCondition::FreeSlipWall,
+            },
+            terrain: Terrain::Hills {
+                elevation: 0.0,
+                hills: vec![Hill {
+                    center: [0.5, 0.5],
+                    radius: 0.15,
+                    height: 0.8,
+                }],
+            },
+            level: Some(0.5),
+            water: Vec::new(),
+            vertical_scale: 10.0,
+            end_time: None,
+            ..Self::dam_break(cells)
+        }
+    }
+
+    /// A reservoir 3 m above the valley floor released into a dry valley 400 m x 200 m
+    /// with a few hills, `cells` cells high, with grassy ground friction. The water
+    /// leaves over the right edge.
+    pub fn flood(cells: usize) -> Self {
+        let hill = |x, y, radius, height| Hill {
+            center: [x, y],
+            radius,
+            height,
+        };
+        Self {
+            width: 2 * cells,
+            height: cells,
+            spacing: 200.0 / cells as f32,
+            manning: 0.03,
+            terrain: Terrain::Hills {
+                elevation: 0.0,
+                hills: vec![
+                    hill(0.45, 0.3, 0.08, 2.5),
+                    hill(0.55, 0.75, 0.1, 4.0),
+                    hill(0.8, 0.45, 0.06, 1.5),
+                ],
+            },
+            boundaries: Boundaries {
+                left: BoundaryCondition::FreeSlipWall,
+                right: BoundaryCondition::Outflow,
+                bottom: BoundaryCondition::FreeSlipWall,
+                top: BoundaryCondition::FreeSlipWall,
+            },
+            level: None,
+            water: vec![WaterRegion {
+                region: Region::new(0.0, 0.15, 0.0, 1.0),
+                level: 3.0,
+                velocity: [0.0, 0.0],
+            }],
+            vertical_scale: 5.0,
+            frame_time: Some(0.5),
+            end_time: None,
+            ..Self::lake_at_rest(cells)
+        }
+    }
+
+    /// Width and height of the domain in metres.
+    pub fn size(&self) -> [f32; 2] {
+        [
+            self.width as f32 * self.spacing,
+            self.height as f32 * self.spacing,
+        ]
+    }
+
+    /// Initial surface elevation and velocity at the point `(x, y)` given in fractions
+    /// of the domain, `None` where there is no water.
+    pub fn water_at(&self, x: f32, y: f32) -> Option<(f32, [f32; 2])> {
+        self.water
+            .iter()
+            .rev()
+            .find(|water| {
+                let Region { x: xs, y: ys } = water.region;
+                xs[0] <= x && x < xs[1] && ys[0] <= y && y < ys[1]
+            })
+            .map(|water| (water.level, water.velocity))
+            .or(self.level.map(|level| (level, [0.0, 0.0])))
+    }
+
+    /// Loads a scene, picking the format from the file extension (`toml`, `json` or `ron`).
+    /// A relative height map path is resolved against the scene file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        let path = path.as_ref();
+        let mut scene: Self = parse_scene_file(path)?;
+
+        if let Terrain::HeightMap(map) = &mut scene.terrain {
+            let directory = path.parent().unwrap_or(Path::new(""));
+            map.path = directory.join(&map.path);
+        }
+        Ok(scene)
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, SceneError> {
+        parse_scene(source, "toml")
+    }
+}
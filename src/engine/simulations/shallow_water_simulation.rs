@@ -0,0 +1,762 @@
+use std::time::Duration;
+
+use glam::Vec3;
+use tracing::warn;
+
+use crate::{
+    scalar_color, Boundaries, BoundaryCondition, Grid2D, GridLocation, HeightMap, Instance,
+    MaskImage, Real, RiemannSolver, SceneError, ShallowWaterField, ShallowWaterScene, SlopeLimiter,
+    Terrain,
+};
+
+/// Ghost cells along each edge, as many as the MUSCL stencil reaches past a face.
+const GHOST_CELLS: usize = 2;
+
+/// Depth and x and y discharge (depth times velocity) of a cell.
+pub type Water<T> = [T; 3];
+
+/// Ghost cell across an edge normal to `axis`, with the interior cells it copies from.
+struct Ghost {
+    condition: BoundaryCondition,
+    axis: usize,
+    cell: (usize, usize),
+    mirror: (usize, usize),
+    nearest: (usize, usize),
+    partner: (usize, usize),
+}
+
+/// Shallow water equations over terrain, solved with a well balanced finite volume
+/// scheme: MUSCL reconstruction of the depth, surface elevation and velocity, the
+/// hydrostatic reconstruction of Audusse et al. (2004) at each face, HLL or HLLC fluxes
+/// and second order SSP Runge-Kutta steps. Water at rest stays at rest over any terrain
+/// and depths stay positive, so dry ground floods and drains without special cases.
+/// Defaults to `f64`, as depths get tiny at the wet/dry fronts.
+pub struct ShallowWaterSimulation<T: Real = f64> {
+    /// Grid size including the ghost cells, two along every edge.
+    pub width: usize,
+    pub height: usize,
+    /// One instance per interior cell, column by column, raised to the surface.
+    pub instances: Vec<Instance>,
+
+    water: Grid2D<Water<T>>,
+    /// Ground elevation in metres.
+    terrain: Grid2D<T>,
+    spacing: T,
+    gravity: T,
+    manning: T,
+    cfl: T,
+    riemann_solver: RiemannSolver,
+    limiter: SlopeLimiter,
+    dry_depth: T,
+    boundaries: Boundaries,
+    display: ShallowWaterField,
+    vertical_scale: f32,
+    frame_time: Option<f32>,
+    max_substeps: usize,
+    end_time: Option<T>,
+    time: T,
+    steps: usize,
+    substeps: usize,
+}
+
+impl<T: Real> ShallowWaterSimulation<T> {
+    pub fn new(width: usize, height: usize, spacing: T) -> Self {
+        let instances = (0..width)
+            .flat_map(move |x| {
+                (0..height).map(move |y| Instance {
+                    position: Vec3::new(x as f32, y as f32, 0.0),
+                    color: [1.0, 1.0, 1.0],
+                })
+            })
+            .collect();
+
+        let width = width + 2 * GHOST_CELLS;
+        let height = height + 2 * GHOST_CELLS;
+
+        Self {
+            width,
+            height,
+            instances,
+            water: Grid2D::new(width, height, GridLocation::CellCenter, [T::zero(); 3]),
+            terrain: Grid2D::new(width, height, GridLocation::CellCenter, T::zero()),
+            spacing,
+            gravity: T::from_f64(9.81),
+            manning: T::zero(),
+            cfl: T::from_f64(0.45),
+            riemann_solver: RiemannSolver::default(),
+            limiter: SlopeLimiter::default(),
+            dry_depth: T::from_f64(1e-4),
+            boundaries: Boundaries::default(),
+            display: ShallowWaterField::default(),
+            vertical_scale: 1.0,
+            frame_time: None,
+            max_substeps: 100,
+            end_time: None,
+            time: T::zero(),
+            steps: 0,
+            substeps: 0,
+        }
+    }
+
+    pub fn from_scene(scene: &ShallowWaterScene) -> Self {
+        let mut simulation = Self::new(scene.width, scene.height, T::from_f32(scene.spacing));
+        simulation.apply_scene(scene);
+        simulation
+    }
+
+    /// Lays out the terrain of `scene`, fills in its initial water and takes over its
+    /// numerics. The grid keeps its size.
+    pub fn apply_scene(&mut self, scene: &ShallowWaterScene) {
+        self.spacing = T::from_f32(scene.spacing);
+        self.gravity = T::from_f32(scene.gravity);
+        self.manning = T::from_f32(scene.manning);
+        self.cfl = T::from_f32(scene.cfl);
+        self.riemann_solver = scene.riemann_solver;
+        self.limiter = scene.limiter;
+        self.dry_depth = T::from_f32(scene.dry_depth);
+        self.boundaries = scene.boundaries.validated();
+        self.display = scene.display;
+        self.vertical_scale = scene.vertical_scale;
+        self.frame_time = scene.frame_time;
+        self.max_substeps = scene.max_substeps;
+        self.end_time = scene.end_time.map(T::from_f32);
+
+        let size = scene.size();
+        for (i, j) in self.interior() {
+            let (x, y) = self.fraction(i, j);
+            let elevation = scene.terrain.elevation_at(x, y, size).unwrap_or(0.0);
+            self.terrain[(i, j)] = T::from_f32(elevation);
+        }
+        if let Terrain::HeightMap(map) = &scene.terrain {
+            if let Err(err) = self.load_height_map(map) {
+                warn!("skipping height map {}: {}", map.path.display(), err);
+            }
+        }
+        self.fill_terrain_ghosts();
+
+        for (i, j) in self.interior() {
+            let (x, y) = self.fraction(i, j);
+            self.water[(i, j)] = match scene.water_at(x, y) {
+                Some((level, [u, v])) => {
+                    let depth = (T::from_f32(level) - self.terrain[(i, j)]).max(T::zero());
+                    [depth, depth * T::from_f32(u), depth * T::from_f32(v)]
+                }
+                None => [T::zero(); 3],
+            };
+        }
+
+        self.time = T::zero();
+        self.steps = 0;
+        self.dry_out();
+        self.apply_boundaries();
+        self.update_instances();
+    }
+
+    /// Loads the image of `map` and applies it, see `apply_height_map`.
+    pub fn load_height_map(&mut self, map: &HeightMap) -> Result<(), SceneError> {
+        let image = map.load()?;
+        self.apply_height_map(map, &image);
+        Ok(())
+    }
+
+    /// Sets the ground elevation of every interior cell from `image` stretched over the
+    /// domain. Water already there keeps its depth.
+    pub fn apply_height_map(&mut self, map: &HeightMap, image: &MaskImage) {
+        for (i, j) in self.interior() {
+            let (x, y) = self.fraction(i, j);
+            self.terrain[(i, j)] = T::from_f32(map.elevation_at(image, x, y));
+        }
+        self.fill_terrain_ghosts();
+    }
+
+    /// Number of cells inside the ghost layers along each axis.
+    pub fn interior_size(&self) -> (usize, usize) {
+        (self.width - 2 * GHOST_CELLS, self.height - 2 * GHOST_CELLS)
+    }
+
+    /// Water depth in cell `(i, j)` counted from the lower left interior cell, `None`
+    /// outside.
+    pub fn depth(&self, i: usize, j: usize) -> Option<T> {
+        self.interior_cell(i, j).map(|c| self.water[c][0])
+    }
+
+    /// Depth averaged velocity in interior cell `(i, j)`, zero where dry.
+    pub fn velocity(&self, i: usize, j: usize) -> Option<[T; 2]> {
+        self.interior_cell(i, j).map(|c| self.velocity_at(c))
+    }
+
+    /// Ground elevation of interior cell `(i, j)`.
+    pub fn elevation(&self, i: usize, j: usize) -> Option<T> {
+        self.interior_cell(i, j).map(|c| self.terrain[c])
+    }
+
+    /// Elevation of the water surface in interior cell `(i, j)`, the ground where dry.
+    pub fn surface(&self, i: usize, j: usize) -> Option<T> {
+        self.interior_cell(i, j)
+            .map(|c| self.terrain[c] + self.water[c][0])
+    }
+
+    /// Centre of interior cell `(i, j)`, from the lower left corner of the domain.
+    pub fn position(&self, i: usize, j: usize) -> (T, T) {
+        let half = T::from_f64(0.5);
+        (
+            (T::from_usize(i) + half) * self.spacing,
+            (T::from_usize(j) + half) * self.spacing,
+        )
+    }
+
+    /// Water volume over the interior, conserved up to what crosses open edges.
+    pub fn volume(&self) -> T {
+        let area = self.spacing * self.spacing;
+        self.interior()
+            .fold(T::zero(), |total, c| total + self.water[c][0] * area)
+    }
+
+    /// Number of interior cells holding water.
+    pub fn wet_cells(&self) -> usize {
+        self.interior().filter(|&c| self.is_wet(c)).count()
+    }
+
+    pub fn time(&self) -> T {
+        self.time
+    }
+
+    /// Total number of steps since the scene was applied.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Number of steps taken by the last `update`.
+    pub fn substeps(&self) -> usize {
+        self.substeps
+    }
+
+    pub fn set_display(&mut self, display: ShallowWaterField) {
+        self.display = display;
+        self.update_instances();
+    }
+
+    pub fn set_riemann_solver(&mut self, riemann_solver: RiemannSolver) {
+        self.riemann_solver = riemann_solver;
+    }
+
+    pub fn set_limiter(&mut self, limiter: SlopeLimiter) {
+        self.limiter = limiter;
+    }
+
+    /// Largest step keeping the fastest gravity wave within `cfl` cells, infinite when
+    /// everything is dry.
+    pub fn cfl_time_step(&self) -> T {
+        let mut max_speed = T::zero();
+        for c in self.interior() {
+            if !self.is_wet(c) {
+                continue;
+            }
+            let c_wave = (self.gravity * self.water[c][0]).sqrt();
+            let [u, v] = self.velocity_at(c);
+            max_speed = max_speed.max(u.abs() + c_wave).max(v.abs() + c_wave);
+        }
+        if max_speed > T::zero() {
+            self.cfl * self.spacing / max_speed
+        } else {
+            T::infinity()
+        }
+    }
+
+    /// Advances the frame time (or the scene's `frame_time`) in CFL limited steps, at
+    /// most `max_substeps` of them, and stops at the scene's end time.
+    pub fn update(&mut self, dt: Duration) {
+        let frame_dt = T::from_f64(dt.as_secs_f64());
+        let target = self.time + self.frame_time.map_or(frame_dt, T::from_f32);
+        let target = self.end_time.map_or(target, |end| target.min(end));
+
+        self.substeps = 0;
+        while self.time < target && self.substeps < self.max_substeps {
+            let dt = self.cfl_time_step().min(target - self.time);
+            self.step(dt);
+            self.substeps += 1;
+        }
+        self.update_instances();
+    }
+
+    /// Steps until `time`, ignoring the end time and substep limit of `update`.
+    pub fn advance_to(&mut self, time: T) {
+        while self.time < time {
+            let dt = self.cfl_time_step().min(time - self.time);
+            self.step(dt);
+        }
+    }
+
+    /// One step of `dt` with Heun's (SSP RK2) method, then the ground friction.
+    fn step(&mut self, dt: T) {
+        let half = T::from_f64(0.5);
+        let start = self.water.clone();
+
+        let rate = self.rate();
+        for (water, rate) in self.water.iter_mut().zip(rate.iter()) {
+            for k in 0..3 {
+                water[k] += dt * rate[k];
+            }
+        }
+        self.dry_out();
+        self.apply_boundaries();
+
+        let rate = self.rate();
+        for ((water, rate), start) in self.water.iter_mut().zip(rate.iter()).zip(start.iter()) {
+            for k in 0..3 {
+                water[k] = half * (start[k] + water[k] + dt * rate[k]);
+            }
+        }
+        self.dry_out();
+        self.apply_friction(dt);
+        self.apply_boundaries();
+
+        self.time += dt;
+        self.steps += 1;
+    }
+
+    /// Time derivative of the water of every interior cell: the net flux through its
+    /// faces over its size, and the pull of gravity down the ground slope inside it.
+    fn rate(&self) -> Grid2D<Water<T>> {
+        let primitives = self
+            .water
+            .cells()
+            .map(|c| self.primitive(c))
+            .collect::<Vec<_>>();
+        let mut rate = Grid2D::new(
+            self.width,
+            self.height,
+            GridLocation::CellCenter,
+            [T::zero(); 3],
+        );
+        let h1 = T::one() / self.spacing;
+        let half = T::from_f64(0.5);
+        let (w, h) = (self.width, self.height);
+        let g = GHOST_CELLS;
+
+        // faces between cells (i - 1, j) and (i, j), then (i, j - 1) and (i, j)
+        for axis in 0..2 {
+            let step = if axis == 0 { h } else { 1 };
+            let slopes = self.slopes(&primitives, step);
+
+            let (faces_x, faces_y) = if axis == 0 {
+                (g..w - g + 1, g..h - g)
+            } else {
+                (g..w - g, g..h - g + 1)
+            };
+            for i in faces_x {
+                for j in faces_y.clone() {
+                    let b = rate.index(i, j);
+                    let a = b - step;
+                    let mut left = primitives[a];
+                    let mut right = primitives[b];
+                    for k in 0..4 {
+                        left[k] += half * slopes[a][k];
+                        right[k] -= half * slopes[b][k];
+                    }
+
+                    let (flux, left_wall, right_wall) = self.face_flux(left, right, axis);
+                    for k in 0..3 {
+                        rate[a][k] -= flux[k] * h1;
+                        rate[b][k] += flux[k] * h1;
+                    }
+                    rate[a][1 + axis] -= left_wall * h1;
+                    rate[b][1 + axis] += right_wall * h1;
+                }
+            }
+
+            // the ground sloping inside the cells, zero without reconstruction
+            for (i, j) in self.interior() {
+                let c = rate.index(i, j);
+                let depth = primitives[c][0];
+                let ground_slope = slopes[c][1] - slopes[c][0];
+                rate[c][1 + axis] -= self.gravity * depth * ground_slope * h1;
+            }
+        }
+        rate
+    }
+
+    /// Limited differences across each cell along the axis whose neighbours are `step`
+    /// apart, of the depth, surface elevation and velocity. Cells at the edge of the
+    /// water stay flat so that no water is reconstructed onto dry ground.
+    fn slopes(&self, primitives: &[[T; 4]], step: usize) -> Vec<[T; 4]> {
+        let mut slopes = vec![[T::zero(); 4]; primitives.len()];
+        if self.limiter == SlopeLimiter::FirstOrder {
+            return slopes;
+        }
+        for c in step..primitives.len() - step {
+            let (before, cell, after) = (primitives[c - step], primitives[c], primitives[c + step]);
+            if [before, cell, after].iter().any(|p| p[0] <= self.dry_depth) {
+                continue;
+            }
+            for k in 0..4 {
+                slopes[c][k] = self.limiter.limit(cell[k] - before[k], after[k] - cell[k]);
+            }
+        }
+        slopes
+    }
+
+    /// Flux along `axis` between the reconstructed states `left` and `right`, and the
+    /// hydrostatic pressure each side exerts on the step the ground makes at the face.
+    fn face_flux(&self, left: [T; 4], right: [T; 4], axis: usize) -> (Water<T>, T, T) {
+        let half_g = T::from_f64(0.5) * self.gravity;
+        let [left_depth, left_surface, ..] = left;
+        let [right_depth, right_surface, ..] = right;
+        let ground = (left_surface - left_depth).max(right_surface - right_depth);
+
+        // depths seen over the higher of the two grounds
+        let left_star = (left_surface - ground).max(T::zero());
+        let right_star = (right_surface - ground).max(T::zero());
+
+        // solve in the frame of the face normal, swapping u and v for y faces
+        let rotate = |[_, _, u, v]: [T; 4], depth: T| {
+            if axis == 0 {
+                [depth, u, v]
+            } else {
+                [depth, v, u]
+            }
+        };
+        let mut flux = self.riemann_flux(rotate(left, left_star), rotate(right, right_star));
+        if axis == 1 {
+            flux.swap(1, 2);
+        }
+
+        (
+            flux,
+            half_g * (left_depth * left_depth - left_star * left_star),
+            half_g * (right_depth * right_depth - right_star * right_star),
+        )
+    }
+
+    /// HLL or HLLC flux along x between states of depth, normal and tangential velocity.
+    fn riemann_flux(&self, left: [T; 3], right: [T; 3]) -> Water<T> {
+        let [hl, ul, vl] = left;
+        let [hr, ur, vr] = right;
+        if hl <= T::zero() && hr <= T::zero() {
+            return [T::zero(); 3];
+        }
+
+        let two = T::from_f64(2.0);
+        let (cl, cr) = ((self.gravity * hl).sqrt(), (self.gravity * hr).sqrt());
+        // a dry side only sees the front of the wet one (Toro, section 10.7)
+        let sl = if hl <= T::zero() {
+            ur - two * cr
+        } else if hr <= T::zero() {
+            ul - cl
+        } else {
+            (ul - cl).min(ur - cr)
+        };
+        let sr = if hr <= T::zero() {
+            ul + two * cl
+        } else if hl <= T::zero() {
+            ur + cr
+        } else {
+            (ul + cl).max(ur + cr)
+        };
+
+        let physical = |[h, u, v]: [T; 3]| {
+            [
+                h * u,
+                h * u * u + T::from_f64(0.5) * self.gravity * h * h,
+                h * u * v,
+            ]
+        };
+        let (fl, fr) = (physical(left), physical(right));
+        if sl >= T::zero() {
+            return fl;
+        }
+        if sr <= T::zero() {
+            return fr;
+        }
+
+        let (ql, qr) = ([hl, hl * ul, hl * vl], [hr, hr * ur, hr * vr]);
+        let mut flux = [T::zero(); 3];
+        for k in 0..3 {
+            flux[k] = (sr * fl[k] - sl * fr[k] + sl * sr * (qr[k] - ql[k])) / (sr - sl);
+        }
+        if self.riemann_solver == RiemannSolver::Hllc {
+            // the tangential velocity is carried across the middle wave (Toro, 10.6)
+            let denominator = hr * (ur - sr) - hl * (ul - sl);
+            let middle = if denominator != T::zero() {
+                (sl * hr * (ur - sr) - sr * hl * (ul - sl)) / denominator
+            } else {
+                T::zero()
+            };
+            flux[2] = flux[0] * if middle >= T::zero() { vl } else { vr };
+        }
+        flux
+    }
+
+    /// Clears the rounding left on drained cells: no negative depths, and no velocity
+    /// where the water is too thin to carry one.
+    fn dry_out(&mut self) {
+        for water in self.water.iter_mut() {
+            if water[0] <= self.dry_depth {
+                water[0] = water[0].max(T::zero());
+                water[1] = T::zero();
+                water[2] = T::zero();
+            }
+        }
+    }
+
+    /// Slows the water by Manning's ground friction, implicitly so that thin sheets
+    /// stop rather than reverse.
+    fn apply_friction(&mut self, dt: T) {
+        if self.manning <= T::zero() {
+            return;
+        }
+        let coefficient = self.gravity * self.manning * self.manning * dt;
+        let exponent = T::from_f64(4.0 / 3.0);
+        for c in self.interior() {
+            if !self.is_wet(c) {
+                continue;
+            }
+            let [u, v] = self.velocity_at(c);
+            let depth = self.water[c][0];
+            let damping = T::one() + coefficient * (u * u + v * v).sqrt() / depth.powf(exponent);
+            self.water[c][1] /= damping;
+            self.water[c][2] /= damping;
+        }
+    }
+
+    /// Fills the ghost layers from the edge conditions.
+    fn apply_boundaries(&mut self) {
+        for ghost in self.ghosts() {
+            let axis = ghost.axis;
+            self.water[ghost.cell] = match ghost.condition {
+                BoundaryCondition::FreeSlipWall => {
+                    let mut water = self.water[ghost.mirror];
+                    water[1 + axis] = -water[1 + axis];
+                    water
+                }
+                BoundaryCondition::NoSlipWall => {
+                    let [depth, qx, qy] = self.water[ghost.mirror];
+                    [depth, -qx, -qy]
+                }
+                BoundaryCondition::Inflow { velocity } => {
+                    let depth = self.water[ghost.nearest][0];
+                    let [u, v] = velocity.map(T::from_f32);
+                    [depth, depth * u, depth * v]
+                }
+                BoundaryCondition::Outflow => self.water[ghost.nearest],
+                BoundaryCondition::Periodic => self.water[ghost.partner],
+            };
+        }
+    }
+
+    /// Extends the ground into the ghost layers: mirrored behind walls, flat past open
+    /// edges and wrapped around periodic ones.
+    fn fill_terrain_ghosts(&mut self) {
+        for ghost in self.ghosts() {
+            self.terrain[ghost.cell] = match ghost.condition {
+                BoundaryCondition::FreeSlipWall | BoundaryCondition::NoSlipWall => {
+                    self.terrain[ghost.mirror]
+                }
+                BoundaryCondition::Inflow { .. } | BoundaryCondition::Outflow => {
+                    self.terrain[ghost.nearest]
+                }
+                BoundaryCondition::Periodic => self.terrain[ghost.partner],
+            };
+        }
+    }
+
+    /// Ghost cells of every edge. Corners are left out, no face flux reads them.
+    fn ghosts(&self) -> Vec<Ghost> {
+        let (w, h) = (self.width, self.height);
+        let g = GHOST_CELLS;
+        let b = self.boundaries;
+        let mut ghosts = Vec::with_capacity(2 * g * (w + h));
+
+        for k in 0..g {
+            for j in g..h - g {
+                ghosts.push(Ghost {
+                    condition: b.left,
+                    axis: 0,
+                    cell: (g - 1 - k, j),
+                    mirror: (g + k, j),
+                    nearest: (g, j),
+                    partner: (w - g - 1 - k, j),
+                });
+                ghosts.push(Ghost {
+                    condition: b.right,
+                    axis: 0,
+                    cell: (w - g + k, j),
+                    mirror: (w - g - 1 - k, j),
+                    nearest: (w - g - 1, j),
+                    partner: (g + k, j),
+                });
+            }
+            for i in g..w - g {
+                ghosts.push(Ghost {
+                    condition: b.bottom,
+                    axis: 1,
+                    cell: (i, g - 1 - k),
+                    mirror: (i, g + k),
+                    nearest: (i, g),
+                    partner: (i, h - g - 1 - k),
+                });
+                ghosts.push(Ghost {
+                    condition: b.top,
+                    axis: 1,
+                    cell: (i, h - g + k),
+                    mirror: (i, h - g - 1 - k),
+                    nearest: (i, h - g - 1),
+                    partner: (i, g + k),
+                });
+            }
+        }
+        ghosts
+    }
+
+    /// Raises every instance to the water surface, or the ground where dry, and colours
+    /// the wet cells by the displayed field over its current range and the dry ones by
+    /// their elevation.
+    pub fn update_instances(&mut self) {
+        let cells: Vec<(usize, usize)> = self.interior().collect();
+        let values: Vec<f32> = cells
+            .iter()
+            .map(|&c| self.display_value(c).as_f32())
+            .collect();
+        let wet: Vec<bool> = cells.iter().map(|&c| self.is_wet(c)).collect();
+
+        let range = |values: &mut dyn Iterator<Item = f32>| {
+            values.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| {
+                (min.min(value), max.max(value))
+            })
+        };
+        let (min, max) = range(
+            &mut values
+                .iter()
+                .zip(&wet)
+                .filter(|(_, &wet)| wet)
+                .map(|(&value, _)| value),
+        );
+        let (low, high) = range(&mut cells.iter().map(|&c| self.terrain[c].as_f32()));
+
+        let scale = self.vertical_scale / self.spacing.as_f32();
+        for (k, instance) in self.instances.iter_mut().enumerate() {
+            let c = cells[k];
+            let ground = self.terrain[c].as_f32();
+            instance.position.z = (ground + self.water[c][0].as_f32()) * scale;
+            instance.color = if wet[k] {
+                scalar_color(values[k], min, max)
+            } else {
+                let shade = if high > low {
+                    (ground - low) / (high - low)
+                } else {
+                    0.5
+                };
+                [0.35 + 0.4 * shade, 0.25 + 0.4 * shade, 0.15 + 0.35 * shade]
+            };
+        }
+    }
+
+    fn display_value(&self, c: (usize, usize)) -> T {
+        let depth = self.water[c][0];
+        let [u, v] = self.velocity_at(c);
+        let speed = (u * u + v * v).sqrt();
+        match self.display {
+            ShallowWaterField::Depth => depth,
+            ShallowWaterField::Surface => self.terrain[c] + depth,
+            ShallowWaterField::Speed => speed,
+            ShallowWaterField::Froude => {
+                if self.is_wet(c) {
+                    speed / (self.gravity * depth).sqrt()
+                } else {
+                    T::zero()
+                }
+            }
+        }
+    }
+
+    /// Depth, surface elevation and velocity of cell `c`.
+    fn primitive(&self, c: (usize, usize)) -> [T; 4] {
+        let depth = self.water[c][0];
+        let [u, v] = self.velocity_at(c);
+        [depth, self.terrain[c] + depth, u, v]
+    }
+
+    fn velocity_at(&self, c: (usize, usize)) -> [T; 2] {
+        let [depth, qx, qy] = self.water[c];
+        if depth > self.dry_depth {
+            [qx / depth, qy / depth]
+        } else {
+            [T::zero(); 2]
+        }
+    }
+
+    fn is_wet(&self, c: (usize, usize)) -> bool {
+        self.water[c][0] > self.dry_depth
+    }
+
+    /// Interior cell `(i, j)` of the padded grid, `None` outside.
+    fn interior_cell(&self, i: usize, j: usize) -> Option<(usize, usize)> {
+        let (nx, ny) = self.interior_size();
+        (i < nx && j < ny).then_some((i + GHOST_CELLS, j + GHOST_CELLS))
+    }
+
+    /// Centre of padded cell `(i, j)` in fractions of the domain.
+    fn fraction(&self, i: usize, j: usize) -> (f32, f32) {
+        let (nx, ny) = self.interior_size();
+        (
+            ((i - GHOST_CELLS) as f32 + 0.5) / nx as f32,
+            ((j - GHOST_CELLS) as f32 + 0.5) / ny as f32,
+        )
+    }
+
+    /// Interior cells of the padded grid, column by column like the instances.
+    fn interior(&self) -> impl Iterator<Item = (usize, usize)> {
+        let (w, h) = (self.width, self.height);
+        (GHOST_CELLS..w - GHOST_CELLS)
+            .flat_map(move |i| (GHOST_CELLS..h - GHOST_CELLS).map(move |j| (i, j)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lake_at_rest_stays_still() {
+        let cells = 40;
+        let mut simulation =
+            ShallowWaterSimulation::<f64>::from_scene(&ShallowWaterScene::lake_at_rest(cells));
+        simulation.advance_to(1.0);
+
+        for i in 0..cells {
+            for j in 0..cells {
+                let [u, v] = simulation.velocity(i, j).unwrap();
+                assert!(u.hypot(v) < 1e-10, "velocity {u}, {v} in cell {i}, {j}");
+                if simulation.depth(i, j).unwrap() > 0.0 {
+                    let surface = simulation.surface(i, j).unwrap();
+                    assert!(
+                        (surface - 0.5).abs() < 1e-10,
+                        "surface {surface} in cell {i}, {j}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dam_break_front_follows_ritter() {
+        let cells = 100;
+        let scene = ShallowWaterScene::dam_break(cells);
+        let mut simulation = ShallowWaterSimulation::<f64>::from_scene(&scene);
+        let time = 0.2;
+        simulation.advance_to(time);
+
+        // the dam stands in the middle of the 10 m channel, holding 1 m of water; the
+        // front runs 12.5 cells, the thin tip of the exact solution trailing a little
+        let spacing = scene.spacing as f64;
+        let front = (0..cells)
+            .rfind(|&i| simulation.depth(i, 0).unwrap() > scene.dry_depth as f64)
+            .map(|i| simulation.position(i, 0).0 + 0.5 * spacing)
+            .unwrap();
+        let exact = 5.0 + 2.0 * (scene.gravity as f64).sqrt() * time;
+        assert!(
+            (front - exact).abs() < 3.0 * spacing,
+            "front at {front}, expected {exact}"
+        );
+    }
+}
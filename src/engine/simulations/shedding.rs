@@ -0,0 +1,119 @@
+use crate::ForceReference;
+
+/// Relative amplitude a signal must swing past its mean before a zero crossing counts,
+/// so that noise around the mean is not taken for oscillation.
+const HYSTERESIS: f32 = 0.2;
+
+/// Dominant oscillation of a signal, such as the transverse velocity in a wake.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SheddingEstimate {
+    pub frequency: f32,
+    pub period: f32,
+    /// Number of full periods the estimate is averaged over.
+    pub cycles: usize,
+    /// Half the peak to peak swing over the analysed window.
+    pub amplitude: f32,
+}
+
+impl SheddingEstimate {
+    /// Estimates the frequency from the upward zero crossings of `values` about their
+    /// mean, ignoring samples before `settle_time`. Samples need not be evenly spaced,
+    /// crossing times are interpolated linearly. Samples past the end of the shorter of
+    /// the two series are ignored. `None` until a full period is seen.
+    pub fn from_series(times: &[f32], values: &[f32], settle_time: f32) -> Option<Self> {
+        let len = times.len().min(values.len());
+        let (times, values) = (&times[..len], &values[..len]);
+        let start = times.iter().position(|t| *t >= settle_time)?;
+        let (times, values) = (&times[start..], &values[start..]);
+        if values.len() < 3 {
+            return None;
+        }
+
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        let (min, max) = values
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), v| {
+                (lo.min(*v), hi.max(*v))
+            });
+        let amplitude = 0.5 * (max - min);
+        if amplitude <= 0.0 {
+            return None;
+        }
+        let threshold = HYSTERESIS * amplitude;
+
+        let mut crossings = Vec::new();
+        let mut armed = false;
+        for k in 1..values.len() {
+            let (a, b) = (values[k - 1] - mean, values[k] - mean);
+            if b < -threshold {
+                armed = true;
+            }
+            if armed && a < 0.0 && b >= 0.0 {
+                let t = times[k - 1] + (times[k] - times[k - 1]) * (-a / (b - a));
+                crossings.push(t);
+                armed = false;
+            }
+        }
+
+        if crossings.len() < 2 {
+            return None;
+        }
+        let cycles = crossings.len() - 1;
+        let period = (crossings[cycles] - crossings[0]) / cycles as f32;
+
+        Some(Self {
+            frequency: 1.0 / period,
+            period,
+            cycles,
+            amplitude,
+        })
+    }
+
+    /// Strouhal number `frequency * length / speed`.
+    pub fn strouhal(&self, reference: &ForceReference) -> f32 {
+        self.frequency * reference.length / reference.speed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, duration: f32, dt: f32) -> (Vec<f32>, Vec<f32>) {
+        let times: Vec<f32> = (0..(duration / dt) as usize)
+            .map(|k| k as f32 * dt)
+            .collect();
+        let values = times
+            .iter()
+            .map(|t| 0.3 + 2.0 * (std::f32::consts::TAU * frequency * t).sin())
+            .collect();
+        (times, values)
+    }
+
+    #[test]
+    fn finds_frequency_of_sine() {
+        let (times, values) = sine(1.7, 10.0, 0.01);
+        let estimate = SheddingEstimate::from_series(&times, &values, 1.0).unwrap();
+        assert!((estimate.frequency - 1.7).abs() < 1e-3, "{:?}", estimate);
+        assert!((estimate.period - 1.0 / 1.7).abs() < 1e-3, "{:?}", estimate);
+        assert!((estimate.amplitude - 2.0).abs() < 1e-2, "{:?}", estimate);
+        assert!(estimate.cycles >= 14, "{:?}", estimate);
+    }
+
+    #[test]
+    fn ignores_samples_without_times() {
+        let (times, values) = sine(1.7, 10.0, 0.01);
+        let estimate = SheddingEstimate::from_series(&times[..500], &values, 1.0).unwrap();
+        assert!((estimate.frequency - 1.7).abs() < 1e-3, "{:?}", estimate);
+        assert!(SheddingEstimate::from_series(&times, &values[..50], 1.0).is_none());
+    }
+
+    #[test]
+    fn needs_a_full_period() {
+        let (times, values) = sine(1.0, 1.2, 0.01);
+        assert!(SheddingEstimate::from_series(&times, &values, 0.0).is_none());
+        let (times, values) = sine(1.0, 2.2, 0.01);
+        let estimate = SheddingEstimate::from_series(&times, &values, 0.0).unwrap();
+        assert_eq!(estimate.cycles, 1);
+    }
+}
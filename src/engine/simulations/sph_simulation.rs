@@ -1,10 +1,10 @@
-use std::{f64::consts::PI, time::Duration};
+use std::time::Duration;
 
-use glam::{DVec2, Vec3};
+use glam::DVec2;
 use rand::random;
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
 
-use crate::Instance;
+use crate::{Instance, Real, Vector2};
 
 const H: f64 = 16.0;
 const HSQ: f64 = H * H;
@@ -12,23 +12,23 @@ const DT: f64 = 0.0001;
 pub const G: DVec2 = DVec2::from_array([0.0, -9.81]);
 
 #[derive(Debug, Default)]
-pub struct SPHSimulation {
-    pub width: f64,
-    pub height: f64,
+pub struct SPHSimulation<T: Real = f64> {
+    pub width: T,
+    pub height: T,
     pub instances: Vec<Instance>,
 
     pub max_particles: usize,
     pub num_particles: usize,
-    pub position: Vec<DVec2>,
-    velocity: Vec<DVec2>,
-    forces: Vec<DVec2>,
-    rho: Vec<f64>,
-    pressure: Vec<f64>,
-    mass: Vec<f64>,
+    pub position: Vec<Vector2<T>>,
+    velocity: Vec<Vector2<T>>,
+    forces: Vec<Vector2<T>>,
+    rho: Vec<T>,
+    pressure: Vec<T>,
+    mass: Vec<T>,
 }
 
-impl SPHSimulation {
-    pub fn new(width: f64, height: f64, max_particles: usize) -> Self {
+impl<T: Real> SPHSimulation<T> {
+    pub fn new(width: T, height: T, max_particles: usize) -> Self {
         let instances = Vec::with_capacity(max_particles);
         let position = Vec::with_capacity(max_particles);
         let velocity = Vec::with_capacity(max_particles);
@@ -68,23 +68,21 @@ impl SPHSimulation {
 
         let mut i = 0;
         for particle in &self.position {
-            let position = Vec3::new(particle[0] as f32, particle[1] as f32, 0.0);
-            let color = [0.0, 0.0, 1.0];
-            instances.push(Instance { position, color });
+            instances.push(Instance::at(particle.x, particle.y, [0.0, 0.0, 1.0]));
             i += 1;
         }
 
         self.instances = instances;
     }
 
-    pub fn add_particle(&mut self, x: f64, y: f64) {
+    pub fn add_particle(&mut self, x: T, y: T) {
         self.num_particles += 1;
-        self.position.push(DVec2::new(x, y));
-        self.velocity.push(DVec2::ZERO);
-        self.forces.push(DVec2::ZERO);
-        self.rho.push(1.0);
-        self.pressure.push(0.0);
-        self.mass.push(1.0);
+        self.position.push(Vector2::new(x, y));
+        self.velocity.push(Vector2::zero());
+        self.forces.push(Vector2::zero());
+        self.rho.push(T::one());
+        self.pressure.push(T::zero());
+        self.mass.push(T::one());
     }
 
     pub fn init_scene(&mut self, dam_max_particles: usize) {
@@ -100,83 +98,88 @@ impl SPHSimulation {
                 }
                 let jitter = random::<f64>();
                 self.add_particle
-        (x + jitter, y);
+        (T::from_f64(x + jitter), T::from_f64(y));
                 placed += 1;
             }
         }
     }
 
     pub fn integrate(&mut self, dt: Duration) {
+        let (h, step, bounce) = (T::from_f64(H), T::from_f64(DT), T::from_f64(-0.5));
         self.position
             .par_iter_mut()
             .zip_eq(self.velocity.par_iter_mut())
             .enumerate()
             .for_each(|(i, (position, velocity))| {
-                *velocity += DT * self.forces[i] / self.rho[i];
-                *position += DT * *velocity;
+                *velocity += self.forces[i] * step / self.rho[i];
+                *position += *velocity * step;
 
-                if position.x - H < 0.0 {
-                    velocity.x *= -0.5;
-                    position.x = H;
+                if position.x - h < T::zero() {
+                    velocity.x *= bounce;
+                    position.x = h;
                 }
-                if position.x + H > self.width {
-                    velocity.x *= -0.5;
-                    position.x = self.width - H;
+                if position.x + h > self.width {
+                    velocity.x *= bounce;
+                    position.x = self.width - h;
                 }
-                if position.y - H < 0.0 {
-                    velocity.y *= -0.5;
-                    position.y = H;
+                if position.y - h < T::zero() {
+                    velocity.y *= bounce;
+                    position.y = h;
                 }
-                if position.y + H > self.height {
-                    velocity.y *= -0.5;
-                    position.y = self.height - H;
+                if position.y + h > self.height {
+                    velocity.y *= bounce;
+                    position.y = self.height - h;
                 }
             });
     }
 
     pub fn compute_d_p(&mut self) {
-        let poly6 = 4.0 / (PI * f64::powf(H, 8.0));
+        let (h, hsq) = (T::from_f64(H), T::from_f64(HSQ));
+        let poly6 = T::from_f64(4.0) / (T::PI() * h.powf(T::from_f64(8.0)));
 
         self.rho.par_iter_mut()
             .zip_eq(self.pressure.par_iter_mut())
             .enumerate()
             .for_each(|(i,(rho, pressure))| {
-                *rho = 0.0;
+                *rho = T::zero();
                 for j in 0..self.num_particles {
                     let pos_diff = self.position[j] - self.position[i];
                     let r = pos_diff.length_squared();
-                    if r < HSQ {
-                        *rho += self.mass[i] * poly6 * f64::powf(HSQ - r, 3.0);
+                    if r < hsq {
+                        *rho += self.mass[i] * poly6 * (hsq - r).powf(T::from_f64(3.0));
                     }
                 }
-                *pressure = 3000.0 * (*rho - 1000.0);
+                *pressure = T::from_f64(3000.0) * (*rho - T::from_f64(1000.0));
             });
     }
 
     pub fn compute_forces(&mut self) {
-        let spiky = -10.0 / (PI * f64::powf(H, 5.0));
-        let viscy = 40.0 / (PI * f64::powf(H, 5.0));
+        let c = T::from_f64;
+        let h = c(H);
+        let spiky = c(-10.0) / (T::PI() * h.powf(c(5.0)));
+        let viscy = c(40.0) / (T::PI() * h.powf(c(5.0)));
+        let gravity = Vector2::new(c(G.x), c(G.y));
         self.forces.par_iter_mut().enumerate()
             .for_each(|(i, forces)| {
-                let mut fpress = DVec2::ZERO;
-                let mut fvisc = DVec2::ZERO;
+                let mut fpress = Vector2::zero();
+                let mut fvisc = Vector2::zero();
                 for j in 0..self.num_particles {
                     if i == j {
                         continue;
                     }
                     let pos_diff = self.position[j] - self.position[i];
-                    let dist: f64 = pos_diff.length();
-                    if dist < H {
+                    let dist: T = pos_diff.length();
+                    if dist < h {
                         fpress += -pos_diff.normalize() * self.mass[i] * (self.pressure[i] + self.pressure[j])
-                            / (2.0 * self.rho[j])
+                            / (c(2.0) * self.rho[j])
                             * spiky
-                            * f64::powf(H - dist, 3.0);
-                        fvisc += 100.0 * self.mass[i] * (self.velocity[j] - self.velocity[i]) / self.rho[j]
+                            * (h - dist).powf(c(3.0));
+                        fvisc += (self.velocity[j] - self.velocity[i]) * (c(100.0) * self.mass[i]) / self.rho[j]
                             * viscy
-                            * (H - dist);
+                            * (h - dist);
                     }
                 }
-                let fgrav = G * self.mass[i] / self.rho[i];
+                let fgrav = gravity * self.mass[i] / self.rho[i];
                 *forces = fpress + fvisc + fgrav;
             });
     }
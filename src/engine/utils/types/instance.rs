@@ -1,5 +1,7 @@
 use glam::Vec3;
 
+use crate::Real;
+
 #[derive(Debug)]
 pub struct Instance {
     pub position: Vec3,
@@ -14,6 +16,15 @@ pub struct InstanceRaw {
 }
 
 impl Instance {
+    /// Instance at the simulation position `(x, y)` in the z = 0 plane, narrowed to the
+    /// `f32` the renderer works in.
+    pub fn at<T: Real>(x: T, y: T, color: [f32; 3]) -> Self {
+        Self {
+            position: Vec3::new(x.as_f32(), y.as_f32(), 0.0),
+            color,
+        }
+    }
+
     pub fn to_raw(&self) -> InstanceRaw {
         InstanceRaw {
             model: glam::Mat4::from_translation(self.position).to_cols_array_2d(),
@@ -1,8 +1,13 @@
 use std::time::Duration;
 
+use glam::Vec3;
+use tracing::{error, info};
+
 use crate::{
-    CameraController2D, CameraDescriptor, Controller, Deg, Engine, EulerSimulation, Point3,
-    Projection, Window, WindowEvents,
+    CameraController2D, CameraController3D, CameraDescriptor, CompressibleScene,
+    CompressibleSimulation, Controller, Deg, Engine, Euler3DScene, Euler3DSimulation, EulerScene,
+    EulerSimulation, FLIPScene, FLIPSimulation, Instance, LBMScene, LBMSimulation, Point3,
+    Projection, SceneError, ShallowWaterScene, ShallowWaterSimulation, Window, WindowEvents,
 };
 
 use winit::{
@@ -10,12 +15,66 @@ use winit::{
     event::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode},
 };
 
+/// The solver driven by the app, picked from the command line.
+enum FluidSimulation {
+    Euler(EulerSimulation),
+    Euler3D(Euler3DSimulation),
+    Compressible(CompressibleSimulation),
+    Lbm(LBMSimulation),
+    Flip(FLIPSimulation),
+    ShallowWater(ShallowWaterSimulation),
+}
+
+impl FluidSimulation {
+    fn update(&mut self, dt: Duration) {
+        match self {
+            FluidSimulation::Euler(simulation) => simulation.update(dt),
+            FluidSimulation::Euler3D(simulation) => simulation.update(dt),
+            FluidSimulation::Compressible(simulation) => simulation.update(dt),
+            FluidSimulation::Lbm(simulation) => simulation.update(dt),
+            FluidSimulation::Flip(simulation) => simulation.update(dt),
+            FluidSimulation::ShallowWater(simulation) => simulation.update(dt),
+        }
+    }
+
+    fn instances(&self) -> &[Instance] {
+        match self {
+            FluidSimulation::Euler(simulation) => &simulation.instances,
+            FluidSimulation::Euler3D(simulation) => &simulation.instances,
+            FluidSimulation::Compressible(simulation) => &simulation.instances,
+            FluidSimulation::Lbm(simulation) => &simulation.instances,
+            FluidSimulation::Flip(simulation) => &simulation.instances,
+            FluidSimulation::ShallowWater(simulation) => &simulation.instances,
+        }
+    }
+
+    /// Whether confinement is enabled afterwards, always false for the solvers without it.
+    fn toggle_vorticity_confinement(&mut self) -> bool {
+        match self {
+            FluidSimulation::Euler(simulation) => simulation.toggle_vorticity_confinement(),
+            FluidSimulation::Euler3D(simulation) => simulation.toggle_vorticity_confinement(),
+            FluidSimulation::Compressible(_)
+            | FluidSimulation::Lbm(_)
+            | FluidSimulation::Flip(_)
+            | FluidSimulation::ShallowWater(_) => false,
+        }
+    }
+
+    /// Key starting and stopping the simulation. The 3D camera moves up with Space.
+    fn pause_key(&self) -> VirtualKeyCode {
+        match self {
+            FluidSimulation::Euler3D(_) | FluidSimulation::ShallowWater(_) => VirtualKeyCode::P,
+            _ => VirtualKeyCode::Space,
+        }
+    }
+}
+
 struct Simulation {
     engine: Engine,
 
-    camera_controller: CameraController2D,
+    camera_controller: Box<dyn Controller>,
     mouse_pressed: bool,
-    simulation: EulerSimulation,
+    simulation: FluidSimulation,
 
     stopped: bool,
 }
@@ -24,13 +83,70 @@ impl Simulation {
     pub async fn new(window: &Window) -> Self {
         let (width, height) = (window.get_width(), window.get_height());
         let projection = Projection::new(width, height, Deg(90.0), 0.1, 10000.0);
-        let camera_controller = CameraController2D::new(100.0, 0.5, 2.0, 1000.0); // 2.0, 2000.0
+
+        let simulation = load_simulation();
+        let (camera_controller, position, pitch): (Box<dyn Controller>, _, _) = match &simulation {
+            FluidSimulation::Euler(_) => (
+                Box::new(CameraController2D::new(100.0, 0.5, 2.0, 1000.0)), // 2.0, 2000.0
+                Point3::from(100.0, 50.0, 50.0),
+                Deg::new(0.0),
+            ),
+            FluidSimulation::Compressible(simulation) => {
+                let (width, height) = simulation.interior_size();
+                (
+                    Box::new(CameraController2D::new(100.0, 0.5, 2.0, 1000.0)),
+                    overview_2d(width, height),
+                    Deg::new(0.0),
+                )
+            }
+            FluidSimulation::Lbm(simulation) => (
+                Box::new(CameraController2D::new(100.0, 0.5, 2.0, 1000.0)),
+                overview_2d(simulation.width, simulation.height),
+                Deg::new(0.0),
+            ),
+            // the instances leave out the solid border
+            FluidSimulation::Flip(simulation) => (
+                Box::new(CameraController2D::new(100.0, 0.5, 2.0, 1000.0)),
+                overview_2d(simulation.width - 2, simulation.height - 2),
+                Deg::new(0.0),
+            ),
+            FluidSimulation::Euler3D(simulation) => {
+                // in front of the domain, far enough back to see all of its width
+                let (width, height, depth) = (
+                    simulation.width as f32,
+                    simulation.height as f32,
+                    simulation.depth as f32,
+                );
+                (
+                    Box::new(CameraController3D::new(20.0, 0.5)),
+                    Point3::from(0.5 * width, 0.5 * height, depth + 0.6 * width),
+                    Deg::new(0.0),
+                )
+            }
+            FluidSimulation::ShallowWater(simulation) => {
+                // in front of the terrain and above it, looking down at 45 degrees onto
+                // the surface raised towards the camera
+                let (width, height) = simulation.interior_size();
+                let (width, height) = (width as f32, height as f32);
+                let top = simulation
+                    .instances
+                    .iter()
+                    .fold(0.0f32, |top, instance| top.max(instance.position.z));
+                let distance = 0.6 * width.max(height) * std::f32::consts::FRAC_1_SQRT_2;
+                (
+                    Box::new(CameraController3D::new(20.0, 0.5)),
+                    Point3::from(0.5 * width, 0.5 * height - distance, top + distance),
+                    Deg::new(45.0),
+                )
+            }
+        };
+
         let mut engine = Engine::new(
             window,
             &CameraDescriptor {
-                position: Point3::from(100.0, 50.0, 50.0),
+                position,
                 yaw: Deg::new(-90.0).into(),
-                pitch: Deg::new(0.0).into(),
+                pitch: pitch.into(),
                 projection,
             },
         )
@@ -38,9 +154,6 @@ impl Simulation {
 
         engine.add_render_pass();
 
-        let mut simulation = EulerSimulation::new(1000.0, 200, 100, 1.0 / 200.0 * 100.0);
-        simulation.init();
-
         Simulation {
             engine,
             camera_controller,
@@ -51,9 +164,13 @@ impl Simulation {
     }
 
     pub fn process_input(&mut self, state: ElementState, key: VirtualKeyCode) {
-        if key == VirtualKeyCode::Space && state == ElementState::Pressed {
+        if key == self.simulation.pause_key() && state == ElementState::Pressed {
             self.stopped = !self.stopped;
         }
+        if key == VirtualKeyCode::V && state == ElementState::Pressed {
+            let enabled = self.simulation.toggle_vorticity_confinement();
+            info!("Vorticity confinement enabled: {}", enabled);
+        }
         self.camera_controller.process_keyboard(key, state);
     }
 
@@ -78,11 +195,19 @@ impl Simulation {
     fn update(&mut self, dt: Duration) {
         self.camera_controller.update(&mut self.engine.camera, dt);
 
+        if let FluidSimulation::Euler3D(simulation) = &mut self.simulation {
+            // the voxels are drawn back to front from wherever the camera went
+            simulation.set_viewpoint(Vec3::from(self.engine.camera.position));
+            if self.stopped {
+                simulation.update_instances();
+            }
+        }
+
         if !self.stopped {
             self.simulation.update(dt);
         }
 
-        self.engine.update_instances(&self.simulation.instances);
+        self.engine.update_instances(self.simulation.instances());
         self.engine.update(dt);
     }
 
@@ -95,6 +220,90 @@ impl Simulation {
     }
 }
 
+/// Camera position above the centre of a flat `width` x `height` cell domain, far enough
+/// back to see all of it.
+fn overview_2d(width: usize, height: usize) -> Point3 {
+    let (width, height) = (width as f32, height as f32);
+    Point3::from(0.5 * width, 0.5 * height, 0.6 * width.max(height))
+}
+
+/// Sets up the simulation from the command line, `[--solver] [scene]`. The flag picks
+/// the solver:
+///
+/// - none: incompressible 2D Euler
+/// - `--3d`: incompressible 3D Euler
+/// - `--compressible`: compressible Euler, a Sod shock tube by default
+/// - `--lbm`: lattice Boltzmann
+/// - `--flip`: FLIP liquid
+/// - `--shallow-water`: shallow water, a dam break by default
+///
+/// The scene file is loaded with the solver's format, its default preset when absent.
+fn load_simulation() -> FluidSimulation {
+    let mut args = std::env::args().skip(1).peekable();
+    let solver = args.next_if(|arg| arg.starts_with("--"));
+    let path = args.next();
+
+    match solver.as_deref() {
+        Some("--3d") => {
+            let scene = load_scene(path, |path| Euler3DScene::from_file(path), Default::default);
+            FluidSimulation::Euler3D(Euler3DSimulation::from_scene(&scene))
+        }
+        Some("--compressible") => {
+            let scene = load_scene(
+                path,
+                |path| CompressibleScene::from_file(path),
+                || CompressibleScene::sod_shock_tube(400),
+            );
+            FluidSimulation::Compressible(CompressibleSimulation::from_scene(&scene))
+        }
+        Some("--lbm") => {
+            let scene = load_scene(path, |path| LBMScene::from_file(path), Default::default);
+            FluidSimulation::Lbm(LBMSimulation::from_scene(&scene))
+        }
+        Some("--flip") => {
+            let scene = load_scene(path, |path| FLIPScene::from_file(path), Default::default);
+            FluidSimulation::Flip(FLIPSimulation::from_scene(&scene))
+        }
+        Some("--shallow-water") => {
+            let scene = load_scene(
+                path,
+                |path| ShallowWaterScene::from_file(path),
+                || ShallowWaterScene::dam_break(200),
+            );
+            FluidSimulation::ShallowWater(ShallowWaterSimulation::from_scene(&scene))
+        }
+        solver => {
+            if let Some(solver) = solver {
+                error!("Unknown solver {}, running the Euler one", solver);
+            }
+            let scene = load_scene(path, |path| EulerScene::from_file(path), Default::default);
+            FluidSimulation::Euler(EulerSimulation::from_scene(&scene))
+        }
+    }
+}
+
+/// Loads the scene at `path`, falling back to the `preset` one.
+fn load_scene<S>(
+    path: Option<String>,
+    from_file: impl Fn(&str) -> Result<S, SceneError>,
+    preset: impl Fn() -> S,
+) -> S {
+    let Some(path) = path else {
+        return preset();
+    };
+
+    match from_file(&path) {
+        Ok(scene) => {
+            info!("Loaded scene from {}", path);
+            scene
+        }
+        Err(err) => {
+            error!("Error loading scene {}: {}", path, err);
+            preset()
+        }
+    }
+}
+
 pub async fn run() {
     let window = Window::new();
     let mut game = Simulation::new(&window).await;